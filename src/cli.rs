@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use http::{Method, StatusCode};
+
+use crate::{config::Config, http_client::HttpClient};
+
+const DLQ_USAGE: &str = "Usage:
+  telemetron dlq list [--plugin NAME] [--limit N]
+  telemetron dlq show ID
+  telemetron dlq purge ID
+  telemetron dlq purge --all [--plugin NAME]
+  telemetron dlq replay ID [--plugin NAME]
+
+Calls the admin API of the server configured in config.toml, or --url URL.";
+
+/// Replays wait for the plugin's retries, so allow for a slow sink.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Arguments of a `dlq` subcommand.
+#[derive(Debug, Default)]
+struct DlqArgs {
+    id: Option<u64>,
+    plugin: Option<String>,
+    limit: Option<usize>,
+    all: bool,
+    url: Option<String>,
+}
+
+impl DlqArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value of {}", arg));
+            match arg.as_str() {
+                "--plugin" => parsed.plugin = Some(value()?.clone()),
+                "--limit" => {
+                    parsed.limit = Some(value()?.parse().map_err(|_| "Invalid --limit")?);
+                }
+                "--url" => parsed.url = Some(value()?.trim_end_matches('/').to_string()),
+                "--all" => parsed.all = true,
+                id if parsed.id.is_none() && !id.starts_with('-') => {
+                    parsed.id = Some(id.parse().map_err(|_| format!("Invalid batch id {}", id))?);
+                }
+                other => return Err(format!("Unexpected argument {}", other)),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn id(&self) -> Result<u64, String> {
+        self.id.ok_or_else(|| "Missing batch id".to_string())
+    }
+
+    fn plugin_query(&self) -> String {
+        self.plugin.as_ref().map(|plugin| format!("plugin={}", plugin)).unwrap_or_default()
+    }
+}
+
+/// Runs `telemetron dlq <command>`: lists, shows, purges and replays batches
+/// in the DLQ of a running server through its admin API, so the server's
+/// view of the DLQ stays consistent.
+pub async fn run_dlq(args: &[String]) -> Result<(), String> {
+    let Some((command, args)) = args.split_first() else {
+        return Err(DLQ_USAGE.to_string());
+    };
+    let args = DlqArgs::parse(args).map_err(|err| format!("{}\n\n{}", err, DLQ_USAGE))?;
+
+    let (method, path) = match command.as_str() {
+        "list" => {
+            let limit = args.limit.map(|limit| format!("limit={}", limit)).unwrap_or_default();
+            let query: Vec<_> =
+                [limit, args.plugin_query()].into_iter().filter(|q| !q.is_empty()).collect();
+            (Method::GET, format!("/admin/dlq?{}", query.join("&")))
+        }
+        "show" => (Method::GET, format!("/admin/dlq/{}", args.id()?)),
+        "purge" if args.all => (Method::DELETE, format!("/admin/dlq?{}", args.plugin_query())),
+        "purge" => (Method::DELETE, format!("/admin/dlq/{}", args.id()?)),
+        "replay" => {
+            (Method::POST, format!("/admin/dlq/{}/replay?{}", args.id()?, args.plugin_query()))
+        }
+        other => return Err(format!("Unknown command {}\n\n{}", other, DLQ_USAGE)),
+    };
+
+    let config = Config::try_load().map_err(|e| format!("Failed to load config: {}", e))?;
+    let base_url = args.url.clone().unwrap_or_else(|| {
+        // A server listening on all interfaces is reachable on loopback
+        let host = match config.http.host.as_str() {
+            "0.0.0.0" | "::" => "127.0.0.1",
+            host => host,
+        };
+        format!("http://{}:{}", host, config.http.port)
+    });
+
+    let client = HttpClient::new(REQUEST_TIMEOUT);
+    let url = format!("{}{}", base_url, path.trim_end_matches('?'));
+    let (status, body) = client
+        .request(method, &url, config.admin.token.as_deref())
+        .await
+        .map_err(|e| format!("Request to {} failed: {}", url, e))?;
+
+    let body = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(json) => serde_json::to_string_pretty(&json).map_err(|e| e.to_string())?,
+        Err(_) => String::from_utf8_lossy(&body).into_owned(),
+    };
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, body));
+    }
+    if status == StatusCode::NO_CONTENT {
+        println!("Purged batch {}", args.id()?);
+    } else {
+        println!("{}", body);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_dlq_args() {
+        let args: Vec<String> = ["--plugin", "StorageProcessor", "42", "--limit", "5"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let Ok(parsed) = DlqArgs::parse(&args) else {
+            panic!("args should parse");
+        };
+        assert_eq!(parsed.id, Some(42));
+        assert_eq!(parsed.limit, Some(5));
+        assert_eq!(parsed.plugin_query(), "plugin=StorageProcessor");
+
+        let invalid: Vec<String> = ["1", "2"].into_iter().map(String::from).collect();
+        assert!(DlqArgs::parse(&invalid).is_err());
+        assert!(DlqArgs::parse(&["--plugin".to_string()]).is_err());
+    }
+}
@@ -4,13 +4,16 @@ use dashmap::DashMap;
 use tokio::sync::mpsc;
 
 use crate::{
-    event::Event,
-    processing::{EventProcessor, source_telemetry::SourceTelemetry},
+    processing::source_telemetry::SourceTelemetry,
+    processor::{EventRouter, QueuedEvent, QueuedProcessor},
+    tenant::SourceKey,
+    transform::EventTransformer,
     validation::EventValidator,
 };
 
-pub type EventSender = mpsc::Sender<Event>;
-pub type EventReceiver = mpsc::Receiver<Event>;
-pub type TelemetryMap = Arc<DashMap<u64, SourceTelemetry>>;
+pub type EventSender = EventRouter;
+pub type EventReceiver = mpsc::Receiver<QueuedEvent>;
+pub type TelemetryMap = Arc<DashMap<SourceKey, SourceTelemetry>>;
 pub type EventValidators = Arc<Vec<Box<dyn EventValidator + Send + Sync>>>;
-pub type EventProcessors = Arc<Vec<Box<dyn EventProcessor + Send + Sync>>>;
+pub type EventTransformers = Arc<Vec<Box<dyn EventTransformer + Send + Sync>>>;
+pub type EventProcessors = Arc<Vec<QueuedProcessor>>;
@@ -1,12 +1,18 @@
 use std::{
     collections::{HashMap, HashSet},
     env, io,
+    path::PathBuf,
 };
 
 use config::Environment;
 use serde::Deserialize;
 
-use crate::{event::EventType, plugins};
+use crate::{
+    event::{EventType, EventValidationError, MAX_SOURCE_NAME_LENGTH, SourceId, SourceIdKind},
+    plugins,
+    tenant::TenantId,
+    transform::FieldPath,
+};
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct HttpConfig {
@@ -25,6 +31,9 @@ pub struct ProcessorConfig {
     pub retry_attempts: u32,
     #[serde(default = "default_retry_delay")]
     pub retry_delay: u64,
+    /// Processor workers; events are routed to them by source
+    #[serde(default = "default_processor_workers")]
+    pub workers: usize,
 }
 
 fn default_batch_size() -> usize {
@@ -43,12 +52,412 @@ fn default_retry_delay() -> u64 {
     1000
 }
 
-// Plugin specific config
+fn default_processor_workers() -> usize {
+    1
+}
+
+/// How the tenant of a request is determined
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TenantResolution {
+    /// Tenant mapped from the API key header (see `api_keys`)
+    #[default]
+    ApiKey,
+    /// Tenant name taken from a request header
+    Header,
+    /// Tenant name taken from a `/t/{tenant}/...` path prefix
+    Path,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TenancyConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub resolution: TenantResolution,
+    /// Header holding the tenant name (`header` resolution)
+    #[serde(default = "default_tenant_header")]
+    pub tenant_header: String,
+    /// Header holding the API key (`api_key` resolution)
+    #[serde(default = "default_api_key_header")]
+    pub api_key_header: String,
+    /// API key to tenant name mapping (`api_key` resolution)
+    #[serde(default)]
+    pub api_keys: HashMap<String, String>,
+    /// Known tenants and their policies, requests for other tenants are
+    /// rejected
+    #[serde(default)]
+    pub tenants: HashMap<String, TenantConfig>,
+}
+
+impl Default for TenancyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            resolution: TenantResolution::default(),
+            tenant_header: default_tenant_header(),
+            api_key_header: default_api_key_header(),
+            api_keys: HashMap::new(),
+            tenants: HashMap::new(),
+        }
+    }
+}
+
+/// Per-tenant validation scope and quotas
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    /// Source ids the tenant may send events for, empty allows any
+    #[serde(default)]
+    pub allowed_sources: HashSet<SourceId>,
+    /// Event types the tenant may send, empty allows any
+    #[serde(default)]
+    pub allowed_types: HashSet<EventType>,
+    /// Sustained ingest rate limit (events per second)
+    #[serde(default)]
+    pub max_events_per_second: Option<u32>,
+    /// Burst size for the ingest rate limit, defaults to one second worth of
+    /// events
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// Max number of distinct sources
+    #[serde(default)]
+    pub max_sources: Option<usize>,
+}
+
+fn default_tenant_header() -> String {
+    "x-tenant-id".to_string()
+}
+
+fn default_api_key_header() -> String {
+    "x-api-key".to_string()
+}
+
+/// Admin API to change validator lists at runtime
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AdminConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Bearer token required on admin requests, if set
+    #[serde(default)]
+    pub token: Option<String>,
+    /// File the runtime list changes are persisted to and restored from
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+}
+
+/// Keeps events rejected by the validators to inspect and re-submit them
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QuarantineConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Max number of kept events per tenant, the oldest are evicted first
+    #[serde(default = "default_quarantine_capacity")]
+    pub capacity: usize,
+    /// Directory events are kept in to survive restarts (memory only if unset)
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for QuarantineConfig {
+    fn default() -> Self {
+        Self { enabled: false, capacity: default_quarantine_capacity(), dir: None }
+    }
+}
+
+fn default_quarantine_capacity() -> usize {
+    10_000
+}
+
+/// Keeps batches that failed in a processor plugin on disk, to inspect and
+/// replay them
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DlqConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory of the segment files
+    #[serde(default = "default_dlq_dir")]
+    pub dir: PathBuf,
+    /// Max batches per segment file
+    #[serde(default = "default_dlq_segment_entries")]
+    pub segment_entries: usize,
+    /// Max segment files, the oldest is deleted first
+    #[serde(default = "default_dlq_max_segments")]
+    pub max_segments: usize,
+}
+
+impl Default for DlqConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_dlq_dir(),
+            segment_entries: default_dlq_segment_entries(),
+            max_segments: default_dlq_max_segments(),
+        }
+    }
+}
+
+fn default_dlq_dir() -> PathBuf {
+    PathBuf::from("dlq")
+}
+
+fn default_dlq_segment_entries() -> usize {
+    1000
+}
+
+fn default_dlq_max_segments() -> usize {
+    100
+}
+
+/// Write-ahead log of accepted events, so events acknowledged to clients
+/// survive a crash until they are processed
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct WalConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory of the segment files
+    #[serde(default = "default_wal_dir")]
+    pub dir: PathBuf,
+    /// Max events per segment file
+    #[serde(default = "default_wal_segment_entries")]
+    pub segment_entries: usize,
+    #[serde(default)]
+    pub fsync: FsyncPolicy,
+    /// Milliseconds between syncs with the `interval` policy
+    #[serde(default = "default_wal_fsync_interval")]
+    pub fsync_interval: u64,
+}
+
+impl Default for WalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_wal_dir(),
+            segment_entries: default_wal_segment_entries(),
+            fsync: FsyncPolicy::default(),
+            fsync_interval: default_wal_fsync_interval(),
+        }
+    }
+}
+
+/// When appended events are synced to disk
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FsyncPolicy {
+    /// Before the event is acknowledged
+    #[default]
+    Always,
+    /// Every `fsync_interval` milliseconds
+    Interval,
+    /// When the OS flushes its page cache
+    Never,
+}
+
+fn default_wal_dir() -> PathBuf {
+    PathBuf::from("wal")
+}
+
+fn default_wal_segment_entries() -> usize {
+    10000
+}
+
+fn default_wal_fsync_interval() -> u64 {
+    1000
+}
+
+/// On-disk buffer for events that don't fit in the channels of the processor
+/// workers, e.g. during traffic bursts
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SpillConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Directory of the segment files, cleared on startup
+    #[serde(default = "default_spill_dir")]
+    pub dir: PathBuf,
+    /// Max bytes of spilled events not yet drained, across all workers
+    #[serde(default = "default_spill_max_bytes")]
+    pub max_bytes: u64,
+    /// Max bytes per segment file
+    #[serde(default = "default_spill_segment_bytes")]
+    pub segment_bytes: u64,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_spill_dir(),
+            max_bytes: default_spill_max_bytes(),
+            segment_bytes: default_spill_segment_bytes(),
+        }
+    }
+}
+
+fn default_spill_dir() -> PathBuf {
+    PathBuf::from("spill")
+}
+
+fn default_spill_max_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+fn default_spill_segment_bytes() -> u64 {
+    16 * 1024 * 1024
+}
+
+/// Accepted forms of source identifiers
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SourceIdConfig {
+    #[serde(default = "default_allowed_source_id_kinds")]
+    pub allowed_kinds: HashSet<SourceIdKind>,
+    /// Max length (characters) of string (`name`) source ids
+    #[serde(default = "default_source_name_max_length")]
+    pub max_length: usize,
+}
+
+impl Default for SourceIdConfig {
+    fn default() -> Self {
+        Self {
+            allowed_kinds: default_allowed_source_id_kinds(),
+            max_length: default_source_name_max_length(),
+        }
+    }
+}
+
+impl SourceIdConfig {
+    /// Checks that the source id has an allowed form and length.
+    pub fn check(&self, source_id: &SourceId) -> Result<(), EventValidationError> {
+        if !self.allowed_kinds.contains(&source_id.kind()) {
+            return Err(EventValidationError::DisallowedSourceIdKind(source_id.kind()));
+        }
+        if let SourceId::Name(name) = source_id {
+            let length = name.chars().count();
+            let max = self.max_length.min(MAX_SOURCE_NAME_LENGTH);
+            if length > max {
+                return Err(EventValidationError::SourceIdTooLong { length, max });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_allowed_source_id_kinds() -> HashSet<SourceIdKind> {
+    HashSet::from([SourceIdKind::Numeric])
+}
+
+fn default_source_name_max_length() -> usize {
+    128
+}
+
 #[derive(Debug, Deserialize, Clone, Default)]
 #[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    #[serde(default)]
+    pub source_stats: SourceStatsMetricsConfig,
+}
+
+/// Export of per-source statistics on `/metrics`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SourceStatsMetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Sources exported with their own labels. If empty, the `top_k` sources
+    /// with the most events are exported
+    #[serde(default)]
+    pub allowed_sources: HashSet<SourceId>,
+    /// Max number of sources exported with their own labels, the rest are
+    /// summed per tenant with `aggregated="source"`
+    #[serde(default = "default_top_k_sources")]
+    pub top_k: usize,
+    /// Max number of event types exported per source, the rest are summed
+    /// with `aggregated="type"`
+    #[serde(default = "default_top_k_event_types")]
+    pub top_k_event_types: usize,
+}
+
+impl Default for SourceStatsMetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allowed_sources: HashSet::new(),
+            top_k: default_top_k_sources(),
+            top_k_event_types: default_top_k_event_types(),
+        }
+    }
+}
+
+fn default_top_k_sources() -> usize {
+    100
+}
+
+fn default_top_k_event_types() -> usize {
+    20
+}
+
+// Plugin specific config
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct SourceIdValidationConfig {
     #[serde(default)]
-    pub allowed: HashSet<u64>,
+    pub allowed: HashSet<SourceId>,
+    /// Inclusive ranges of allowed numeric source ids
+    #[serde(default)]
+    pub allowed_ranges: Vec<SourceIdRange>,
+    /// Denied source ids, these take precedence over allowed ones
+    #[serde(default)]
+    pub denied: HashSet<SourceId>,
+    #[serde(default)]
+    pub denied_ranges: Vec<SourceIdRange>,
+    /// File with additional allowed source ids, one id or `start..end` range
+    /// per line
+    #[serde(default)]
+    pub allowed_file: Option<PathBuf>,
+    /// File with additional denied source ids, same format as `allowed_file`
+    #[serde(default)]
+    pub denied_file: Option<PathBuf>,
+    /// How often (seconds) list files are checked for changes
+    #[serde(default = "default_list_reload_interval")]
+    pub reload_interval: u64,
+}
+
+/// Inclusive range of numeric source ids
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct SourceIdRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl SourceIdRange {
+    pub fn contains(&self, source_id: &SourceId) -> bool {
+        matches!(source_id, SourceId::Numeric(id) if (self.start..=self.end).contains(id))
+    }
+}
+
+impl Default for SourceIdValidationConfig {
+    fn default() -> Self {
+        Self {
+            allowed: HashSet::new(),
+            allowed_ranges: Vec::new(),
+            denied: HashSet::new(),
+            denied_ranges: Vec::new(),
+            allowed_file: None,
+            denied_file: None,
+            reload_interval: default_list_reload_interval(),
+        }
+    }
+}
+
+fn default_list_reload_interval() -> u64 {
+    30
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
@@ -56,21 +465,532 @@ pub struct SourceIdValidationConfig {
 pub struct EventTypeValidationConfig {
     #[serde(default)]
     pub allowed: HashSet<EventType>,
+    /// Denied event types, these take precedence over allowed ones
+    #[serde(default)]
+    pub denied: HashSet<EventType>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HeartbeatMonitorConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Expected heartbeat interval (seconds) for sources with neither a
+    /// configured nor a learned interval
+    #[serde(default)]
+    pub default_interval: Option<u64>,
+    /// Per-source expected heartbeat intervals, these take precedence over
+    /// learned intervals
+    #[serde(default)]
+    pub sources: Vec<HeartbeatSourceConfig>,
+    /// Number of missed intervals after which a source is marked as down
+    #[serde(default = "default_missed_intervals")]
+    pub missed_intervals: u32,
+    /// How often (seconds) sources are checked for missed heartbeats
+    #[serde(default = "default_check_interval")]
+    pub check_interval: u64,
+    #[serde(default)]
+    pub notifier: LivenessNotifierConfig,
+}
+
+impl Default for HeartbeatMonitorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            default_interval: None,
+            sources: Vec::new(),
+            missed_intervals: default_missed_intervals(),
+            check_interval: default_check_interval(),
+            notifier: LivenessNotifierConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HeartbeatSourceConfig {
+    pub id: SourceId,
+    #[serde(default)]
+    pub tenant: TenantId,
+    /// Expected heartbeat interval (seconds)
+    pub interval: u64,
+}
+
+/// Where source up/down transitions are reported
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum LivenessNotifierConfig {
+    #[default]
+    Log,
+    Webhook {
+        url: String,
+        /// Request timeout (ms)
+        #[serde(default = "default_webhook_timeout")]
+        timeout: u64,
+    },
+}
+
+fn default_missed_intervals() -> u32 {
+    3
+}
+
+fn default_check_interval() -> u64 {
+    5
+}
+
+fn default_webhook_timeout() -> u64 {
+    5000
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LabelValidationConfig {
+    /// Allowed label keys, empty allows any key
+    #[serde(default)]
+    pub allowed_keys: HashSet<String>,
+    /// Max number of labels per event
+    #[serde(default)]
+    pub max_labels: Option<usize>,
+    /// Max length (characters) of a label key
+    #[serde(default)]
+    pub max_key_length: Option<usize>,
+    /// Max length (characters) of a label value
+    #[serde(default)]
+    pub max_value_length: Option<usize>,
+}
+
+/// What the JsonSchemaValidator does with event types without a schema
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownTypePolicy {
+    #[default]
+    Allow,
+    Reject,
+}
+
+/// Where a JSON Schema document comes from: a string is a file path, a table
+/// is the schema itself
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum SchemaSourceConfig {
+    Path(PathBuf),
+    Inline(serde_json::Value),
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct JsonSchemaValidationConfig {
+    /// Schemas of the `data` payload by event type
+    #[serde(default)]
+    pub schemas: HashMap<EventType, SchemaSourceConfig>,
+    #[serde(default)]
+    pub unknown_types: UnknownTypePolicy,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuleValidationConfig {
+    #[serde(default)]
+    pub rules: Vec<RuleConfig>,
+}
+
+/// A named rule expression, see `validation::rule_expr` for the syntax
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConfig {
+    pub name: String,
+    pub expr: String,
+    /// Message reported when an event violates the rule
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteRegistryValidationConfig {
+    /// SQLite database holding the provisioned sources, opened read-only
+    pub path: PathBuf,
+    /// Query returning a row if the source id bound to `?1` is provisioned
+    #[serde(default = "default_registry_query")]
+    pub query: String,
+    /// Number of database connections used for concurrent lookups
+    #[serde(default = "default_registry_connections")]
+    pub connections: usize,
+}
+
+fn default_registry_query() -> String {
+    "SELECT 1 FROM devices WHERE source_id = ?1".to_string()
+}
+
+fn default_registry_connections() -> usize {
+    4
+}
+
+/// Reserved keys of a transformer plugin table, not passed to the plugin
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TransformerChainConfig {
+    /// Position in the chain, transformers run in ascending order
+    #[serde(default)]
+    pub order: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct EventTransformConfig {
+    /// Plugin parameters by plugin name, see `TransformerChainConfig` for the
+    /// reserved keys.
+    #[serde(default)]
+    pub plugins: HashMap<String, toml::Value>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct IngestTimeTransformConfig {
+    /// Field the ingest time (RFC 3339) is written to
+    #[serde(default = "default_ingest_time_field")]
+    pub field: FieldPath,
+    #[serde(default = "default_true")]
+    pub overwrite: bool,
+}
+
+fn default_ingest_time_field() -> FieldPath {
+    FieldPath::Label("ingested_at".to_string())
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ClientAddressTransformConfig {
+    /// Field the client IP address is written to
+    #[serde(default = "default_client_address_field")]
+    pub field: FieldPath,
+    /// Header set by a trusted proxy holding the client address chain (e.g.
+    /// `x-forwarded-for`)
+    #[serde(default)]
+    pub forwarded_header: Option<String>,
+    /// Number of trusted proxies in front of the server. Each appends the
+    /// address of its peer to the chain, so the client address is the entry
+    /// this many hops from the right; entries left of it can be spoofed
+    #[serde(default = "default_trusted_proxies")]
+    pub trusted_proxies: usize,
+    #[serde(default = "default_true")]
+    pub overwrite: bool,
+}
+
+fn default_client_address_field() -> FieldPath {
+    FieldPath::Label("client_addr".to_string())
+}
+
+fn default_trusted_proxies() -> usize {
+    1
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct StaticLabelsTransformConfig {
+    pub labels: HashMap<String, String>,
+    /// Whether labels sent with the event are replaced
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct FilterTransformConfig {
+    /// Rule expressions, events matching any of them are dropped
+    #[serde(default)]
+    pub drop_when: Vec<String>,
+}
+
+/// How redacted values are replaced
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RedactionAction {
+    /// Replace with the `mask` string
+    #[default]
+    Mask,
+    /// Replace with the hex HMAC-SHA256 of the value, keyed with the hash key
+    Hash,
+    /// Remove the field, or the match from the string
+    Remove,
+}
+
+/// Built-in patterns of personal data
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinPattern {
+    Email,
+    Ipv4,
+    /// 13 to 19 digits, optionally separated by spaces or dashes, passing the
+    /// Luhn check
+    CardNumber,
+}
+
+/// Scrubs personal data from events after the transformers ran
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Fields redacted as a whole
+    #[serde(default)]
+    pub paths: Vec<FieldPath>,
+    /// Built-in patterns redacted in `data` strings and label values
+    #[serde(default)]
+    pub patterns: Vec<BuiltinPattern>,
+    /// Custom regexes redacted like the built-in patterns
+    #[serde(default)]
+    pub regexes: Vec<String>,
+    #[serde(default)]
+    pub action: RedactionAction,
+    #[serde(default = "default_redaction_mask")]
+    pub mask: String,
+    /// Key of the `hash` action
+    #[serde(default)]
+    pub hash_key: Option<String>,
+    /// Environment variable holding the key of the `hash` action
+    #[serde(default)]
+    pub hash_key_env: Option<String>,
+}
+
+fn default_redaction_mask() -> String {
+    "[REDACTED]".to_string()
+}
+
+/// Sampling rates of a group of sources
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SamplingGroupConfig {
+    pub name: String,
+    #[serde(default)]
+    pub sources: Vec<SourceId>,
+    #[serde(default)]
+    pub source_ranges: Vec<SourceIdRange>,
+    /// Rates by event type, types not listed use the top-level rates
+    #[serde(default)]
+    pub rates: HashMap<EventType, f64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SamplingTransformConfig {
+    /// Rate of event types without a configured rate
+    #[serde(default = "default_sample_rate")]
+    pub default_rate: f64,
+    /// Rates by event type, from 0 (drop all) to 1 (keep all)
+    #[serde(default)]
+    pub rates: HashMap<EventType, f64>,
+    /// Source groups with their own rates, the first matching group applies
+    #[serde(default)]
+    pub groups: Vec<SamplingGroupConfig>,
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Limits of the per-source statistics kept by the StorageProcessor
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct StorageProcessorConfig {
+    /// Max distinct metric names aggregated per source, further names are only
+    /// counted in `other_metrics`
+    #[serde(default = "default_max_metric_names")]
+    pub max_metric_names: usize,
+    /// Max distinct error codes counted per source, further codes are only
+    /// counted in `other_errors`
+    #[serde(default = "default_max_error_codes")]
+    pub max_error_codes: usize,
+    /// Max distinct label sets counted per source, events with further label
+    /// sets are only counted in `other_label_sets`
+    #[serde(default = "default_max_label_sets")]
+    pub max_label_sets: usize,
+}
+
+impl Default for StorageProcessorConfig {
+    fn default() -> Self {
+        Self {
+            max_metric_names: default_max_metric_names(),
+            max_error_codes: default_max_error_codes(),
+            max_label_sets: default_max_label_sets(),
+        }
+    }
+}
+
+fn default_max_metric_names() -> usize {
+    100
+}
+
+fn default_max_error_codes() -> usize {
+    100
+}
+
+fn default_max_label_sets() -> usize {
+    1000
+}
+
+/// How the validator chain handles failing validators
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationMode {
+    /// Reject the event on the first failing validator
+    #[default]
+    FailFast,
+    /// Run every validator and report all failures together
+    CollectAll,
+}
+
+/// What happens to an event when a validator can't reach its backing store
+/// or times out
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Reject the event
+    #[default]
+    FailClosed,
+    /// Let the event pass the validator
+    FailOpen,
+}
+
+/// Whether a failing validator rejects the event
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidatorMode {
+    /// Reject the event
+    #[default]
+    Enforce,
+    /// Log and count the failure, but accept the event
+    Shadow,
 }
 
-/// Config struct for plugins that do not require any parameters
+/// Reserved keys of a validator plugin table. They configure how the plugin
+/// runs in the validator chain and are not passed to the plugin.
 #[derive(Debug, Deserialize, Clone, Default)]
 #[serde(deny_unknown_fields)]
-pub struct NoParamsValidationConfig {}
+pub struct ValidatorChainConfig {
+    /// Position in the chain, validators run in ascending order
+    #[serde(default)]
+    pub order: Option<i64>,
+    /// Max time a validation may take (ms)
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+    #[serde(default)]
+    pub mode: ValidatorMode,
+}
 
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct EventValidationConfig {
+    #[serde(default)]
+    pub mode: ValidationMode,
+    /// Plugin parameters by plugin name, see `ValidatorChainConfig` for the
+    /// reserved keys.
     #[serde(default)]
     pub plugins: HashMap<String, toml::Value>,
 }
 
+/// What happens to a batch for a processor plugin whose queue is full
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait for room in the queue, holding up all plugins
+    #[default]
+    Block,
+    /// Drop the new batch for this plugin
+    DropNewest,
+    /// Drop the oldest queued batch for this plugin
+    DropOldest,
+}
+
+/// How a processor plugin retries batches that failed with a retryable error
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Attempts per batch, including the first (default: `processor.retry_attempts`)
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    /// Delay (ms) before the first retry (default: `processor.retry_delay`)
+    #[serde(default)]
+    pub initial_delay: Option<u64>,
+    /// Factor the delay grows by after each retry
+    #[serde(default = "default_backoff_multiplier")]
+    pub multiplier: f64,
+    /// Max delay (ms) between retries
+    #[serde(default = "default_max_retry_delay")]
+    pub max_delay: u64,
+    /// Fraction each delay is randomized by, e.g. 0.2 for +/-20%
+    #[serde(default)]
+    pub jitter: f64,
+    /// Time (ms) since the first attempt after which no retry is started
+    #[serde(default)]
+    pub max_elapsed: Option<u64>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: None,
+            initial_delay: None,
+            multiplier: default_backoff_multiplier(),
+            max_delay: default_max_retry_delay(),
+            jitter: 0.0,
+            max_elapsed: None,
+        }
+    }
+}
+
+fn default_backoff_multiplier() -> f64 {
+    2.0
+}
+
+fn default_max_retry_delay() -> u64 {
+    30_000
+}
+
+/// Reserved keys of a processing plugin table. They configure the queue,
+/// workers and retries of the plugin and are not passed to the plugin.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ProcessorPluginConfig {
+    /// Max batches waiting for the plugin
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+    /// Batches the plugin processes at the same time
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+    #[serde(default)]
+    pub retry: RetryConfig,
+}
+
+impl Default for ProcessorPluginConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: default_queue_capacity(),
+            concurrency: default_concurrency(),
+            overflow: OverflowPolicy::default(),
+            retry: RetryConfig::default(),
+        }
+    }
+}
+
+fn default_queue_capacity() -> usize {
+    16
+}
+
+fn default_concurrency() -> usize {
+    1
+}
+
 #[derive(Debug, Deserialize, Clone, Default)]
 pub struct ProcessingConfig {
+    /// Plugin parameters by plugin name, see `ProcessorPluginConfig` for the
+    /// reserved keys.
     #[serde(default)]
     pub plugins: HashMap<String, toml::Value>,
 }
@@ -80,7 +1000,29 @@ pub struct Config {
     pub http: HttpConfig,
     pub processor: ProcessorConfig,
     pub validation: EventValidationConfig,
+    #[serde(default)]
+    pub transform: EventTransformConfig,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    #[serde(default)]
+    pub heartbeat: HeartbeatMonitorConfig,
     pub processing: ProcessingConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub source_ids: SourceIdConfig,
+    #[serde(default)]
+    pub tenancy: TenancyConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub quarantine: QuarantineConfig,
+    #[serde(default)]
+    pub dlq: DlqConfig,
+    #[serde(default)]
+    pub wal: WalConfig,
+    #[serde(default)]
+    pub spill: SpillConfig,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -91,8 +1033,14 @@ pub enum ConfigError {
     IoError(#[from] io::Error),
     #[error("Unknown validation plugin(s): {0:?}")]
     UnknownValidationPlugin(HashSet<String>),
+    #[error("Unknown transform plugin(s): {0:?}")]
+    UnknownTransformPlugin(HashSet<String>),
     #[error("Unknown processing plugin(s): {0:?}")]
     UnknownProcessingPlugin(HashSet<String>),
+    #[error("API key(s) mapped to unknown tenant(s): {0:?}")]
+    UnknownApiKeyTenant(HashSet<String>),
+    #[error("Per-source metrics of multiple tenants require an admin token")]
+    MissingMetricsToken,
 }
 
 impl Config {
@@ -104,6 +1052,7 @@ impl Config {
         let config = settings.try_deserialize::<Config>()?;
 
         config.validate_plugins()?;
+        config.validate_tenancy()?;
 
         Ok(config)
     }
@@ -150,6 +1099,24 @@ impl Config {
             return Err(ConfigError::UnknownValidationPlugin(unknown_validation_plugins));
         }
 
+        // Check transform plugins
+        let known_transform_plugins: HashSet<String> =
+            inventory::iter::<plugins::TransformPluginFactory>
+                .into_iter()
+                .map(|p| p.name.to_string())
+                .collect();
+        let unknown_transform_plugins = self
+            .transform
+            .plugins
+            .keys()
+            .filter(|name| !known_transform_plugins.contains(*name))
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        if !unknown_transform_plugins.is_empty() {
+            return Err(ConfigError::UnknownTransformPlugin(unknown_transform_plugins));
+        }
+
         // Check processing plugins
         let configured_processing_plugins: HashSet<String> =
             self.processing.plugins.keys().cloned().collect();
@@ -166,4 +1133,29 @@ impl Config {
 
         Ok(())
     }
+
+    fn validate_tenancy(&self) -> Result<(), ConfigError> {
+        if !self.tenancy.enabled {
+            return Ok(());
+        }
+
+        // A key of an unknown tenant would bypass the tenant's scope and quotas
+        let unknown_tenants = self
+            .tenancy
+            .api_keys
+            .values()
+            .filter(|tenant| !self.tenancy.tenants.contains_key(*tenant))
+            .cloned()
+            .collect::<HashSet<_>>();
+        if !unknown_tenants.is_empty() {
+            return Err(ConfigError::UnknownApiKeyTenant(unknown_tenants));
+        }
+
+        // The source ids of all tenants are exported on `/metrics`
+        if self.metrics.source_stats.enabled && self.admin.token.is_none() {
+            return Err(ConfigError::MissingMetricsToken);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,469 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::DlqConfig,
+    event::{Event, PersistedEvent},
+    metrics::{DLQ_BATCHES, DLQ_BATCHES_TOTAL, DLQ_EVICTED_BATCHES_TOTAL},
+    processing::error::ProcessingError,
+};
+
+/// A batch a processor plugin failed to process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub id: u64,
+    pub plugin: String,
+    pub failed_at: DateTime<Utc>,
+    pub attempts: u32,
+    /// The error and its sources, outermost first
+    pub errors: Vec<String>,
+    pub events: Vec<PersistedEvent>,
+}
+
+/// A dead-lettered batch without its events, as listed.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetterSummary {
+    pub id: u64,
+    pub plugin: String,
+    pub failed_at: DateTime<Utc>,
+    pub attempts: u32,
+    pub error: String,
+    pub events: usize,
+}
+
+impl From<&DeadLetter> for DeadLetterSummary {
+    fn from(entry: &DeadLetter) -> Self {
+        Self {
+            id: entry.id,
+            plugin: entry.plugin.clone(),
+            failed_at: entry.failed_at,
+            attempts: entry.attempts,
+            error: entry.errors.first().cloned().unwrap_or_default(),
+            events: entry.events.len(),
+        }
+    }
+}
+
+/// Segment files and the batches in them. Only summaries are kept in memory,
+/// events are read from the segment file when needed.
+#[derive(Debug, Default)]
+struct Index {
+    next_id: u64,
+    /// Live batches by segment (named by the id of its first batch)
+    segments: BTreeMap<u64, usize>,
+    /// Segment new batches are appended to and the lines written to it
+    current: Option<(u64, usize)>,
+    entries: BTreeMap<u64, (u64, DeadLetterSummary)>,
+    /// Batches being replayed
+    claimed: HashSet<u64>,
+}
+
+/// The result of claiming a batch for a replay.
+#[derive(Debug)]
+pub enum Claim {
+    Claimed(DeadLetter),
+    /// Another replay of the batch is running
+    InProgress,
+    NotFound,
+}
+
+/// Durable dead-letter queue of batches that failed in a processor plugin,
+/// so they can be replayed after a sink outage instead of being lost. Batches
+/// are appended as JSON lines to segment files; when there are too many
+/// segments, the oldest is deleted.
+#[derive(Debug)]
+pub struct DeadLetterQueue {
+    dir: PathBuf,
+    segment_entries: usize,
+    max_segments: usize,
+    index: Mutex<Index>,
+}
+
+impl DeadLetterQueue {
+    /// Opens the queue, indexing the batches in its segment files. Lines that
+    /// can't be read, e.g. cut off by a crash, are skipped.
+    pub fn open(config: &DlqConfig) -> Result<Self, String> {
+        let dir = &config.dir;
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        let mut queue = Self {
+            dir: dir.clone(),
+            segment_entries: config.segment_entries.max(1),
+            max_segments: config.max_segments.max(1),
+            index: Mutex::new(Index::default()),
+        };
+
+        let read_dir =
+            fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let mut index = Index::default();
+        for path in read_dir.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            let Some(segment) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix("segment-")?.strip_suffix(".jsonl"))
+                .and_then(|id| id.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let entries = queue.read_segment(segment)?;
+            index.segments.insert(segment, entries.len());
+            for entry in entries {
+                index.next_id = index.next_id.max(entry.id + 1);
+                index.entries.insert(entry.id, (segment, DeadLetterSummary::from(&entry)));
+            }
+        }
+        // New batches go to a new segment, the last one may end in a cut-off line
+        index.current = None;
+        metrics::gauge!(DLQ_BATCHES).set(index.entries.len() as f64);
+        queue.index = Mutex::new(index);
+        Ok(queue)
+    }
+
+    fn segment_path(&self, segment: u64) -> PathBuf {
+        self.dir.join(format!("segment-{:020}.jsonl", segment))
+    }
+
+    fn read_segment(&self, segment: u64) -> Result<Vec<DeadLetter>, String> {
+        let path = self.segment_path(segment);
+        let file =
+            File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let mut entries = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(err) => tracing::warn!(
+                    "Skipping unreadable line {} of {}: {}",
+                    number + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Rewrites a segment without the given batches, deleting it if none are
+    /// left. Returns the number of batches left.
+    fn rewrite_segment(&self, segment: u64, removed: &HashSet<u64>) -> Result<usize, String> {
+        let path = self.segment_path(segment);
+        let kept: Vec<_> = self
+            .read_segment(segment)?
+            .into_iter()
+            .filter(|entry| !removed.contains(&entry.id))
+            .collect();
+        if kept.is_empty() {
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+            return Ok(0);
+        }
+        let mut content = String::new();
+        for entry in &kept {
+            content.push_str(&serde_json::to_string(entry).map_err(|e| e.to_string())?);
+            content.push('\n');
+        }
+        // Replace the segment at once, so a crash leaves the old or the new one
+        let tmp = path.with_extension("jsonl.tmp");
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(kept.len())
+    }
+
+    /// Writes a failed batch to the queue. Returns its id.
+    pub fn push(
+        &self,
+        plugin: &str,
+        events: &[Event],
+        error: &ProcessingError,
+        attempts: u32,
+    ) -> Result<u64, String> {
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        let id = index.next_id.max(Utc::now().timestamp_micros().unsigned_abs());
+        index.next_id = id + 1;
+
+        let mut errors = vec![error.to_string()];
+        let mut source = std::error::Error::source(error);
+        while let Some(err) = source {
+            errors.push(err.to_string());
+            source = err.source();
+        }
+        let entry = DeadLetter {
+            id,
+            plugin: plugin.to_string(),
+            failed_at: Utc::now(),
+            attempts,
+            errors,
+            events: events.iter().cloned().map(PersistedEvent::from).collect(),
+        };
+
+        let segment = match index.current {
+            Some((segment, lines)) if lines < self.segment_entries => segment,
+            _ => id,
+        };
+        let path = self.segment_path(segment);
+        let line = serde_json::to_string(&entry).map_err(|e| e.to_string())? + "\n";
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| {
+                file.write_all(line.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        let lines = index.current.filter(|(current, _)| *current == segment).map_or(0, |c| c.1);
+        index.current = Some((segment, lines + 1));
+        *index.segments.entry(segment).or_default() += 1;
+        index.entries.insert(id, (segment, DeadLetterSummary::from(&entry)));
+        self.evict(&mut index);
+
+        metrics::counter!(DLQ_BATCHES_TOTAL, "plugin" => plugin.to_string()).increment(1);
+        metrics::gauge!(DLQ_BATCHES).set(index.entries.len() as f64);
+        Ok(id)
+    }
+
+    /// Deletes the oldest segments above the max number of segments.
+    fn evict(&self, index: &mut Index) {
+        while index.segments.len() > self.max_segments {
+            let Some((segment, count)) = index.segments.pop_first() else {
+                break;
+            };
+            tracing::warn!(segment, count, "DLQ full, deleting oldest segment");
+            index.entries.retain(|_, (entry_segment, _)| *entry_segment != segment);
+            let entries = &index.entries;
+            index.claimed.retain(|id| entries.contains_key(id));
+            metrics::counter!(DLQ_EVICTED_BATCHES_TOTAL).increment(count as u64);
+            let path = self.segment_path(segment);
+            if let Err(err) = fs::remove_file(&path) {
+                tracing::error!("Failed to delete DLQ segment {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Batches, oldest first, optionally of one plugin, and the total number
+    /// of matching batches.
+    pub fn list(
+        &self,
+        plugin: Option<&str>,
+        limit: usize,
+    ) -> Result<(Vec<DeadLetterSummary>, usize), String> {
+        let index = self.index.lock().map_err(|e| e.to_string())?;
+        let mut matching = index
+            .entries
+            .values()
+            .map(|(_, summary)| summary)
+            .filter(|summary| plugin.is_none_or(|plugin| summary.plugin == plugin));
+        let listed: Vec<_> = matching.by_ref().take(limit).cloned().collect();
+        let total = listed.len() + matching.count();
+        Ok((listed, total))
+    }
+
+    /// A batch with its events.
+    pub fn get(&self, id: u64) -> Result<Option<DeadLetter>, String> {
+        let index = self.index.lock().map_err(|e| e.to_string())?;
+        let Some((segment, _)) = index.entries.get(&id) else {
+            return Ok(None);
+        };
+        Ok(self.read_segment(*segment)?.into_iter().find(|entry| entry.id == id))
+    }
+
+    /// Marks a batch as being replayed and returns it. A claimed batch can't
+    /// be claimed again until it is released or deleted, so it is not
+    /// replayed twice at once.
+    pub fn claim(&self, id: u64) -> Result<Claim, String> {
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        let Some((segment, _)) = index.entries.get(&id) else {
+            return Ok(Claim::NotFound);
+        };
+        let segment = *segment;
+        if index.claimed.contains(&id) {
+            return Ok(Claim::InProgress);
+        }
+        let Some(entry) = self.read_segment(segment)?.into_iter().find(|entry| entry.id == id)
+        else {
+            return Ok(Claim::NotFound);
+        };
+        index.claimed.insert(id);
+        Ok(Claim::Claimed(entry))
+    }
+
+    /// Releases a claimed batch, e.g. after a failed replay.
+    pub fn release(&self, id: u64) -> Result<(), String> {
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        index.claimed.remove(&id);
+        Ok(())
+    }
+
+    /// Runs a call doing file I/O on the blocking thread pool, so it doesn't
+    /// hold up the async runtime.
+    pub async fn blocking<T, F>(self: &Arc<Self>, call: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&DeadLetterQueue) -> Result<T, String> + Send + 'static,
+    {
+        let queue = self.clone();
+        tokio::task::spawn_blocking(move || call(&queue))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()))
+    }
+
+    /// Deletes batches, e.g. once replayed. Returns the number deleted.
+    pub fn remove(&self, ids: &[u64]) -> Result<usize, String> {
+        let mut index = self.index.lock().map_err(|e| e.to_string())?;
+        let mut by_segment: BTreeMap<u64, HashSet<u64>> = BTreeMap::new();
+        for id in ids {
+            if let Some((segment, _)) = index.entries.get(id) {
+                by_segment.entry(*segment).or_default().insert(*id);
+            }
+        }
+
+        let mut removed = 0;
+        for (segment, segment_ids) in by_segment {
+            let left = self.rewrite_segment(segment, &segment_ids)?;
+            for id in &segment_ids {
+                index.entries.remove(id);
+                index.claimed.remove(id);
+            }
+            removed += segment_ids.len();
+            if left == 0 {
+                index.segments.remove(&segment);
+                // A new batch starts a new segment
+                if index.current.is_some_and(|(current, _)| current == segment) {
+                    index.current = None;
+                }
+            } else {
+                index.segments.insert(segment, left);
+                if let Some((current, lines)) = &mut index.current
+                    && *current == segment
+                {
+                    *lines = left;
+                }
+            }
+        }
+        metrics::gauge!(DLQ_BATCHES).set(index.entries.len() as f64);
+        Ok(removed)
+    }
+
+    /// Ids of the batches, oldest first, optionally of one plugin.
+    pub fn ids(&self, plugin: Option<&str>) -> Result<Vec<u64>, String> {
+        let index = self.index.lock().map_err(|e| e.to_string())?;
+        Ok(index
+            .entries
+            .values()
+            .filter(|(_, summary)| plugin.is_none_or(|plugin| summary.plugin == plugin))
+            .map(|(_, summary)| summary.id)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event::EventType, tenant::TenantId};
+
+    fn create_events(count: usize) -> Vec<Event> {
+        (0..count)
+            .map(|i| Event {
+                source_id: (i as u64).into(),
+                r#type: EventType::Heartbeat,
+                timestamp: Utc::now(),
+                data: None,
+                labels: Default::default(),
+                tenant: TenantId::new("a"),
+                sample_weight: 10,
+                payload: None,
+            })
+            .collect()
+    }
+
+    fn create_config(name: &str, max_segments: usize) -> DlqConfig {
+        let dir =
+            std::env::temp_dir().join(format!("telemetron-dlq-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        DlqConfig { enabled: true, dir, segment_entries: 2, max_segments }
+    }
+
+    fn push(dlq: &DeadLetterQueue, plugin: &str, events: usize) -> u64 {
+        let source = std::io::Error::other("connection refused");
+        let error = ProcessingError::new("Sink", "write failed", Some(Box::new(source)));
+        let Ok(id) = dlq.push(plugin, &create_events(events), &error, 3) else {
+            panic!("batch should be written");
+        };
+        id
+    }
+
+    #[test]
+    fn test_reloads_and_removes_batches() {
+        let config = create_config("reload", 10);
+        let Ok(dlq) = DeadLetterQueue::open(&config) else {
+            panic!("queue should open");
+        };
+        let first = push(&dlq, "Sink", 2);
+        let second = push(&dlq, "Other", 1);
+        let third = push(&dlq, "Sink", 1);
+        assert!(matches!(dlq.remove(&[second]), Ok(1)));
+
+        let Ok(reopened) = DeadLetterQueue::open(&config) else {
+            panic!("queue should reopen");
+        };
+        let Ok(Some(entry)) = reopened.get(first) else {
+            panic!("batch should be reloaded");
+        };
+        assert_eq!(
+            entry.errors,
+            vec![
+                "Processing error in plugin Sink: write failed".to_string(),
+                "connection refused".to_string()
+            ]
+        );
+        assert_eq!(entry.attempts, 3);
+        let events: Vec<_> = entry.events.into_iter().map(PersistedEvent::into_event).collect();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.tenant == TenantId::new("a") && e.sample_weight == 10));
+        assert!(matches!(reopened.get(second), Ok(None)));
+        assert!(matches!(reopened.ids(Some("Sink")), Ok(ids) if ids == vec![first, third]));
+        assert!(matches!(reopened.list(None, 1), Ok((listed, 2)) if listed.len() == 1));
+        assert!(push(&reopened, "Sink", 1) > third);
+        let _ = fs::remove_dir_all(config.dir);
+    }
+
+    #[test]
+    fn test_claims_batch_once() {
+        let config = create_config("claim", 10);
+        let Ok(dlq) = DeadLetterQueue::open(&config) else {
+            panic!("queue should open");
+        };
+        let id = push(&dlq, "Sink", 2);
+        assert!(matches!(dlq.claim(id), Ok(Claim::Claimed(entry)) if entry.events.len() == 2));
+        assert!(matches!(dlq.claim(id), Ok(Claim::InProgress)));
+        assert!(dlq.release(id).is_ok());
+        assert!(matches!(dlq.claim(id), Ok(Claim::Claimed(_))));
+        assert!(matches!(dlq.remove(&[id]), Ok(1)));
+        assert!(matches!(dlq.claim(id), Ok(Claim::NotFound)));
+        let _ = fs::remove_dir_all(config.dir);
+    }
+
+    #[test]
+    fn test_deletes_oldest_segment_when_full() {
+        let config = create_config("evict", 2);
+        let Ok(dlq) = DeadLetterQueue::open(&config) else {
+            panic!("queue should open");
+        };
+        let ids: Vec<_> = (0..5).map(|_| push(&dlq, "Sink", 1)).collect();
+        // Two batches per segment, the segment of the first two was deleted
+        assert!(matches!(dlq.ids(None), Ok(kept) if kept == ids[2..]));
+        assert!(matches!(dlq.remove(&ids[2..4]), Ok(2)));
+        assert!(matches!(dlq.ids(None), Ok(kept) if kept == ids[4..]));
+        let _ = fs::remove_dir_all(config.dir);
+    }
+}
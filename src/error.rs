@@ -9,6 +9,9 @@ use crate::event::EventValidationError;
 pub enum Error {
     #[error("Invalid event")]
     InvalidEvent(#[from] EventValidationError),
+    /// All failures of a `collect_all` validation run, by validator name
+    #[error("Invalid event: {} validation failure(s)", .0.len())]
+    InvalidEventFailures(Vec<(&'static str, EventValidationError)>),
     #[error("IO error: {0}")]
     Io(#[from] io::Error),
     #[error("Server error: {0}")]
@@ -17,6 +20,21 @@ pub enum Error {
     Internal(String),
     #[error("Not found")]
     NotFound(String),
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// The resource is busy, e.g. a DLQ batch already being replayed
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+    /// A processor plugin's sink failed
+    #[error("Bad gateway: {0}")]
+    BadGateway(String),
+    /// The server can't take more events for now
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
 }
 
 const INTERNAL_ERROR_MESSAGE: &str = "Internal server error";
@@ -24,10 +42,37 @@ const INTERNAL_ERROR_MESSAGE: &str = "Internal server error";
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         let (status, error_message) = match self {
+            Self::InvalidEventFailures(failures) => {
+                tracing::warn!("Invalid event rejected by {} validator(s)", failures.len());
+                let errors: Vec<_> = failures
+                    .iter()
+                    .map(|(validator, e)| {
+                        serde_json::json!({ "validator": validator, "error": e.to_string() })
+                    })
+                    .collect();
+                let body = Json(serde_json::json!({
+                    "error": format!("Event failed {} validator(s)", failures.len()),
+                    "errors": errors,
+                }));
+                // Sources retry events a validator could not check
+                let status = if failures
+                    .iter()
+                    .any(|(_, e)| matches!(e, EventValidationError::ValidatorUnavailable { .. }))
+                {
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE
+                } else {
+                    axum::http::StatusCode::BAD_REQUEST
+                };
+                return (status, body).into_response();
+            }
             Self::Internal(msg) => {
                 tracing::error!("Internal server error: {}", msg);
                 (axum::http::StatusCode::INTERNAL_SERVER_ERROR, INTERNAL_ERROR_MESSAGE.to_string())
             }
+            Self::InvalidEvent(e @ EventValidationError::ValidatorUnavailable { .. }) => {
+                tracing::error!("Event validation failed: {}", e);
+                (axum::http::StatusCode::SERVICE_UNAVAILABLE, e.to_string())
+            }
             Self::InvalidEvent(e) => {
                 tracing::warn!("Invalid event rejected: {}", e);
                 (axum::http::StatusCode::BAD_REQUEST, e.to_string())
@@ -44,6 +89,30 @@ impl IntoResponse for Error {
                 tracing::warn!("Not found: {}", msg);
                 (axum::http::StatusCode::NOT_FOUND, msg)
             }
+            Self::BadRequest(msg) => {
+                tracing::warn!("Bad request: {}", msg);
+                (axum::http::StatusCode::BAD_REQUEST, msg)
+            }
+            Self::Unauthorized(msg) => {
+                tracing::warn!("Unauthorized: {}", msg);
+                (axum::http::StatusCode::UNAUTHORIZED, msg)
+            }
+            Self::Conflict(msg) => {
+                tracing::warn!("Conflict: {}", msg);
+                (axum::http::StatusCode::CONFLICT, msg)
+            }
+            Self::TooManyRequests(msg) => {
+                tracing::warn!("Too many requests: {}", msg);
+                (axum::http::StatusCode::TOO_MANY_REQUESTS, msg)
+            }
+            Self::BadGateway(msg) => {
+                tracing::error!("Bad gateway: {}", msg);
+                (axum::http::StatusCode::BAD_GATEWAY, msg)
+            }
+            Self::ServiceUnavailable(msg) => {
+                tracing::warn!("Service unavailable: {}", msg);
+                (axum::http::StatusCode::SERVICE_UNAVAILABLE, msg)
+            }
         };
 
         let body = Json(serde_json::json!({
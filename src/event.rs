@@ -1,17 +1,188 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::tenant::{SourceKey, TenantId};
+
 #[derive(Debug, thiserror::Error)]
 #[error("Failed to parse event type: {0}")]
 pub struct ParseEventTypeError(String);
 
+/// Hard upper bound on the length of string source identifiers.
+pub const MAX_SOURCE_NAME_LENGTH: usize = 256;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to parse source id: {0}")]
+pub struct ParseSourceIdError(String);
+
+/// Form of a source identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceIdKind {
+    Numeric,
+    Uuid,
+    Name,
+}
+
+impl Display for SourceIdKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceIdKind::Numeric => write!(f, "numeric"),
+            SourceIdKind::Uuid => write!(f, "uuid"),
+            SourceIdKind::Name => write!(f, "name"),
+        }
+    }
+}
+
+/// Opaque source identifier: an unsigned integer, a UUID or a bounded string
+/// (e.g. a hostname).
+/// Strings made of digits only are parsed as numeric ids and strings in the
+/// hyphenated UUID format as UUIDs, so `123` and `"123"` are the same source.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SourceId {
+    Numeric(u64),
+    Uuid(u128),
+    Name(String),
+}
+
+impl SourceId {
+    pub fn kind(&self) -> SourceIdKind {
+        match self {
+            SourceId::Numeric(_) => SourceIdKind::Numeric,
+            SourceId::Uuid(_) => SourceIdKind::Uuid,
+            SourceId::Name(_) => SourceIdKind::Name,
+        }
+    }
+
+    /// Parses a hyphenated UUID (`8-4-4-4-12` hex digits).
+    fn parse_uuid(s: &str) -> Option<u128> {
+        let groups: Vec<&str> = s.split('-').collect();
+        let lengths = groups.iter().map(|group| group.len()).collect::<Vec<_>>();
+        if lengths != [8, 4, 4, 4, 12]
+            || !groups.iter().all(|g| g.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            return None;
+        }
+        u128::from_str_radix(&groups.concat(), 16).ok()
+    }
+}
+
+impl From<u64> for SourceId {
+    fn from(id: u64) -> Self {
+        SourceId::Numeric(id)
+    }
+}
+
+impl FromStr for SourceId {
+    type Err = ParseSourceIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ParseSourceIdError("Source id cannot be empty".to_string()));
+        }
+        if s.bytes().all(|b| b.is_ascii_digit()) {
+            return s
+                .parse()
+                .map(SourceId::Numeric)
+                .map_err(|_| ParseSourceIdError(format!("Numeric source id out of range: {}", s)));
+        }
+        if let Some(uuid) = Self::parse_uuid(s) {
+            return Ok(SourceId::Uuid(uuid));
+        }
+        if s.chars().count() > MAX_SOURCE_NAME_LENGTH {
+            return Err(ParseSourceIdError(format!(
+                "Source id is longer than {} characters",
+                MAX_SOURCE_NAME_LENGTH
+            )));
+        }
+        if s.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(ParseSourceIdError(format!(
+                "Source id contains whitespace or control characters: {:?}",
+                s
+            )));
+        }
+        Ok(SourceId::Name(s.to_string()))
+    }
+}
+
+impl Display for SourceId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceId::Numeric(id) => write!(f, "{}", id),
+            SourceId::Uuid(uuid) => {
+                let hex = format!("{:032x}", uuid);
+                write!(
+                    f,
+                    "{}-{}-{}-{}-{}",
+                    &hex[0..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..32]
+                )
+            }
+            SourceId::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Serialize for SourceId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            SourceId::Numeric(id) => serializer.serialize_u64(*id),
+            other => serializer.collect_str(other),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SourceId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct SourceIdVisitor;
+
+        impl serde::de::Visitor<'_> for SourceIdVisitor {
+            type Value = SourceId;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "an unsigned integer, a UUID or a string source id")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(SourceId::Numeric(v))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u64::try_from(v)
+                    .map(SourceId::Numeric)
+                    .map_err(|_| E::custom(format!("source id cannot be negative: {}", v)))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(SourceIdVisitor)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 pub enum EventType {
     /// Heartbeat event
     Heartbeat,
+    /// Metric sample, `data` must be a [`MetricPayload`]
+    Metric,
+    /// Log record, `data` must be a [`LogPayload`]
+    Log,
+    /// Error report, `data` must be an [`ErrorPayload`]
+    Error,
     /// Custom event type
     /// This is used for custom event types that are not predefined in the
     /// system.
@@ -22,17 +193,33 @@ impl Display for EventType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             EventType::Heartbeat => write!(f, "Heartbeat"),
+            EventType::Metric => write!(f, "Metric"),
+            EventType::Log => write!(f, "Log"),
+            EventType::Error => write!(f, "Error"),
             EventType::Custom(s) => write!(f, "{}", s),
         }
     }
 }
 
-/// Parses a string into an EventType.
-/// If the string is "Heartbeat", it returns EventType::Heartbeat.
 impl EventType {
-    pub fn from_str(s: &str) -> Result<Self, ParseEventTypeError> {
+    /// Whether the type has a typed payload schema.
+    pub fn has_payload(&self) -> bool {
+        matches!(self, EventType::Metric | EventType::Log | EventType::Error)
+    }
+}
+
+/// Parses a string into an EventType.
+/// Names of built-in types ("Heartbeat", "Metric", "Log", "Error") map to
+/// their variants, anything else is a custom type.
+impl FromStr for EventType {
+    type Err = ParseEventTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "Heartbeat" => Ok(EventType::Heartbeat),
+            "Metric" => Ok(EventType::Metric),
+            "Log" => Ok(EventType::Log),
+            "Error" => Ok(EventType::Error),
             _ => {
                 if s.is_empty() {
                     return Err(ParseEventTypeError("Event type cannot be empty".to_string()));
@@ -49,8 +236,8 @@ impl Serialize for EventType {
         S: serde::Serializer,
     {
         match self {
-            EventType::Heartbeat => serializer.serialize_str("Heartbeat"),
             EventType::Custom(s) => serializer.serialize_str(s),
+            built_in => serializer.serialize_str(&built_in.to_string()),
         }
     }
 }
@@ -65,19 +252,374 @@ impl<'de> Deserialize<'de> for EventType {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// Payload of [`EventType::Metric`] events.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct MetricPayload {
+    pub name: String,
+    pub value: f64,
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Payload of [`EventType::Log`] events.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LogPayload {
+    pub level: LogLevel,
+    pub message: String,
+}
+
+/// Payload of [`EventType::Error`] events.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ErrorPayload {
+    pub code: String,
+    pub message: String,
+    #[serde(default)]
+    pub stacktrace: Option<String>,
+}
+
+/// Typed payload of a built-in event type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedPayload {
+    Metric(MetricPayload),
+    Log(LogPayload),
+    Error(ErrorPayload),
+}
+
+impl TypedPayload {
+    /// Required fields of the typed payloads: the event type, the key in `data`
+    /// and whether any string is a valid value.
+    pub const REQUIRED_FIELDS: &[(EventType, &str, bool)] = &[
+        (EventType::Metric, "name", true),
+        (EventType::Metric, "value", false),
+        (EventType::Log, "level", false),
+        (EventType::Log, "message", true),
+        (EventType::Error, "code", true),
+        (EventType::Error, "message", true),
+    ];
+
+    /// Parses the payload of a built-in event type. Returns `Ok(None)` for types
+    /// without a typed payload.
+    pub fn parse(
+        event_type: &EventType,
+        data: Option<&Value>,
+    ) -> Result<Option<Self>, InvalidPayloadError> {
+        fn parse_data<T: serde::de::DeserializeOwned>(
+            event_type: &EventType,
+            data: Option<&Value>,
+        ) -> Result<T, InvalidPayloadError> {
+            let data = data.ok_or_else(|| InvalidPayloadError {
+                event_type: event_type.clone(),
+                reason: "missing data payload".to_string(),
+            })?;
+            T::deserialize(data).map_err(|e| InvalidPayloadError {
+                event_type: event_type.clone(),
+                reason: e.to_string(),
+            })
+        }
+
+        let payload = match event_type {
+            EventType::Metric => TypedPayload::Metric(parse_data(event_type, data)?),
+            EventType::Log => TypedPayload::Log(parse_data(event_type, data)?),
+            EventType::Error => TypedPayload::Error(parse_data(event_type, data)?),
+            EventType::Heartbeat | EventType::Custom(_) => return Ok(None),
+        };
+
+        Ok(Some(payload))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid {event_type} payload: {reason}")]
+pub struct InvalidPayloadError {
+    pub event_type: EventType,
+    pub reason: String,
+}
+
+/// Event labels (tags), e.g. `region` or `firmware`.
+pub type Labels = HashMap<String, String>;
+
+/// Event as sent by clients, before the payload of built-in types is checked.
+#[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
+struct RawEvent {
+    source_id: SourceId,
+    r#type: EventType,
+    timestamp: DateTime<Utc>,
+    data: Option<Value>,
+    #[serde(default)]
+    labels: Labels,
+}
+
+/// Serializes to the request body form, without the fields set by the server.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(try_from = "RawEvent", rename_all = "camelCase")]
 pub struct Event {
-    pub source_id: u64,
+    pub source_id: SourceId,
     pub r#type: EventType,
     pub timestamp: DateTime<Utc>,
     pub data: Option<Value>,
+    pub labels: Labels,
+    /// Tenant the event was ingested for, set by the server (not part of the
+    /// request body)
+    #[serde(skip_serializing)]
+    pub tenant: TenantId,
+    /// Number of ingested events this event stands for after sampling, set by
+    /// the server (not part of the request body)
+    #[serde(skip_serializing)]
+    pub sample_weight: u32,
+    /// Typed payload of built-in types, parsed from `data` on ingestion, so
+    /// processors don't parse it again (not part of the request body)
+    #[serde(skip_serializing)]
+    pub payload: Option<TypedPayload>,
+}
+
+impl TryFrom<RawEvent> for Event {
+    type Error = InvalidPayloadError;
+
+    fn try_from(raw: RawEvent) -> Result<Self, Self::Error> {
+        let payload = TypedPayload::parse(&raw.r#type, raw.data.as_ref())?;
+        Ok(Self::from_raw(raw, payload))
+    }
+}
+
+impl Event {
+    fn from_raw(raw: RawEvent, payload: Option<TypedPayload>) -> Self {
+        Event {
+            source_id: raw.source_id,
+            r#type: raw.r#type,
+            timestamp: raw.timestamp,
+            data: raw.data,
+            labels: raw.labels,
+            tenant: TenantId::default(),
+            sample_weight: 1,
+            payload,
+        }
+    }
+
+    /// Key of the event's source within its tenant.
+    pub fn source_key(&self) -> SourceKey {
+        SourceKey::new(self.tenant.clone(), self.source_id.clone())
+    }
+
+    /// Parses the typed payload from `data` again, after it was changed. The
+    /// payload is `None` if it is invalid.
+    pub fn parse_payload(&mut self) -> Result<(), InvalidPayloadError> {
+        self.payload = None;
+        self.payload = TypedPayload::parse(&self.r#type, self.data.as_ref())?;
+        Ok(())
+    }
+}
+
+/// An event as written to disk by the WAL, the spill buffer and the DLQ,
+/// with the fields set by the server that `Event` doesn't serialize.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PersistedEvent {
+    pub tenant: TenantId,
+    pub sample_weight: u32,
+    #[serde(deserialize_with = "deserialize_accepted")]
+    pub event: Event,
+}
+
+/// Reads an event back without checking its typed payload: it was accepted
+/// once, so it must not be dropped now, e.g. after a redaction or an upgrade
+/// changed what the check allows.
+fn deserialize_accepted<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Event, D::Error> {
+    RawEvent::deserialize(deserializer).map(|raw| {
+        let payload = TypedPayload::parse(&raw.r#type, raw.data.as_ref()).ok().flatten();
+        Event::from_raw(raw, payload)
+    })
+}
+
+impl From<Event> for PersistedEvent {
+    fn from(event: Event) -> Self {
+        Self { tenant: event.tenant.clone(), sample_weight: event.sample_weight, event }
+    }
+}
+
+impl PersistedEvent {
+    /// The event as it was accepted.
+    pub fn into_event(self) -> Event {
+        Event { tenant: self.tenant, sample_weight: self.sample_weight, ..self.event }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum EventValidationError {
     #[error("Disallowed source_id: {0}")]
-    DisallowedSourceId(u64),
+    DisallowedSourceId(SourceId),
+    #[error("Denied source_id: {0}")]
+    DeniedSourceId(SourceId),
+    #[error("Disallowed source_id kind: {0}")]
+    DisallowedSourceIdKind(SourceIdKind),
+    #[error("Source id is too long: {length} characters (max {max})")]
+    SourceIdTooLong { length: usize, max: usize },
     #[error("Disallowed event type: {0}")]
     DisallowedEventType(EventType),
+    #[error("Denied event type: {0}")]
+    DeniedEventType(EventType),
+    #[error("Disallowed label key: {0}")]
+    DisallowedLabelKey(String),
+    #[error("Too many labels: {count} (max {max})")]
+    TooManyLabels { count: usize, max: usize },
+    #[error("Label '{key}' is too long (max {max} characters)")]
+    LabelTooLong { key: String, max: usize },
+    #[error("No schema for event type: {0}")]
+    MissingSchema(EventType),
+    #[error("Data of {event_type} event does not match its schema at '{pointer}': {message}")]
+    SchemaViolation { event_type: EventType, pointer: String, message: String },
+    #[error("{message} (rule '{rule}')")]
+    RuleViolation { rule: String, message: String },
+    #[error("Source id is not provisioned: {0}")]
+    UnprovisionedSourceId(SourceId),
+    #[error("Validator {validator} is unavailable: {message}")]
+    ValidatorUnavailable { validator: &'static str, message: String },
+}
+
+impl EventValidationError {
+    /// Kind of the failure, without the event specific details (for metrics).
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Self::DisallowedSourceId(_) => "disallowed_source_id",
+            Self::DeniedSourceId(_) => "denied_source_id",
+            Self::DisallowedSourceIdKind(_) => "disallowed_source_id_kind",
+            Self::SourceIdTooLong { .. } => "source_id_too_long",
+            Self::DisallowedEventType(_) => "disallowed_event_type",
+            Self::DeniedEventType(_) => "denied_event_type",
+            Self::DisallowedLabelKey(_) => "disallowed_label_key",
+            Self::TooManyLabels { .. } => "too_many_labels",
+            Self::LabelTooLong { .. } => "label_too_long",
+            Self::MissingSchema(_) => "missing_schema",
+            Self::SchemaViolation { .. } => "schema_violation",
+            Self::RuleViolation { .. } => "rule_violation",
+            Self::UnprovisionedSourceId(_) => "unprovisioned_source_id",
+            Self::ValidatorUnavailable { .. } => "validator_unavailable",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<Event, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn test_parses_built_in_types() {
+        for name in ["Heartbeat", "Metric", "Log", "Error"] {
+            let event_type = EventType::from_str(name).ok();
+            assert!(!matches!(event_type, Some(EventType::Custom(_)) | None));
+            assert_eq!(event_type.map(|t| t.to_string()), Some(name.to_string()));
+        }
+        assert_eq!(EventType::from_str("Login").ok(), Some(EventType::Custom("Login".to_string())));
+    }
+
+    #[test]
+    fn test_accepts_valid_payloads() {
+        let metric = parse(
+            r#"{"sourceId":1,"type":"Metric","timestamp":"2024-01-01T00:00:00Z","data":{"name":"cpu","value":0.5,"unit":"ratio"}}"#,
+        );
+        assert!(matches!(metric.map(|e| e.payload), Ok(Some(TypedPayload::Metric(_)))));
+
+        let log = parse(
+            r#"{"sourceId":1,"type":"Log","timestamp":"2024-01-01T00:00:00Z","data":{"level":"warn","message":"disk low"}}"#,
+        );
+        assert!(matches!(log.map(|e| e.payload), Ok(Some(TypedPayload::Log(_)))));
+
+        let error = parse(
+            r#"{"sourceId":1,"type":"Error","timestamp":"2024-01-01T00:00:00Z","data":{"code":"E42","message":"boom"}}"#,
+        );
+        assert!(matches!(error.map(|e| e.payload), Ok(Some(TypedPayload::Error(_)))));
+    }
+
+    #[test]
+    fn test_rejects_invalid_payloads() {
+        assert!(
+            parse(r#"{"sourceId":1,"type":"Metric","timestamp":"2024-01-01T00:00:00Z"}"#).is_err()
+        );
+        assert!(
+            parse(
+                r#"{"sourceId":1,"type":"Metric","timestamp":"2024-01-01T00:00:00Z","data":{"name":"cpu","value":"high"}}"#
+            )
+            .is_err()
+        );
+        assert!(
+            parse(
+                r#"{"sourceId":1,"type":"Log","timestamp":"2024-01-01T00:00:00Z","data":{"level":"loud","message":"x"}}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_loads_persisted_events_without_payload_check() {
+        let json = r#"{"tenant":"a","sample_weight":5,"event":{"sourceId":1,"type":"Log","timestamp":"2024-01-01T00:00:00Z","data":{"level":"info"}}}"#;
+        let Ok(persisted) = serde_json::from_str::<PersistedEvent>(json) else {
+            panic!("persisted event should load");
+        };
+        let event = persisted.into_event();
+        assert_eq!(event.tenant, TenantId::new("a"));
+        assert_eq!(event.sample_weight, 5);
+        assert!(event.payload.is_none());
+    }
+
+    #[test]
+    fn test_parses_source_ids() {
+        assert_eq!("123".parse::<SourceId>().ok(), Some(SourceId::Numeric(123)));
+        assert_eq!(
+            "0F8FAD5B-D9CB-469F-A165-70867728950E"
+                .parse::<SourceId>()
+                .map(|id| id.to_string())
+                .ok(),
+            Some("0f8fad5b-d9cb-469f-a165-70867728950e".to_string())
+        );
+        assert_eq!(
+            "gw-01.eu.example.com".parse::<SourceId>().ok(),
+            Some(SourceId::Name("gw-01.eu.example.com".to_string()))
+        );
+        assert!("".parse::<SourceId>().is_err());
+        assert!("has space".parse::<SourceId>().is_err());
+        assert!("x".repeat(MAX_SOURCE_NAME_LENGTH + 1).parse::<SourceId>().is_err());
+    }
+
+    #[test]
+    fn test_deserializes_source_ids() {
+        let numeric =
+            parse(r#"{"sourceId":7,"type":"Heartbeat","timestamp":"2024-01-01T00:00:00Z"}"#);
+        let string =
+            parse(r#"{"sourceId":"7","type":"Heartbeat","timestamp":"2024-01-01T00:00:00Z"}"#);
+        assert_eq!(numeric.map(|e| e.source_id).ok(), Some(SourceId::Numeric(7)));
+        assert_eq!(string.map(|e| e.source_id).ok(), Some(SourceId::Numeric(7)));
+
+        assert!(
+            parse(r#"{"sourceId":-1,"type":"Heartbeat","timestamp":"2024-01-01T00:00:00Z"}"#)
+                .is_err()
+        );
+        assert_eq!(serde_json::to_string(&SourceId::Numeric(7)).ok(), Some("7".to_string()));
+        assert_eq!(
+            serde_json::to_string(&SourceId::Name("gw".to_string())).ok(),
+            Some("\"gw\"".to_string())
+        );
+    }
+
+    #[test]
+    fn test_custom_types_have_no_payload_schema() {
+        let event = parse(
+            r#"{"sourceId":1,"type":"Login","timestamp":"2024-01-01T00:00:00Z","data":[1,2]}"#,
+        );
+        assert!(matches!(event.map(|e| e.payload), Ok(None)));
+    }
 }
@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use axum::body::Bytes;
+use http::{Request, StatusCode, Uri, header};
+use http_body_util::{BodyExt, Full};
+use hyper_util::{
+    client::legacy::{Client, connect::HttpConnector},
+    rt::TokioExecutor,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpClientError {
+    #[error("Invalid URL '{0}'")]
+    InvalidUrl(String),
+    #[error("Failed to build request: {0}")]
+    Request(#[from] http::Error),
+    #[error("Request failed: {0}")]
+    Send(#[from] hyper_util::client::legacy::Error),
+    #[error("Failed to serialize request body: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("Request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Unexpected response status: {0}")]
+    Status(StatusCode),
+    #[error("Failed to read response body: {0}")]
+    Body(String),
+}
+
+/// Minimal HTTP client for outgoing JSON requests (webhooks, admin calls).
+/// Only plain `http://` URLs are supported.
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    client: Client<HttpConnector, Full<Bytes>>,
+    timeout: Duration,
+}
+
+impl HttpClient {
+    pub fn new(timeout: Duration) -> Self {
+        Self { client: Client::builder(TokioExecutor::new()).build_http(), timeout }
+    }
+
+    /// Sends a JSON request and returns an error for non-2xx responses.
+    pub async fn send_json(
+        &self,
+        method: http::Method,
+        url: &str,
+        body: &impl serde::Serialize,
+    ) -> Result<StatusCode, HttpClientError> {
+        let uri: Uri = url.parse().map_err(|_| HttpClientError::InvalidUrl(url.to_string()))?;
+        let body = serde_json::to_vec(body)?;
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))?;
+
+        let response = tokio::time::timeout(self.timeout, self.client.request(request))
+            .await
+            .map_err(|_| HttpClientError::Timeout(self.timeout))??;
+
+        let status = response.status();
+        if status.is_success() { Ok(status) } else { Err(HttpClientError::Status(status)) }
+    }
+
+    /// Sends a JSON `POST` request.
+    pub async fn post_json(
+        &self,
+        url: &str,
+        body: &impl serde::Serialize,
+    ) -> Result<StatusCode, HttpClientError> {
+        self.send_json(http::Method::POST, url, body).await
+    }
+
+    /// Sends a request without a body, with a bearer token if given, and
+    /// returns the status and body of the response whatever the status.
+    pub async fn request(
+        &self,
+        method: http::Method,
+        url: &str,
+        bearer: Option<&str>,
+    ) -> Result<(StatusCode, Bytes), HttpClientError> {
+        let uri: Uri = url.parse().map_err(|_| HttpClientError::InvalidUrl(url.to_string()))?;
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(token) = bearer {
+            builder = builder.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = builder.body(Full::new(Bytes::new()))?;
+
+        let response = tokio::time::timeout(self.timeout, async {
+            let response = self.client.request(request).await?;
+            let status = response.status();
+            let body = response
+                .into_body()
+                .collect()
+                .await
+                .map_err(|e| HttpClientError::Body(e.to_string()))?
+                .to_bytes();
+            Ok::<_, HttpClientError>((status, body))
+        })
+        .await
+        .map_err(|_| HttpClientError::Timeout(self.timeout))??;
+        Ok(response)
+    }
+}
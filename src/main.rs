@@ -4,17 +4,25 @@
 
 //! TODO: Add a description
 
+mod cli;
 mod common_types;
 mod config;
+mod dlq;
 mod error;
 mod event;
+mod http_client;
 mod metrics;
 mod plugins;
 mod processing;
 mod processor;
+mod quarantine;
 mod server;
+mod spill;
 mod state;
+mod tenant;
+mod transform;
 mod validation;
+mod wal;
 
 use std::{error::Error, sync::Arc};
 
@@ -24,6 +32,17 @@ use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    // Subcommands talk to a running server and print plain output, no logs
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(("dlq", args)) = args.split_first().map(|(command, args)| (command.as_str(), args))
+    {
+        if let Err(err) = cli::run_dlq(args).await {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Initialize the tracing subscriber
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -64,6 +83,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     };
 
+    // Build transformer plugins
+    let transformers = match plugins::build_transformers(&config) {
+        Ok(transformers) => {
+            tracing::info!("{} transformers loaded successfully", transformers.len());
+            transformers
+        }
+        Err(err) => {
+            tracing::error!("Failed to load transformers: {}", err);
+            std::process::exit(1);
+        }
+    };
+
     // Build processor plugins
     let processors = match plugins::build_processors(&config) {
         Ok(processors) => {
@@ -76,7 +107,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     };
 
-    if let Err(err) = run_server(config, validators, processors, prometheus_handle).await {
+    if let Err(err) =
+        run_server(config, validators, transformers, processors, prometheus_handle).await
+    {
         tracing::error!("Error: {}", err);
         std::process::exit(1);
     }
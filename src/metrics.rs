@@ -1,6 +1,20 @@
-use metrics::{Unit, describe_counter, describe_histogram};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap},
+    fmt::Write,
+};
+
+use chrono::{DateTime, Utc};
+use metrics::{Unit, describe_counter, describe_gauge, describe_histogram};
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
 
+use crate::{
+    common_types::TelemetryMap,
+    config::SourceStatsMetricsConfig,
+    event::EventType,
+    tenant::{SourceKey, TenantId},
+};
+
 pub fn setup_metrics() -> PrometheusHandle {
     PrometheusBuilder::new().install_recorder().expect("Failed to install Prometheus recorder") // Use expect here as it's critical for startup
 }
@@ -12,10 +26,70 @@ pub const HTTP_REQUESTS_DURATION_SECONDS: &str = "telemetron_http_requests_durat
 // -------- Processor Metrics --------
 // Renamed for clarity: focuses on plugin errors leading to DLQ
 pub const PROCESSOR_PLUGIN_ERRORS_TOTAL: &str = "telemetron_processor_plugin_errors_total";
+pub const PROCESSOR_PLUGIN_RETRIES_TOTAL: &str = "telemetron_processor_plugin_retries_total";
+pub const PROCESSOR_PLUGIN_FAILED_EVENTS_TOTAL: &str =
+    "telemetron_processor_plugin_failed_events_total";
 // Renamed for clarity: focuses on plugin execution time
 pub const PROCESSOR_PLUGIN_DURATION_SECONDS: &str = "telemetron_processor_plugin_duration_seconds";
 // Overall successful event count
 pub const EVENTS_PROCESSED_TOTAL: &str = "telemetron_events_processed_total";
+pub const PROCESSOR_QUEUE_BACKLOG: &str = "telemetron_processor_queue_backlog";
+pub const PROCESSOR_QUEUE_DROPPED_EVENTS_TOTAL: &str =
+    "telemetron_processor_queue_dropped_events_total";
+pub const PROCESSOR_WORKER_EVENTS_TOTAL: &str = "telemetron_processor_worker_events_total";
+pub const PROCESSOR_WORKER_BATCHES_TOTAL: &str = "telemetron_processor_worker_batches_total";
+
+// -------- Validator Metrics --------
+pub const VALIDATOR_LIST_RELOADS_TOTAL: &str = "telemetron_validator_list_reloads_total";
+pub const VALIDATOR_UNAVAILABLE_TOTAL: &str = "telemetron_validator_unavailable_total";
+pub const VALIDATION_SHADOW_REJECTS_TOTAL: &str = "telemetron_validation_shadow_rejects_total";
+
+// -------- Quarantine Metrics --------
+pub const QUARANTINED_EVENTS_TOTAL: &str = "telemetron_quarantined_events_total";
+pub const QUARANTINE_EVICTED_EVENTS_TOTAL: &str = "telemetron_quarantine_evicted_events_total";
+pub const QUARANTINE_EVENTS: &str = "telemetron_quarantine_events";
+
+// -------- Dead-Letter Queue Metrics --------
+pub const DLQ_BATCHES_TOTAL: &str = "telemetron_dlq_batches_total";
+pub const DLQ_EVICTED_BATCHES_TOTAL: &str = "telemetron_dlq_evicted_batches_total";
+pub const DLQ_BATCHES: &str = "telemetron_dlq_batches";
+pub const DLQ_REPLAYS_TOTAL: &str = "telemetron_dlq_replays_total";
+
+// -------- Write-Ahead Log Metrics --------
+pub const WAL_APPENDED_EVENTS_TOTAL: &str = "telemetron_wal_appended_events_total";
+pub const WAL_REPLAYED_EVENTS_TOTAL: &str = "telemetron_wal_replayed_events_total";
+pub const WAL_UNCOMMITTED_EVENTS: &str = "telemetron_wal_uncommitted_events";
+pub const WAL_SEGMENTS: &str = "telemetron_wal_segments";
+
+// -------- Spill Metrics --------
+pub const SPILLED_EVENTS_TOTAL: &str = "telemetron_spilled_events_total";
+pub const SPILLED_BYTES_TOTAL: &str = "telemetron_spilled_bytes_total";
+pub const SPILL_BUFFERED_BYTES: &str = "telemetron_spill_buffered_bytes";
+pub const SPILL_DRAIN_LAG_SECONDS: &str = "telemetron_spill_drain_lag_seconds";
+pub const SPILL_REJECTED_EVENTS_TOTAL: &str = "telemetron_spill_rejected_events_total";
+
+// -------- Transformer Metrics --------
+pub const TRANSFORMER_DROPPED_EVENTS_TOTAL: &str = "telemetron_transformer_dropped_events_total";
+pub const REDACTIONS_TOTAL: &str = "telemetron_redactions_total";
+
+// -------- Liveness Metrics --------
+pub const SOURCES_DOWN: &str = "telemetron_sources_down";
+pub const SOURCE_LIVENESS_TRANSITIONS_TOTAL: &str = "telemetron_source_liveness_transitions_total";
+
+// -------- Source Stats Metrics --------
+// Rendered from the telemetry map on each scrape, see `render_source_stats`
+pub const SOURCE_EVENTS: &str = "telemetron_source_events";
+pub const SOURCE_SECONDS_SINCE_LAST_EVENT: &str = "telemetron_source_seconds_since_last_event";
+pub const SOURCE_EVENTS_AGGREGATED: &str = "telemetron_source_events_aggregated";
+
+// -------- Tenant Metrics --------
+pub const TENANT_EVENTS_TOTAL: &str = "telemetron_tenant_events_total";
+pub const TENANT_REJECTED_EVENTS_TOTAL: &str = "telemetron_tenant_rejected_events_total";
+/// Values of the `aggregated` label: what the series sums beyond the
+/// cardinality cap, the summed label is left out
+const AGGREGATED_SOURCES: &str = "source";
+const AGGREGATED_TYPES: &str = "type";
+const AGGREGATED_SOURCES_AND_TYPES: &str = "source_and_type";
 
 /// Call this function once at startup to describe metrics to the exporter.
 pub fn describe_metrics() {
@@ -37,6 +111,16 @@ pub fn describe_metrics() {
         Unit::Count,
         "Total number of permanent processing errors for each plugin (leading to DLQ)."
     );
+    describe_counter!(
+        PROCESSOR_PLUGIN_RETRIES_TOTAL,
+        Unit::Count,
+        "Total number of batch retries after retryable errors for each plugin."
+    );
+    describe_counter!(
+        PROCESSOR_PLUGIN_FAILED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events each plugin failed to process after all retries."
+    );
     describe_histogram!(
         PROCESSOR_PLUGIN_DURATION_SECONDS,
         Unit::Seconds,
@@ -48,4 +132,509 @@ pub fn describe_metrics() {
         Unit::Count,
         "Total number of events successfully processed by all plugins in the pipeline."
     );
+    describe_gauge!(
+        PROCESSOR_QUEUE_BACKLOG,
+        Unit::Count,
+        "Number of events waiting in the queue of each processor plugin, per worker."
+    );
+    describe_counter!(
+        PROCESSOR_QUEUE_DROPPED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events dropped because the queue of a processor plugin was full, \
+         partitioned by plugin and overflow policy."
+    );
+    describe_counter!(
+        PROCESSOR_WORKER_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events routed to each processor worker."
+    );
+    describe_counter!(
+        PROCESSOR_WORKER_BATCHES_TOTAL,
+        Unit::Count,
+        "Total number of batches formed by each processor worker."
+    );
+
+    // --- Validators ---
+    describe_counter!(
+        VALIDATOR_LIST_RELOADS_TOTAL,
+        Unit::Count,
+        "Total number of validator list file reloads, partitioned by validator, list and status."
+    );
+    describe_counter!(
+        VALIDATOR_UNAVAILABLE_TOTAL,
+        Unit::Count,
+        "Total number of validations that failed or timed out in the validator itself, \
+         partitioned by validator and failure policy."
+    );
+    describe_counter!(
+        VALIDATION_SHADOW_REJECTS_TOTAL,
+        Unit::Count,
+        "Total number of events validators in shadow mode would have rejected, \
+         partitioned by validator and reason."
+    );
+
+    // --- Quarantine ---
+    describe_counter!(
+        QUARANTINED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of rejected events kept in the quarantine, partitioned by the first \
+         failing validator."
+    );
+    describe_counter!(
+        QUARANTINE_EVICTED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of quarantined events evicted because the quarantine was full."
+    );
+    describe_gauge!(
+        QUARANTINE_EVENTS,
+        Unit::Count,
+        "Number of events currently in the quarantine."
+    );
+
+    // --- Dead-Letter Queue ---
+    describe_counter!(
+        DLQ_BATCHES_TOTAL,
+        Unit::Count,
+        "Total number of failed batches written to the dead-letter queue (label: plugin)."
+    );
+    describe_counter!(
+        DLQ_EVICTED_BATCHES_TOTAL,
+        Unit::Count,
+        "Total number of batches deleted with the oldest segment of a full dead-letter queue."
+    );
+    describe_gauge!(
+        DLQ_BATCHES,
+        Unit::Count,
+        "Number of batches currently in the dead-letter queue."
+    );
+    describe_counter!(
+        DLQ_REPLAYS_TOTAL,
+        Unit::Count,
+        "Total number of dead-letter queue replays (labels: plugin, status)."
+    );
+
+    // --- Write-Ahead Log ---
+    describe_counter!(
+        WAL_APPENDED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of accepted events appended to the write-ahead log."
+    );
+    describe_counter!(
+        WAL_REPLAYED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of unprocessed events replayed from the write-ahead log on startup."
+    );
+    describe_gauge!(
+        WAL_UNCOMMITTED_EVENTS,
+        Unit::Count,
+        "Number of events in the write-ahead log not yet processed by all plugins."
+    );
+    describe_gauge!(WAL_SEGMENTS, Unit::Count, "Number of segment files of the write-ahead log.");
+
+    // --- Spill ---
+    describe_counter!(
+        SPILLED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events spilled to disk because a processor worker channel was full \
+         (label: worker)."
+    );
+    describe_counter!(
+        SPILLED_BYTES_TOTAL,
+        Unit::Bytes,
+        "Total number of bytes of events spilled to disk (label: worker)."
+    );
+    describe_gauge!(
+        SPILL_BUFFERED_BYTES,
+        Unit::Bytes,
+        "Bytes of spilled events not yet drained back to the processor workers."
+    );
+    describe_histogram!(
+        SPILL_DRAIN_LAG_SECONDS,
+        Unit::Seconds,
+        "Time spilled events waited on disk before they were drained back (label: worker)."
+    );
+    describe_counter!(
+        SPILL_REJECTED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events rejected because the spill buffer was full."
+    );
+
+    // --- Transformers ---
+    describe_counter!(
+        TRANSFORMER_DROPPED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events dropped by transformers, partitioned by transformer."
+    );
+    describe_counter!(
+        REDACTIONS_TOTAL,
+        Unit::Count,
+        "Total number of values redacted from events, partitioned by rule (path or pattern)."
+    );
+
+    // --- Tenants ---
+    describe_counter!(
+        TENANT_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events accepted for processing, partitioned by tenant."
+    );
+    describe_counter!(
+        TENANT_REJECTED_EVENTS_TOTAL,
+        Unit::Count,
+        "Total number of events rejected by tenant scope or quotas, partitioned by tenant and \
+         reason."
+    );
+
+    // --- Liveness ---
+    describe_gauge!(
+        SOURCES_DOWN,
+        Unit::Count,
+        "Number of sources currently marked as down by the heartbeat monitor."
+    );
+    describe_counter!(
+        SOURCE_LIVENESS_TRANSITIONS_TOTAL,
+        Unit::Count,
+        "Total number of source up/down transitions, partitioned by status."
+    );
+}
+
+/// Renders per-source statistics from the telemetry map in the Prometheus
+/// text format, to be appended to the output of the `PrometheusHandle`.
+/// Only allowed (or top-K by event count) sources and their top-K event types
+/// get their own label values, the rest is summed per tenant into a separate
+/// gauge so the label cardinality stays bounded. All series are gauges: a
+/// series disappears or drops when its source or type leaves the top-K.
+pub fn render_source_stats(
+    telemetry_map: &TelemetryMap,
+    config: &SourceStatsMetricsConfig,
+    now: DateTime<Utc>,
+) -> String {
+    let mut exported: Vec<SourceKey> = if config.allowed_sources.is_empty() {
+        top_sources(telemetry_map, config.top_k)
+    } else {
+        telemetry_map
+            .iter()
+            .filter(|entry| config.allowed_sources.contains(&entry.key().source_id))
+            .map(|entry| entry.key().clone())
+            .collect()
+    };
+    exported.sort_unstable();
+
+    let mut events_lines = Vec::new();
+    let mut last_event_lines = Vec::new();
+    // Rendered labels (without `aggregated`) and value of each aggregated series
+    let mut aggregated_lines: Vec<(String, &str, u64)> = Vec::new();
+    let mut other: BTreeMap<TenantId, HashMap<EventType, u64>> = BTreeMap::new();
+
+    for entry in telemetry_map.iter() {
+        if exported.binary_search(entry.key()).is_err() {
+            let other = other.entry(entry.key().tenant.clone()).or_default();
+            for (event_type, count) in &entry.value().events_by_type {
+                *other.entry(event_type.clone()).or_insert(0) += count;
+            }
+        }
+    }
+
+    for key in &exported {
+        let Some(entry) = telemetry_map.get(key) else {
+            continue;
+        };
+        let telemetry = entry.value();
+
+        let tenant = key.tenant.to_string();
+        let source = key.source_id.to_string();
+        let (types, other_types) =
+            cap_event_types(&telemetry.events_by_type, config.top_k_event_types);
+        for (event_type, count) in types {
+            events_lines.push((tenant.clone(), source.clone(), event_type, count));
+        }
+        if let Some(count) = other_types {
+            let labels = format!(
+                "tenant=\"{}\",source=\"{}\"",
+                escape_label_value(&tenant),
+                escape_label_value(&source)
+            );
+            aggregated_lines.push((labels, AGGREGATED_TYPES, count));
+        }
+        let since_last_event =
+            (now - telemetry.last_timestamp).num_milliseconds().max(0) as f64 / 1000.0;
+        last_event_lines.push((tenant, source, since_last_event));
+    }
+
+    for (tenant, events_by_type) in other {
+        let (types, other_types) = cap_event_types(&events_by_type, config.top_k_event_types);
+        for (event_type, count) in types {
+            let labels = format!(
+                "tenant=\"{}\",type=\"{}\"",
+                escape_label_value(&tenant.to_string()),
+                escape_label_value(&event_type)
+            );
+            aggregated_lines.push((labels, AGGREGATED_SOURCES, count));
+        }
+        if let Some(count) = other_types {
+            let labels = format!("tenant=\"{}\"", escape_label_value(&tenant.to_string()));
+            aggregated_lines.push((labels, AGGREGATED_SOURCES_AND_TYPES, count));
+        }
+    }
+
+    let mut output = String::new();
+    if !events_lines.is_empty() {
+        let _ = writeln!(
+            output,
+            "# HELP {} Events processed per exported source and event type.",
+            SOURCE_EVENTS
+        );
+        let _ = writeln!(output, "# TYPE {} gauge", SOURCE_EVENTS);
+        for (tenant, source, event_type, count) in events_lines {
+            let _ = writeln!(
+                output,
+                "{}{{tenant=\"{}\",source=\"{}\",type=\"{}\"}} {}",
+                SOURCE_EVENTS,
+                escape_label_value(&tenant),
+                escape_label_value(&source),
+                escape_label_value(&event_type),
+                count
+            );
+        }
+    }
+    if !last_event_lines.is_empty() {
+        let _ = writeln!(
+            output,
+            "# HELP {} Seconds since the latest event timestamp per source.",
+            SOURCE_SECONDS_SINCE_LAST_EVENT
+        );
+        let _ = writeln!(output, "# TYPE {} gauge", SOURCE_SECONDS_SINCE_LAST_EVENT);
+        for (tenant, source, seconds) in last_event_lines {
+            let _ = writeln!(
+                output,
+                "{}{{tenant=\"{}\",source=\"{}\"}} {}",
+                SOURCE_SECONDS_SINCE_LAST_EVENT,
+                escape_label_value(&tenant),
+                escape_label_value(&source),
+                seconds
+            );
+        }
+    }
+    if !aggregated_lines.is_empty() {
+        let _ = writeln!(
+            output,
+            "# HELP {} Events of the sources and event types beyond the cardinality cap, per \
+             tenant.",
+            SOURCE_EVENTS_AGGREGATED
+        );
+        let _ = writeln!(output, "# TYPE {} gauge", SOURCE_EVENTS_AGGREGATED);
+        for (labels, aggregated, count) in aggregated_lines {
+            let _ = writeln!(
+                output,
+                "{}{{{},aggregated=\"{}\"}} {}",
+                SOURCE_EVENTS_AGGREGATED, labels, aggregated, count
+            );
+        }
+    }
+
+    output
+}
+
+/// Keys of the `k` sources with the most events, ties broken by key so the
+/// selection is stable. Only `k` sources are kept in a heap while scanning,
+/// instead of sorting all of them.
+fn top_sources(telemetry_map: &TelemetryMap, k: usize) -> Vec<SourceKey> {
+    // The least active kept source is on top
+    let mut heap: BinaryHeap<(Reverse<u64>, SourceKey)> = BinaryHeap::with_capacity(k + 1);
+    for entry in telemetry_map.iter() {
+        let count = Reverse(entry.value().total_events);
+        if heap.len() == k
+            && heap.peek().is_none_or(|top| (&count, entry.key()) >= (&top.0, &top.1))
+        {
+            continue;
+        }
+        heap.push((count, entry.key().clone()));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+    heap.into_iter().map(|(_, key)| key).collect()
+}
+
+/// Keeps the `limit` most frequent event types, and returns the sum of the
+/// rest if any.
+fn cap_event_types(
+    events_by_type: &HashMap<EventType, u64>,
+    limit: usize,
+) -> (Vec<(String, u64)>, Option<u64>) {
+    let mut types: Vec<(String, u64)> =
+        events_by_type.iter().map(|(event_type, count)| (event_type.to_string(), *count)).collect();
+    types.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    if types.len() > limit {
+        let other: u64 = types.split_off(limit).iter().map(|(_, count)| count).sum();
+        return (types, Some(other));
+    }
+
+    (types, None)
+}
+
+/// Escapes a label value for the Prometheus text format.
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, sync::Arc};
+
+    use dashmap::DashMap;
+
+    use super::*;
+    use crate::{
+        event::{Event, SourceId},
+        processing::source_telemetry::SourceTelemetry,
+    };
+
+    fn create_map(events: &[(u64, EventType)]) -> TelemetryMap {
+        let map: TelemetryMap = Arc::new(DashMap::new());
+        for (source_id, event_type) in events {
+            let event = Event {
+                source_id: (*source_id).into(),
+                r#type: event_type.clone(),
+                timestamp: Utc::now(),
+                data: None,
+                labels: Default::default(),
+                tenant: Default::default(),
+                sample_weight: 1,
+                payload: None,
+            };
+            map.entry(event.source_key())
+                .and_modify(|t| t.update(&event, &Default::default()))
+                .or_insert_with(|| SourceTelemetry::new(&event, &Default::default()));
+        }
+        map
+    }
+
+    fn create_config(top_k: usize, allowed_sources: HashSet<SourceId>) -> SourceStatsMetricsConfig {
+        SourceStatsMetricsConfig { enabled: true, allowed_sources, top_k, ..Default::default() }
+    }
+
+    #[test]
+    fn test_renders_top_k_sources() {
+        let map = create_map(&[
+            (1, EventType::Heartbeat),
+            (1, EventType::Heartbeat),
+            (2, EventType::Heartbeat),
+            (3, EventType::Heartbeat),
+        ]);
+
+        let output = render_source_stats(&map, &create_config(1, HashSet::new()), Utc::now());
+
+        assert!(output.contains(
+            "telemetron_source_events{tenant=\"default\",source=\"1\",type=\"Heartbeat\"} 2"
+        ));
+        assert!(output.contains(
+            "telemetron_source_events_aggregated{tenant=\"default\",type=\"Heartbeat\",\
+             aggregated=\"source\"} 2"
+        ));
+        assert!(output.contains("# TYPE telemetron_source_events gauge"));
+        assert!(output.contains("# TYPE telemetron_source_events_aggregated gauge"));
+        assert!(output.contains(
+            "telemetron_source_seconds_since_last_event{tenant=\"default\",source=\"1\"}"
+        ));
+        assert!(!output.contains("source=\"2\""));
+    }
+
+    #[test]
+    fn test_renders_allowed_sources() {
+        let map = create_map(&[
+            (1, EventType::Heartbeat),
+            (1, EventType::Heartbeat),
+            (2, EventType::Heartbeat),
+        ]);
+
+        let output =
+            render_source_stats(&map, &create_config(1, HashSet::from([2.into()])), Utc::now());
+
+        assert!(output.contains(
+            "telemetron_source_events{tenant=\"default\",source=\"2\",type=\"Heartbeat\"} 1"
+        ));
+        assert!(output.contains(
+            "telemetron_source_events_aggregated{tenant=\"default\",type=\"Heartbeat\",\
+             aggregated=\"source\"} 2"
+        ));
+    }
+
+    #[test]
+    fn test_source_named_other_does_not_collide() {
+        let map: TelemetryMap = Arc::new(DashMap::new());
+        for (source_id, event_type) in [
+            ("other", EventType::Heartbeat),
+            ("other", EventType::Custom("A".to_string())),
+            ("b", EventType::Heartbeat),
+        ] {
+            let event = Event {
+                source_id: SourceId::Name(source_id.to_string()),
+                r#type: event_type,
+                timestamp: Utc::now(),
+                data: None,
+                labels: Default::default(),
+                tenant: Default::default(),
+                sample_weight: 1,
+                payload: None,
+            };
+            map.entry(event.source_key())
+                .and_modify(|t| t.update(&event, &Default::default()))
+                .or_insert_with(|| SourceTelemetry::new(&event, &Default::default()));
+        }
+        let config =
+            SourceStatsMetricsConfig { top_k_event_types: 1, ..create_config(1, HashSet::new()) };
+
+        let output = render_source_stats(&map, &config, Utc::now());
+
+        assert!(output.contains(
+            "telemetron_source_events{tenant=\"default\",source=\"other\",type=\"A\"} 1"
+        ));
+        assert!(output.contains(
+            "telemetron_source_events_aggregated{tenant=\"default\",source=\"other\",\
+             aggregated=\"type\"} 1"
+        ));
+        assert!(output.contains(
+            "telemetron_source_events_aggregated{tenant=\"default\",type=\"Heartbeat\",\
+             aggregated=\"source\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_top_sources_breaks_ties_by_key() {
+        let map = create_map(&[
+            (3, EventType::Heartbeat),
+            (3, EventType::Heartbeat),
+            (2, EventType::Heartbeat),
+            (1, EventType::Heartbeat),
+            (4, EventType::Heartbeat),
+        ]);
+
+        let mut top = top_sources(&map, 2);
+        top.sort_unstable();
+
+        let key = |id: u64| SourceKey::new(Default::default(), id.into());
+        assert_eq!(top, vec![key(1), key(3)]);
+        assert!(top_sources(&map, 0).is_empty());
+        assert_eq!(top_sources(&map, 10).len(), 4);
+    }
+
+    #[test]
+    fn test_caps_event_types() {
+        let events_by_type = HashMap::from([
+            (EventType::Heartbeat, 5),
+            (EventType::Custom("A".to_string()), 2),
+            (EventType::Custom("B".to_string()), 1),
+        ]);
+
+        let (types, other) = cap_event_types(&events_by_type, 1);
+
+        assert_eq!(types, vec![("Heartbeat".to_string(), 5)]);
+        assert_eq!(other, Some(3));
+    }
+
+    #[test]
+    fn test_escapes_label_values() {
+        assert_eq!(escape_label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
 }
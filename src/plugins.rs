@@ -1,10 +1,14 @@
 use std::sync::Arc;
 
+use serde::de::DeserializeOwned;
+
 use crate::{
-    common_types::{EventProcessors, EventValidators},
-    config::Config,
+    common_types::{EventProcessors, EventTransformers, EventValidators},
+    config::{Config, ProcessorPluginConfig, TransformerChainConfig, ValidatorChainConfig},
     processing::EventProcessor,
-    validation::EventValidator,
+    processor::QueuedProcessor,
+    transform::EventTransformer,
+    validation::{EventValidator, guard::GuardedValidator},
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -15,6 +19,8 @@ pub enum PluginError {
         #[source]
         source: toml::de::Error,
     },
+    #[error("Invalid parameters for plugin '{plugin_name}': {message}")]
+    InvalidParameters { plugin_name: String, message: String },
 }
 
 pub struct ValidationPluginFactory {
@@ -22,6 +28,12 @@ pub struct ValidationPluginFactory {
     pub constructor: fn(toml::Value) -> Result<Box<dyn EventValidator + Send + Sync>, PluginError>,
 }
 
+pub struct TransformPluginFactory {
+    pub name: &'static str,
+    pub constructor:
+        fn(toml::Value) -> Result<Box<dyn EventTransformer + Send + Sync>, PluginError>,
+}
+
 pub struct ProcessingPluginFactory {
     pub name: &'static str,
     pub constructor: fn(toml::Value) -> Result<Box<dyn EventProcessor + Send + Sync>, PluginError>,
@@ -29,32 +41,130 @@ pub struct ProcessingPluginFactory {
 
 // Register the plugin factories
 inventory::collect!(ValidationPluginFactory);
+inventory::collect!(TransformPluginFactory);
 inventory::collect!(ProcessingPluginFactory);
 
+/// Reserved keys of a validator plugin table, see `ValidatorChainConfig`.
+const VALIDATOR_CHAIN_KEYS: &[&str] = &["order", "timeout", "on_failure", "mode"];
+/// Reserved keys of a transformer plugin table, see `TransformerChainConfig`.
+const TRANSFORMER_CHAIN_KEYS: &[&str] = &["order"];
+/// Reserved keys of a processing plugin table, see `ProcessorPluginConfig`.
+const PROCESSOR_PLUGIN_KEYS: &[&str] = &["queue_capacity", "concurrency", "overflow", "retry"];
+
+/// Splits the reserved keys off the parameters of a plugin, so plugin configs
+/// don't need to know about them.
+fn split_chain_config<T: DeserializeOwned>(
+    plugin_name: &str,
+    params: &toml::Value,
+    keys: &[&str],
+) -> Result<(T, toml::Value), PluginError> {
+    let mut params = params.clone();
+    let mut chain = toml::Table::new();
+    if let Some(table) = params.as_table_mut() {
+        for &key in keys {
+            if let Some(value) = table.remove(key) {
+                chain.insert(key.to_string(), value);
+            }
+        }
+    }
+    let chain = toml::Value::Table(chain).try_into().map_err(|e| {
+        PluginError::ParameterDeserialization { plugin_name: plugin_name.to_string(), source: e }
+    })?;
+    Ok((chain, params))
+}
+
+/// Builds the configured validators in chain order: by ascending `order`,
+/// then plugins without one, ties broken by plugin name so the order does not
+/// depend on the build. Each validator runs with its timeout, failure policy
+/// and mode.
 pub fn build_validators(config: &Config) -> Result<EventValidators, PluginError> {
-    let mut validators = Vec::new();
     let config_plugins = &config.validation.plugins;
+    let mut configured = Vec::new();
 
     for factory in inventory::iter::<ValidationPluginFactory> {
         let name = factory.name;
-        tracing::debug!(plugin_name = name, "Loading validator plugin");
         // Check if the plugin is in the config
-        // and if so, call the constructor with the parameters
-        // provided in the config
+        // and if so, take its chain settings and parameters
         if let Some(params) = config_plugins.get(name) {
-            tracing::debug!(plugin_name = name, "Loading validator plugin");
-
-            let plugin_box = (factory.constructor)(params.clone())?;
-            validators.push(plugin_box);
-            tracing::info!(plugin_name = name, "Validator plugin loaded successfully");
+            let (chain, params): (ValidatorChainConfig, _) =
+                split_chain_config(name, params, VALIDATOR_CHAIN_KEYS)?;
+            configured.push((chain, factory, params));
         } else {
             tracing::warn!(plugin_name = name, "Validator plugin not found in config");
         }
     }
+    configured
+        .sort_by_key(|(chain, factory, _)| (chain.order.is_none(), chain.order, factory.name));
+
+    let mut validators = Vec::with_capacity(configured.len());
+    for (chain, factory, params) in configured {
+        let name = factory.name;
+        tracing::debug!(plugin_name = name, order = chain.order, "Loading validator plugin");
+
+        let plugin_box = (factory.constructor)(params)?;
+        if chain.timeout.is_some() && !plugin_box.supports_timeout() {
+            return Err(PluginError::InvalidParameters {
+                plugin_name: name.to_string(),
+                message: "timeout is only supported by validators with async lookups".to_string(),
+            });
+        }
+        validators.push(Box::new(GuardedValidator::new(plugin_box, &chain)) as Box<_>);
+        tracing::info!(plugin_name = name, "Validator plugin loaded successfully");
+    }
 
     Ok(Arc::new(validators))
 }
 
+/// Builds the configured transformers in chain order, ordered like the
+/// validators.
+pub fn build_transformers(config: &Config) -> Result<EventTransformers, PluginError> {
+    let config_plugins = &config.transform.plugins;
+    let mut configured = Vec::new();
+
+    for factory in inventory::iter::<TransformPluginFactory> {
+        let name = factory.name;
+        if let Some(params) = config_plugins.get(name) {
+            let (chain, params): (TransformerChainConfig, _) =
+                split_chain_config(name, params, TRANSFORMER_CHAIN_KEYS)?;
+            configured.push((chain, factory, params));
+        }
+    }
+    configured
+        .sort_by_key(|(chain, factory, _)| (chain.order.is_none(), chain.order, factory.name));
+
+    let mut transformers = Vec::with_capacity(configured.len());
+    for (chain, factory, params) in configured {
+        let name = factory.name;
+        tracing::debug!(plugin_name = name, order = chain.order, "Loading transformer plugin");
+
+        let plugin_box = (factory.constructor)(params)?;
+        transformers.push(plugin_box);
+        tracing::info!(plugin_name = name, "Transformer plugin loaded successfully");
+    }
+
+    Ok(Arc::new(transformers))
+}
+
+/// Checks the reserved keys of a processing plugin table.
+fn check_processor_config(config: &ProcessorPluginConfig) -> Result<(), String> {
+    let retry = &config.retry;
+    if config.queue_capacity == 0 || config.concurrency == 0 {
+        return Err("queue_capacity and concurrency must be at least 1".to_string());
+    }
+    if retry.max_attempts == Some(0) {
+        return Err("retry.max_attempts must be at least 1".to_string());
+    }
+    if retry.multiplier < 1.0 {
+        return Err("retry.multiplier must be at least 1".to_string());
+    }
+    if !(0.0..=1.0).contains(&retry.jitter) {
+        return Err("retry.jitter must be between 0 and 1".to_string());
+    }
+    Ok(())
+}
+
+/// Builds the configured processors, each with the queue, workers and retry
+/// policy set by its reserved keys.
 pub fn build_processors(config: &Config) -> Result<EventProcessors, PluginError> {
     let mut processors = Vec::new();
     let config_plugins = &config.processing.plugins;
@@ -68,8 +178,22 @@ pub fn build_processors(config: &Config) -> Result<EventProcessors, PluginError>
         if let Some(params) = config_plugins.get(name) {
             tracing::debug!(plugin_name = name, "Loading processor plugin");
 
-            let plugin_box = (factory.constructor)(params.clone())?;
-            processors.push(plugin_box);
+            let (plugin_config, params): (ProcessorPluginConfig, _) =
+                split_chain_config(name, params, PROCESSOR_PLUGIN_KEYS)?;
+            check_processor_config(&plugin_config).map_err(|message| {
+                PluginError::InvalidParameters { plugin_name: name.to_string(), message }
+            })?;
+            let plugin_box = (factory.constructor)(params)?;
+            if plugin_config.concurrency > 1 && !plugin_box.supports_concurrency() {
+                return Err(PluginError::InvalidParameters {
+                    plugin_name: name.to_string(),
+                    message: "concurrency above 1 is only supported by processors that don't \
+                              depend on the event order"
+                        .to_string(),
+                });
+            }
+            processors
+                .push(QueuedProcessor { processor: Arc::from(plugin_box), config: plugin_config });
             tracing::info!(plugin_name = name, "Processor plugin loaded successfully");
         } else {
             tracing::warn!(plugin_name = name, "Processor plugin not found in config");
@@ -78,3 +202,37 @@ pub fn build_processors(config: &Config) -> Result<EventProcessors, PluginError>
 
     Ok(Arc::new(processors))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_chain_config_off_plugin_params() {
+        let Ok(params) = toml::from_str::<toml::Value>("order = 2\ntimeout = 50\nallowed = [1]")
+        else {
+            panic!("params should parse");
+        };
+        let Ok((chain, params)) = split_chain_config::<ValidatorChainConfig>(
+            "SourceIdValidator",
+            &params,
+            VALIDATOR_CHAIN_KEYS,
+        ) else {
+            panic!("chain config should be split off");
+        };
+        assert_eq!(chain.order, Some(2));
+        assert_eq!(chain.timeout, Some(50));
+        assert!(params.get("order").is_none() && params.get("timeout").is_none());
+        assert!(params.get("allowed").is_some());
+
+        let Ok(params) = toml::from_str::<toml::Value>("order = \"first\"") else {
+            panic!("params should parse");
+        };
+        let chain = split_chain_config::<ValidatorChainConfig>(
+            "SourceIdValidator",
+            &params,
+            VALIDATOR_CHAIN_KEYS,
+        );
+        assert!(chain.is_err());
+    }
+}
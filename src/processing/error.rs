@@ -1,5 +1,6 @@
 /// This module defines a custom error type for handling errors that occur
-/// during the processing of data in a plugin.
+/// during the processing of data in a plugin. Errors are retryable unless
+/// created with `ProcessingError::permanent`.
 /// Example:
 /// ```rust
 /// Err(ProcessingError::new(
@@ -12,16 +13,42 @@
 pub struct ProcessingError {
     pub plugin_name: &'static str,
     pub details: String,
+    pub kind: ProcessingErrorKind,
     #[source]
     pub source: Option<Box<dyn std::error::Error + Send + Sync>>,
 }
 
+/// Whether retrying a failed batch can succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingErrorKind {
+    /// A transient failure, e.g. a timeout or an unreachable sink. The batch
+    /// is retried with the plugin's retry policy.
+    Retryable,
+    /// A failure that repeats on every attempt, e.g. data the sink's schema
+    /// rejects. The batch fails at once.
+    Permanent,
+}
+
 impl ProcessingError {
+    /// A retryable error.
     pub fn new(
         plugin_name: &'static str,
         details: impl Into<String>,
         source: Option<Box<dyn std::error::Error + Send + Sync>>,
     ) -> Self {
-        Self { plugin_name, details: details.into(), source }
+        Self { plugin_name, details: details.into(), kind: ProcessingErrorKind::Retryable, source }
+    }
+
+    /// An error that is not worth retrying.
+    pub fn permanent(
+        plugin_name: &'static str,
+        details: impl Into<String>,
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> Self {
+        Self { plugin_name, details: details.into(), kind: ProcessingErrorKind::Permanent, source }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.kind == ProcessingErrorKind::Retryable
     }
 }
@@ -0,0 +1,520 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::{HeartbeatMonitorConfig, LivenessNotifierConfig},
+    event::{Event, EventType, SourceId},
+    http_client::HttpClient,
+    metrics::{SOURCE_LIVENESS_TRANSITIONS_TOTAL, SOURCES_DOWN},
+    tenant::{SourceKey, TenantId},
+};
+
+/// Weight of the newest observed gap when learning a source's heartbeat
+/// interval.
+const LEARNING_RATE: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LivenessStatus {
+    Up,
+    Down,
+}
+
+impl LivenessStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LivenessStatus::Up => "up",
+            LivenessStatus::Down => "down",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SourceLiveness {
+    pub last_heartbeat: DateTime<Utc>,
+    /// Exponentially weighted average of observed heartbeat gaps (seconds)
+    pub learned_interval: Option<f64>,
+    pub status: LivenessStatus,
+    pub down_since: Option<DateTime<Utc>>,
+}
+
+/// A source changing from up to down or back.
+#[derive(Debug, Clone, Serialize)]
+pub struct LivenessTransition {
+    pub tenant: TenantId,
+    pub source_id: SourceId,
+    pub status: LivenessStatus,
+    pub last_heartbeat: DateTime<Utc>,
+    /// Expected heartbeat interval (seconds) used for the decision
+    pub expected_interval: Option<f64>,
+    pub at: DateTime<Utc>,
+}
+
+/// A source currently marked as down.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownSource {
+    pub tenant: TenantId,
+    pub source_id: SourceId,
+    pub last_heartbeat: DateTime<Utc>,
+    pub down_since: Option<DateTime<Utc>>,
+    pub expected_interval: Option<f64>,
+}
+
+/// Keeps the last heartbeat and liveness status of every source.
+#[derive(Debug)]
+pub struct LivenessTracker {
+    sources: DashMap<SourceKey, SourceLiveness>,
+    configured_intervals: HashMap<SourceKey, f64>,
+    default_interval: Option<f64>,
+    missed_intervals: u32,
+}
+
+impl LivenessTracker {
+    pub fn new(config: &HeartbeatMonitorConfig) -> Self {
+        Self {
+            sources: DashMap::new(),
+            configured_intervals: config
+                .sources
+                .iter()
+                .map(|source| {
+                    (
+                        SourceKey::new(source.tenant.clone(), source.id.clone()),
+                        source.interval as f64,
+                    )
+                })
+                .collect(),
+            default_interval: config.default_interval.map(|interval| interval as f64),
+            missed_intervals: config.missed_intervals.max(1),
+        }
+    }
+
+    /// Expected interval (seconds): configured, then learned, then default.
+    fn expected_interval(&self, key: &SourceKey, liveness: &SourceLiveness) -> Option<f64> {
+        self.configured_intervals
+            .get(key)
+            .copied()
+            .or(liveness.learned_interval)
+            .or(self.default_interval)
+    }
+
+    /// Records a heartbeat received at `at`, returning a transition if the
+    /// source came back up. Heartbeats not newer than the last one are ignored,
+    /// so a delayed heartbeat can't bring a down source back up.
+    pub fn record_heartbeat(
+        &self,
+        key: &SourceKey,
+        at: DateTime<Utc>,
+    ) -> Option<LivenessTransition> {
+        let mut entry = self.sources.entry(key.clone()).or_insert_with(|| SourceLiveness {
+            last_heartbeat: at,
+            learned_interval: None,
+            status: LivenessStatus::Up,
+            down_since: None,
+        });
+        let expected_interval = self.expected_interval(key, entry.value());
+        let liveness = entry.value_mut();
+
+        let gap = (at - liveness.last_heartbeat).num_milliseconds() as f64 / 1000.0;
+        if gap <= 0.0 {
+            return None;
+        }
+        // An outage is not an interval, learning it would detect the next
+        // outage later
+        let outage = liveness.status == LivenessStatus::Down
+            || expected_interval
+                .is_some_and(|interval| gap > interval * self.missed_intervals as f64);
+        if !outage {
+            liveness.learned_interval = Some(match liveness.learned_interval {
+                Some(interval) => interval + LEARNING_RATE * (gap - interval),
+                None => gap,
+            });
+        }
+        liveness.last_heartbeat = at;
+
+        if liveness.status == LivenessStatus::Down {
+            liveness.status = LivenessStatus::Up;
+            liveness.down_since = None;
+            let expected_interval = self.expected_interval(key, liveness);
+            return Some(LivenessTransition {
+                tenant: key.tenant.clone(),
+                source_id: key.source_id.clone(),
+                status: LivenessStatus::Up,
+                last_heartbeat: liveness.last_heartbeat,
+                expected_interval,
+                at: Utc::now(),
+            });
+        }
+
+        None
+    }
+
+    /// Marks sources that missed too many heartbeats as down.
+    pub fn sweep(&self, now: DateTime<Utc>) -> Vec<LivenessTransition> {
+        let mut transitions = Vec::new();
+
+        for mut entry in self.sources.iter_mut() {
+            let key = entry.key().clone();
+            let Some(expected_interval) = self.expected_interval(&key, entry.value()) else {
+                continue;
+            };
+            let liveness = entry.value_mut();
+            if liveness.status == LivenessStatus::Down {
+                continue;
+            }
+
+            let silence = (now - liveness.last_heartbeat).num_milliseconds() as f64 / 1000.0;
+            if silence > expected_interval * self.missed_intervals as f64 {
+                liveness.status = LivenessStatus::Down;
+                liveness.down_since = Some(now);
+                transitions.push(LivenessTransition {
+                    tenant: key.tenant,
+                    source_id: key.source_id,
+                    status: LivenessStatus::Down,
+                    last_heartbeat: liveness.last_heartbeat,
+                    expected_interval: Some(expected_interval),
+                    at: now,
+                });
+            }
+        }
+
+        transitions
+    }
+
+    /// Returns all sources currently marked as down, optionally only those of
+    /// one tenant.
+    pub fn down_sources(&self, tenant: Option<&TenantId>) -> Vec<DownSource> {
+        let mut down: Vec<DownSource> = self
+            .sources
+            .iter()
+            .filter(|entry| entry.value().status == LivenessStatus::Down)
+            .filter(|entry| tenant.is_none_or(|tenant| &entry.key().tenant == tenant))
+            .map(|entry| DownSource {
+                tenant: entry.key().tenant.clone(),
+                source_id: entry.key().source_id.clone(),
+                last_heartbeat: entry.value().last_heartbeat,
+                down_since: entry.value().down_since,
+                expected_interval: self.expected_interval(entry.key(), entry.value()),
+            })
+            .collect();
+        down.sort_by(|a, b| (&a.tenant, &a.source_id).cmp(&(&b.tenant, &b.source_id)));
+        down
+    }
+
+    fn update_gauge(&self) {
+        let down = self
+            .sources
+            .iter()
+            .filter(|entry| entry.value().status == LivenessStatus::Down)
+            .count();
+        metrics::gauge!(SOURCES_DOWN).set(down as f64);
+    }
+}
+
+/// Receives source up/down transitions.
+#[async_trait::async_trait]
+pub trait LivenessNotifier: Send + Sync + Debug {
+    async fn notify(&self, transition: &LivenessTransition);
+}
+
+/// Logs transitions.
+#[derive(Debug, Default)]
+pub struct LogNotifier;
+
+#[async_trait::async_trait]
+impl LivenessNotifier for LogNotifier {
+    async fn notify(&self, transition: &LivenessTransition) {
+        match transition.status {
+            LivenessStatus::Down => tracing::warn!(
+                tenant = %transition.tenant,
+                source_id = %transition.source_id,
+                last_heartbeat = %transition.last_heartbeat,
+                "Source is down"
+            ),
+            LivenessStatus::Up => tracing::info!(
+                tenant = %transition.tenant,
+                source_id = %transition.source_id,
+                "Source is up"
+            ),
+        }
+    }
+}
+
+/// Posts transitions as JSON to a webhook URL.
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    url: String,
+    client: HttpClient,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String, timeout: Duration) -> Self {
+        Self { url, client: HttpClient::new(timeout) }
+    }
+}
+
+#[async_trait::async_trait]
+impl LivenessNotifier for WebhookNotifier {
+    async fn notify(&self, transition: &LivenessTransition) {
+        if let Err(err) = self.client.post_json(&self.url, transition).await {
+            tracing::error!(
+                source_id = %transition.source_id,
+                url = %self.url,
+                "Failed to send liveness notification: {}",
+                err
+            );
+        }
+    }
+}
+
+/// Tracks heartbeats per source and detects sources that went quiet. It is fed
+/// on ingestion, before the transformers, so it sees every heartbeat.
+#[derive(Debug)]
+pub struct HeartbeatMonitor {
+    tracker: LivenessTracker,
+    notifier: Arc<dyn LivenessNotifier>,
+    check_interval: Duration,
+    transitions_tx: mpsc::UnboundedSender<LivenessTransition>,
+    transitions_rx: Mutex<Option<mpsc::UnboundedReceiver<LivenessTransition>>>,
+}
+
+impl HeartbeatMonitor {
+    pub fn new(config: &HeartbeatMonitorConfig) -> Self {
+        let notifier: Arc<dyn LivenessNotifier> = match &config.notifier {
+            LivenessNotifierConfig::Log => Arc::new(LogNotifier),
+            LivenessNotifierConfig::Webhook { url, timeout } => {
+                Arc::new(WebhookNotifier::new(url.clone(), Duration::from_millis(*timeout)))
+            }
+        };
+        let (transitions_tx, transitions_rx) = mpsc::unbounded_channel();
+
+        Self {
+            tracker: LivenessTracker::new(config),
+            notifier,
+            check_interval: Duration::from_secs(config.check_interval.max(1)),
+            transitions_tx,
+            transitions_rx: Mutex::new(Some(transitions_rx)),
+        }
+    }
+
+    /// Records the event if it is a heartbeat. Liveness is decided by the
+    /// receive time, the event timestamp is set by the source's clock.
+    pub fn record(&self, event: &Event, received_at: DateTime<Utc>) {
+        if event.r#type != EventType::Heartbeat {
+            return;
+        }
+        if let Some(transition) = self.tracker.record_heartbeat(&event.source_key(), received_at) {
+            self.report(transition);
+            self.tracker.update_gauge();
+        }
+    }
+
+    /// Returns the sources currently marked as down, optionally only those of
+    /// one tenant.
+    pub fn down_sources(&self, tenant: Option<&TenantId>) -> Vec<DownSource> {
+        self.tracker.down_sources(tenant)
+    }
+
+    fn report(&self, transition: LivenessTransition) {
+        if !report_transition(&self.transitions_tx, transition) {
+            tracing::warn!("Liveness notifier is not running, dropping transition");
+        }
+    }
+
+    /// Starts delivering notifications and the periodic check for sources
+    /// that went quiet.
+    pub fn start(self: &Arc<Self>) {
+        let Some(mut transitions_rx) = self.transitions_rx.lock().ok().and_then(|mut rx| rx.take())
+        else {
+            tracing::warn!("HeartbeatMonitor already started");
+            return;
+        };
+
+        // Deliver notifications in order, without blocking ingestion
+        let notifier = self.notifier.clone();
+        tokio::spawn(async move {
+            while let Some(transition) = transitions_rx.recv().await {
+                notifier.notify(&transition).await;
+            }
+        });
+
+        // Periodically look for sources that went quiet
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(monitor.check_interval);
+            loop {
+                ticker.tick().await;
+                for transition in monitor.tracker.sweep(Utc::now()) {
+                    if !report_transition(&monitor.transitions_tx, transition) {
+                        return;
+                    }
+                }
+                monitor.tracker.update_gauge();
+            }
+        });
+    }
+}
+
+/// Counts the transition and queues it for the notifier. Returns false if the
+/// notifier is gone.
+fn report_transition(
+    transitions_tx: &mpsc::UnboundedSender<LivenessTransition>,
+    transition: LivenessTransition,
+) -> bool {
+    metrics::counter!(SOURCE_LIVENESS_TRANSITIONS_TOTAL, "status" => transition.status.as_str())
+        .increment(1);
+    transitions_tx.send(transition).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration as ChronoDuration;
+
+    use super::*;
+    use crate::config::HeartbeatSourceConfig;
+
+    fn key(source_id: u64) -> SourceKey {
+        SourceKey::new(Default::default(), source_id.into())
+    }
+
+    fn create_config(default_interval: Option<u64>) -> HeartbeatMonitorConfig {
+        HeartbeatMonitorConfig { default_interval, ..Default::default() }
+    }
+
+    #[test]
+    fn test_marks_source_down_after_missed_intervals() {
+        let tracker = LivenessTracker::new(&create_config(Some(10)));
+        let now = Utc::now();
+        tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(25));
+
+        // 25s of silence with 3 x 10s allowed
+        assert!(tracker.sweep(now).is_empty());
+
+        let transitions = tracker.sweep(now + ChronoDuration::seconds(10));
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].status, LivenessStatus::Down);
+        assert_eq!(tracker.down_sources(None).len(), 1);
+
+        // Already down, no repeated transition
+        assert!(tracker.sweep(now + ChronoDuration::seconds(20)).is_empty());
+    }
+
+    #[test]
+    fn test_heartbeat_brings_source_back_up() {
+        let tracker = LivenessTracker::new(&create_config(Some(10)));
+        let now = Utc::now();
+        tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(60));
+        assert_eq!(tracker.sweep(now).len(), 1);
+
+        let transition = tracker.record_heartbeat(&key(1), now);
+        assert_eq!(transition.map(|t| t.status), Some(LivenessStatus::Up));
+        assert!(tracker.down_sources(None).is_empty());
+    }
+
+    #[test]
+    fn test_learns_interval() {
+        let tracker = LivenessTracker::new(&create_config(None));
+        let now = Utc::now();
+        tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(20));
+
+        // No interval known yet, the source can't be judged
+        assert!(tracker.sweep(now + ChronoDuration::hours(1)).is_empty());
+
+        tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(10));
+        tracker.record_heartbeat(&key(1), now);
+
+        assert!(tracker.sweep(now + ChronoDuration::seconds(29)).is_empty());
+        assert_eq!(tracker.sweep(now + ChronoDuration::seconds(31)).len(), 1);
+    }
+
+    #[test]
+    fn test_does_not_learn_outages() {
+        let tracker = LivenessTracker::new(&create_config(None));
+        let now = Utc::now();
+        for seconds in [40, 30, 20, 10, 0] {
+            tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(seconds));
+        }
+        assert_eq!(tracker.sweep(now + ChronoDuration::seconds(31)).len(), 1);
+
+        // Back up after an hour, the interval is still 10s
+        let back = now + ChronoDuration::hours(1);
+        assert!(tracker.record_heartbeat(&key(1), back).is_some());
+        assert_eq!(tracker.sweep(back + ChronoDuration::seconds(31)).len(), 1);
+
+        // A long gap while up is an outage the sweep missed
+        let later = back + ChronoDuration::hours(1);
+        tracker.record_heartbeat(&key(1), later);
+        tracker.record_heartbeat(&key(1), later + ChronoDuration::hours(1));
+        assert_eq!(tracker.sweep(later + ChronoDuration::seconds(3631)).len(), 1);
+    }
+
+    #[test]
+    fn test_configured_interval_takes_precedence() {
+        let config = HeartbeatMonitorConfig {
+            default_interval: Some(1000),
+            sources: vec![HeartbeatSourceConfig {
+                id: 1.into(),
+                tenant: Default::default(),
+                interval: 1,
+            }],
+            ..Default::default()
+        };
+        let tracker = LivenessTracker::new(&config);
+        let now = Utc::now();
+        tracker.record_heartbeat(&key(1), now);
+        tracker.record_heartbeat(&key(2), now);
+
+        let transitions = tracker.sweep(now + ChronoDuration::seconds(5));
+        assert_eq!(transitions.len(), 1);
+        assert_eq!(transitions[0].source_id, 1.into());
+    }
+
+    #[test]
+    fn test_ignores_heartbeats_not_newer_than_last() {
+        let tracker = LivenessTracker::new(&create_config(Some(10)));
+        let now = Utc::now();
+        tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(100));
+        tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(90));
+        assert_eq!(tracker.sweep(now).len(), 1);
+
+        // A delayed heartbeat doesn't bring the source back up
+        assert!(tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(95)).is_none());
+        assert!(tracker.record_heartbeat(&key(1), now - ChronoDuration::seconds(90)).is_none());
+        let down = tracker.down_sources(None);
+        assert_eq!(down.len(), 1);
+        assert_eq!(down[0].last_heartbeat, now - ChronoDuration::seconds(90));
+        assert!(tracker.sweep(now).is_empty());
+    }
+
+    #[test]
+    fn test_records_heartbeats_by_receive_time() {
+        let monitor = HeartbeatMonitor::new(&create_config(Some(10)));
+        let now = Utc::now();
+        let event = |r#type| Event {
+            source_id: 1.into(),
+            r#type,
+            // The source's clock is a day behind
+            timestamp: now - ChronoDuration::days(1),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
+
+        monitor.record(&event(EventType::Custom("Login".to_string())), now);
+        assert!(monitor.tracker.sources.is_empty());
+
+        monitor.record(&event(EventType::Heartbeat), now);
+        assert!(monitor.tracker.sweep(now + ChronoDuration::seconds(5)).is_empty());
+        assert_eq!(monitor.tracker.sweep(now + ChronoDuration::seconds(31)).len(), 1);
+    }
+}
@@ -1,11 +1,25 @@
 pub mod error;
+pub mod heartbeat;
 pub mod source_telemetry;
 pub mod storage;
+pub mod time_window;
 
 use error::ProcessingError;
 
 use crate::{common_types::TelemetryMap, event::Event};
 
+/// Outcome of processing a batch of events.
+#[derive(Debug)]
+pub enum BatchOutcome {
+    /// All events were processed
+    Processed,
+    /// No event was processed
+    Failed(ProcessingError),
+    /// Only the events at these indices of the batch failed, each with its
+    /// error. The other events were processed.
+    PartiallyFailed(Vec<(usize, ProcessingError)>),
+}
+
 #[async_trait::async_trait]
 pub trait EventProcessor: Send + Sync {
     /// Process an event.
@@ -15,6 +29,23 @@ pub trait EventProcessor: Send + Sync {
         events: &[Event],
     ) -> Result<(), ProcessingError>;
 
+    /// Process a batch, reporting which events failed, so only those are
+    /// retried and dead-lettered. Defaults to `process_event`, where an error
+    /// fails the whole batch.
+    async fn process_events(&self, telemetry_map: &TelemetryMap, events: &[Event]) -> BatchOutcome {
+        match self.process_event(telemetry_map, events).await {
+            Ok(()) => BatchOutcome::Processed,
+            Err(err) => BatchOutcome::Failed(err),
+        }
+    }
+
     /// Processor name (for logging purposes).
     fn name(&self) -> &'static str;
+
+    /// Whether the processor may see the batches of a source out of order, so
+    /// it can run with a `concurrency` above 1. Processors whose results
+    /// depend on the event order of a source don't.
+    fn supports_concurrency(&self) -> bool {
+        false
+    }
 }
@@ -1,34 +1,298 @@
 use std::collections::HashMap;
 
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 
-use crate::event::{Event, EventType};
+use super::time_window::RollingCounts;
+use crate::{
+    config::StorageProcessorConfig,
+    event::{Event, EventType, Labels, LogLevel, MetricPayload, TypedPayload},
+};
 
+/// Sorted label key/value pairs of an event, used as an aggregation key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LabelSet(Vec<(String, String)>);
+
+impl LabelSet {
+    pub fn new(labels: &Labels) -> Self {
+        let mut pairs: Vec<(String, String)> =
+            labels.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+        pairs.sort();
+        Self(pairs)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+
+    /// Returns true if all `filters` (key, value) pairs are present.
+    pub fn matches(&self, filters: &[(String, String)]) -> bool {
+        filters.iter().all(|(key, value)| self.get(key) == Some(value.as_str()))
+    }
+}
+
+/// Event counts matching a set of label filters, optionally grouped by the
+/// value of one label key.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LabelCounts {
+    pub events_count: u64,
+    /// Counts by value of the group key; events without the key are not grouped
+    pub groups: HashMap<String, u64>,
+}
+
+impl LabelCounts {
+    /// Merges another set of counts into this one.
+    pub fn merge(&mut self, other: &LabelCounts) {
+        self.events_count += other.events_count;
+        for (value, count) in &other.groups {
+            *self.groups.entry(value.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+/// Aggregate of all samples of one metric name.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricAggregate {
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    pub unit: Option<String>,
+}
+
+impl MetricAggregate {
+    fn new(metric: &MetricPayload, weight: u64) -> Self {
+        Self {
+            count: weight,
+            sum: metric.value * weight as f64,
+            min: metric.value,
+            max: metric.value,
+            unit: metric.unit.clone(),
+        }
+    }
+
+    fn update(&mut self, metric: &MetricPayload, weight: u64) {
+        self.count += weight;
+        self.sum += metric.value * weight as f64;
+        self.min = self.min.min(metric.value);
+        self.max = self.max.max(metric.value);
+        if metric.unit.is_some() {
+            self.unit = metric.unit.clone();
+        }
+    }
+}
+
+/// Statistics of a source. Counts of sampled events scale with their sample
+/// weight, so they estimate the ingested totals.
 #[derive(Debug, Clone)]
 pub struct SourceTelemetry {
     pub total_events: u64,
     pub first_timestamp: DateTime<Utc>,
     pub last_timestamp: DateTime<Utc>,
     pub events_by_type: HashMap<EventType, u64>,
+    /// Recent per-type counts, bucketed by event timestamp (future ones now)
+    pub windows: RollingCounts,
+    /// Aggregates of `Metric` events by metric name
+    pub metrics: HashMap<String, MetricAggregate>,
+    /// Counts of `Log` events by level
+    pub logs_by_level: HashMap<LogLevel, u64>,
+    /// Counts of `Metric` events whose name didn't fit in `metrics`
+    pub other_metrics: u64,
+    /// Counts of `Error` events by error code
+    pub errors_by_code: HashMap<String, u64>,
+    /// Counts of `Error` events whose code didn't fit in `errors_by_code`
+    pub other_errors: u64,
+    /// Counts by distinct label set
+    pub events_by_labels: HashMap<LabelSet, u64>,
+    /// Counts of events whose label set didn't fit in `events_by_labels`
+    pub other_label_sets: u64,
 }
 
 impl SourceTelemetry {
-    pub fn new(event: &Event) -> Self {
+    pub fn new(event: &Event, limits: &StorageProcessorConfig) -> Self {
+        let weight = u64::from(event.sample_weight);
         let mut events_by_type = HashMap::new();
-        events_by_type.insert(event.r#type.clone(), 1);
+        events_by_type.insert(event.r#type.clone(), weight);
 
-        Self {
-            total_events: 1,
+        let mut windows = RollingCounts::default();
+        windows.record(&event.r#type, event.timestamp, Utc::now(), weight);
+
+        let mut telemetry = Self {
+            total_events: weight,
             first_timestamp: event.timestamp,
             last_timestamp: event.timestamp,
             events_by_type,
-        }
+            windows,
+            metrics: HashMap::new(),
+            other_metrics: 0,
+            logs_by_level: HashMap::new(),
+            errors_by_code: HashMap::new(),
+            other_errors: 0,
+            events_by_labels: HashMap::from([(LabelSet::new(&event.labels), weight)]),
+            other_label_sets: 0,
+        };
+        telemetry.record_payload(event, limits);
+        telemetry
     }
 
-    pub fn update(&mut self, event: &Event) {
-        self.total_events += 1;
+    pub fn update(&mut self, event: &Event, limits: &StorageProcessorConfig) {
+        let weight = u64::from(event.sample_weight);
+        self.total_events += weight;
         self.first_timestamp = self.first_timestamp.min(event.timestamp);
         self.last_timestamp = self.last_timestamp.max(event.timestamp);
-        *self.events_by_type.entry(event.r#type.clone()).or_insert(0) += 1;
+        *self.events_by_type.entry(event.r#type.clone()).or_insert(0) += weight;
+        self.windows.record(&event.r#type, event.timestamp, Utc::now(), weight);
+        self.record_labels(event, weight, limits);
+        self.record_payload(event, limits);
+    }
+
+    /// Counts events whose labels match all `filters`, grouped by the value of
+    /// the `group_by` label key if given. Events beyond the label set cap are
+    /// only counted without filters, and never grouped.
+    pub fn count_by_labels(
+        &self,
+        filters: &[(String, String)],
+        group_by: Option<&str>,
+    ) -> LabelCounts {
+        let mut counts = LabelCounts::default();
+        if filters.is_empty() {
+            counts.events_count = self.other_label_sets;
+        }
+        for (label_set, count) in
+            self.events_by_labels.iter().filter(|(set, _)| set.matches(filters))
+        {
+            counts.events_count += count;
+            if let Some(value) = group_by.and_then(|key| label_set.get(key)) {
+                *counts.groups.entry(value.to_string()).or_insert(0) += count;
+            }
+        }
+        counts
+    }
+
+    fn record_labels(&mut self, event: &Event, weight: u64, limits: &StorageProcessorConfig) {
+        let label_set = LabelSet::new(&event.labels);
+        if let Some(count) = self.events_by_labels.get_mut(&label_set) {
+            *count += weight;
+        } else if self.events_by_labels.len() < limits.max_label_sets {
+            self.events_by_labels.insert(label_set, weight);
+        } else {
+            self.other_label_sets += weight;
+        }
+    }
+
+    /// Updates the type-aware aggregates of built-in event types.
+    fn record_payload(&mut self, event: &Event, limits: &StorageProcessorConfig) {
+        let weight = u64::from(event.sample_weight);
+        match &event.payload {
+            Some(TypedPayload::Metric(metric)) => {
+                if let Some(aggregate) = self.metrics.get_mut(&metric.name) {
+                    aggregate.update(metric, weight);
+                } else if self.metrics.len() < limits.max_metric_names {
+                    self.metrics.insert(metric.name.clone(), MetricAggregate::new(metric, weight));
+                } else {
+                    self.other_metrics += weight;
+                }
+            }
+            Some(TypedPayload::Log(log)) => {
+                *self.logs_by_level.entry(log.level).or_insert(0) += weight;
+            }
+            Some(TypedPayload::Error(error)) => {
+                if let Some(count) = self.errors_by_code.get_mut(&error.code) {
+                    *count += weight;
+                } else if self.errors_by_code.len() < limits.max_error_codes {
+                    self.errors_by_code.insert(error.code.clone(), weight);
+                } else {
+                    self.other_errors += weight;
+                }
+            }
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_event(labels: &[(&str, &str)]) -> Event {
+        Event {
+            source_id: 1.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    fn limits() -> StorageProcessorConfig {
+        StorageProcessorConfig { max_error_codes: 2, max_label_sets: 3, ..Default::default() }
+    }
+
+    #[test]
+    fn test_counts_by_labels() {
+        let mut telemetry =
+            SourceTelemetry::new(&create_event(&[("region", "eu"), ("fw", "1.0")]), &limits());
+        telemetry.update(&create_event(&[("region", "eu"), ("fw", "2.0")]), &limits());
+        telemetry.update(&create_event(&[("region", "us"), ("fw", "2.0")]), &limits());
+        telemetry.update(&create_event(&[]), &limits());
+
+        let all = telemetry.count_by_labels(&[], Some("region"));
+        assert_eq!(all.events_count, 4);
+        assert_eq!(all.groups, HashMap::from([("eu".to_string(), 2), ("us".to_string(), 1)]));
+
+        let filtered =
+            telemetry.count_by_labels(&[("fw".to_string(), "2.0".to_string())], Some("region"));
+        assert_eq!(filtered.events_count, 2);
+        assert_eq!(filtered.groups, HashMap::from([("eu".to_string(), 1), ("us".to_string(), 1)]));
+    }
+
+    #[test]
+    fn test_scales_counts_by_sample_weight() {
+        let mut sampled = create_event(&[("region", "eu")]);
+        sampled.sample_weight = 100;
+        let mut telemetry = SourceTelemetry::new(&sampled, &limits());
+        telemetry.update(&create_event(&[]), &limits());
+
+        assert_eq!(telemetry.total_events, 101);
+        assert_eq!(telemetry.events_by_type.get(&EventType::Heartbeat), Some(&101));
+        assert_eq!(telemetry.count_by_labels(&[], Some("region")).groups.get("eu"), Some(&100));
+    }
+
+    #[test]
+    fn test_caps_error_codes() {
+        let mut telemetry = SourceTelemetry::new(&create_event(&[]), &limits());
+        for i in 0..=limits().max_error_codes {
+            let mut event = create_event(&[]);
+            event.r#type = EventType::Error;
+            event.data =
+                Some(serde_json::json!({ "code": format!("E{}", i), "message": "failed" }));
+            let _ = event.parse_payload();
+            telemetry.update(&event, &limits());
+            telemetry.update(&event, &limits());
+        }
+
+        assert_eq!(telemetry.errors_by_code.len(), limits().max_error_codes);
+        assert_eq!(telemetry.errors_by_code.get("E0"), Some(&2));
+        assert_eq!(telemetry.other_errors, 2);
+    }
+
+    #[test]
+    fn test_caps_label_sets() {
+        let mut telemetry = SourceTelemetry::new(&create_event(&[("region", "eu")]), &limits());
+        for i in 0..limits().max_label_sets {
+            telemetry.update(&create_event(&[("request", &i.to_string())]), &limits());
+        }
+
+        assert_eq!(telemetry.events_by_labels.len(), limits().max_label_sets);
+        assert_eq!(telemetry.other_label_sets, 1);
+        assert_eq!(
+            telemetry.count_by_labels(&[], None).events_count,
+            limits().max_label_sets as u64 + 1
+        );
+        let filtered = telemetry.count_by_labels(&[("region".to_string(), "eu".to_string())], None);
+        assert_eq!(filtered.events_count, 1);
     }
 }
@@ -1,18 +1,20 @@
-use super::{EventProcessor, ProcessingError};
+use super::{BatchOutcome, EventProcessor, ProcessingError};
 use crate::{
     common_types::TelemetryMap,
-    config::NoParamsValidationConfig,
+    config::StorageProcessorConfig,
     event::Event,
     plugins::{PluginError, ProcessingPluginFactory},
     processing::source_telemetry::SourceTelemetry,
 };
 
 #[derive(Debug, Default)]
-pub struct StorageProcessor;
+pub struct StorageProcessor {
+    config: StorageProcessorConfig,
+}
 
 impl StorageProcessor {
-    pub fn new(_config: NoParamsValidationConfig) -> Self {
-        Self
+    pub fn new(config: StorageProcessorConfig) -> Self {
+        Self { config }
     }
 }
 
@@ -23,37 +25,61 @@ impl EventProcessor for StorageProcessor {
         telemetry_map: &TelemetryMap,
         events: &[Event],
     ) -> Result<(), ProcessingError> {
+        // The valid events are stored, the error is the first failure
+        match self.process_events(telemetry_map, events).await {
+            BatchOutcome::Processed => Ok(()),
+            BatchOutcome::Failed(err) => Err(err),
+            BatchOutcome::PartiallyFailed(mut failed) => Err(failed.remove(0).1),
+        }
+    }
+
+    /// Stores the events of the batch. Events of built-in types without a
+    /// typed payload fail on their own, e.g. events persisted by a version
+    /// with a different schema and replayed from the WAL.
+    async fn process_events(&self, telemetry_map: &TelemetryMap, events: &[Event]) -> BatchOutcome {
         tracing::debug!("Processing batch of events: {:?}", events.len());
 
-        // iterate over each group and store the events
-        for event in events {
-            if events.is_empty() {
+        let mut failed = Vec::new();
+        for (index, event) in events.iter().enumerate() {
+            if event.r#type.has_payload() && event.payload.is_none() {
+                let details = format!("Invalid {} payload", event.r#type);
+                failed.push((index, ProcessingError::permanent(self.name(), details, None)));
                 continue;
             }
-            let source_entry = telemetry_map.entry(event.source_id);
+            let source_entry = telemetry_map.entry(event.source_key());
 
             source_entry
                 // if the telemetry already exists, update it
                 .and_modify(|telemetry| {
                     tracing::debug!("Updating telemetry for source_id: {}", event.source_id);
-                    telemetry.update(event)
+                    telemetry.update(event, &self.config)
                 })
                 // if the telemetry does not exist, create it
                 .or_insert_with(|| {
                     tracing::debug!("Creating new telemetry for source_id: {}", event.source_id);
 
-                    SourceTelemetry::new(event)
+                    SourceTelemetry::new(event, &self.config)
                 });
         }
 
         tracing::debug!("Finish processing event batch");
 
-        Ok(())
+        if failed.is_empty() {
+            BatchOutcome::Processed
+        } else {
+            BatchOutcome::PartiallyFailed(failed)
+        }
     }
 
     fn name(&self) -> &'static str {
         "StorageProcessor"
     }
+
+    // Counts and aggregates don't depend on the order, and the last timestamp
+    // is the newest one seen
+    fn supports_concurrency(&self) -> bool {
+        true
+    }
 }
 
 /// Constructs a StorageProcessor plugin.
@@ -62,9 +88,7 @@ impl EventProcessor for StorageProcessor {
 fn construct_storage_processor(
     config_params: toml::Value,
 ) -> Result<Box<dyn EventProcessor + Send + Sync>, PluginError> {
-    // StorageProcessor does not require any parameters, but keep deserialization
-    // for consistency with other plugins
-    let config: NoParamsValidationConfig =
+    let config: StorageProcessorConfig =
         config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
             plugin_name: "StorageProcessor".to_string(),
             source: e,
@@ -90,7 +114,8 @@ mod tests {
     use super::*;
     use crate::{
         common_types::TelemetryMap,
-        event::{Event, EventType},
+        event::{Event, EventType, LogLevel},
+        tenant::SourceKey,
     };
 
     /// Creates a new telemetry map for testing purposes.
@@ -98,14 +123,28 @@ mod tests {
         Arc::new(DashMap::new())
     }
 
+    /// Creates a telemetry map key of the default tenant for testing purposes.
+    fn key(source_id: u64) -> SourceKey {
+        SourceKey::new(Default::default(), source_id.into())
+    }
+
     /// Creates a new event for testing purposes.
     fn create_event(source_id: u64, event_type: EventType) -> Event {
-        Event { source_id, r#type: event_type, timestamp: Utc::now(), data: None }
+        Event {
+            source_id: source_id.into(),
+            r#type: event_type,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
     }
 
     #[tokio::test]
     async fn test_empty_batch() {
-        let processor = StorageProcessor::new(NoParamsValidationConfig::default());
+        let processor = StorageProcessor::new(StorageProcessorConfig::default());
         let telemetry_map = create_map();
         let events: Vec<Event> = vec![];
 
@@ -118,7 +157,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_single_event() {
-        let processor = StorageProcessor::new(NoParamsValidationConfig::default());
+        let processor = StorageProcessor::new(StorageProcessorConfig::default());
         let telemetry_map = create_map();
         let events = vec![create_event(1, EventType::Heartbeat)];
 
@@ -127,12 +166,12 @@ mod tests {
 
         assert!(result.is_ok());
         assert_eq!(telemetry_map.len(), 1);
-        assert!(telemetry_map.contains_key(&1));
+        assert!(telemetry_map.contains_key(&key(1)));
     }
 
     #[tokio::test]
     async fn test_multiple_events() {
-        let processor = StorageProcessor::new(NoParamsValidationConfig::default());
+        let processor = StorageProcessor::new(StorageProcessorConfig::default());
         let telemetry_map = create_map();
         let events = vec![
             create_event(1, EventType::Heartbeat),
@@ -145,13 +184,13 @@ mod tests {
 
         assert!(result.is_ok());
         assert_eq!(telemetry_map.len(), 2);
-        assert!(telemetry_map.contains_key(&1));
-        assert!(telemetry_map.contains_key(&2));
+        assert!(telemetry_map.contains_key(&key(1)));
+        assert!(telemetry_map.contains_key(&key(2)));
     }
 
     #[tokio::test]
     async fn test_update_same_source_id() {
-        let processor = StorageProcessor::new(NoParamsValidationConfig::default());
+        let processor = StorageProcessor::new(StorageProcessorConfig::default());
         let telemetry_map = create_map();
         let events = vec![
             create_event(1, EventType::Heartbeat),
@@ -163,9 +202,9 @@ mod tests {
 
         assert!(result.is_ok());
         assert_eq!(telemetry_map.len(), 1);
-        assert!(telemetry_map.contains_key(&1));
+        assert!(telemetry_map.contains_key(&key(1)));
 
-        let entry = telemetry_map.get(&1);
+        let entry = telemetry_map.get(&key(1));
 
         assert!(entry.is_some());
 
@@ -178,4 +217,65 @@ mod tests {
             None => panic!("Telemetry entry should exist"),
         }
     }
+
+    #[tokio::test]
+    async fn test_typed_event_aggregates() {
+        let processor = StorageProcessor::new(StorageProcessorConfig::default());
+        let telemetry_map = create_map();
+        let metric = |value: f64| Event {
+            data: Some(serde_json::json!({ "name": "cpu", "value": value, "unit": "%" })),
+            ..create_event(1, EventType::Metric)
+        };
+        let mut events = vec![
+            metric(10.0),
+            metric(30.0),
+            Event {
+                data: Some(serde_json::json!({ "level": "warn", "message": "disk low" })),
+                ..create_event(1, EventType::Log)
+            },
+            Event {
+                data: Some(serde_json::json!({ "code": "E42", "message": "boom" })),
+                ..create_event(1, EventType::Error)
+            },
+        ];
+        for event in &mut events {
+            assert!(event.parse_payload().is_ok());
+        }
+
+        let result = processor.process_event(&telemetry_map, &events).await;
+
+        assert!(result.is_ok());
+        let Some(telemetry) = telemetry_map.get(&key(1)) else {
+            panic!("Telemetry entry should exist");
+        };
+        let cpu = telemetry.metrics.get("cpu");
+        assert_eq!(cpu.map(|m| (m.count, m.sum, m.min, m.max)), Some((2, 40.0, 10.0, 30.0)));
+        assert_eq!(telemetry.logs_by_level.get(&LogLevel::Warn), Some(&1));
+        assert_eq!(telemetry.errors_by_code.get("E42"), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn test_fails_events_with_invalid_payload() {
+        let processor = StorageProcessor::new(StorageProcessorConfig::default());
+        let telemetry_map = create_map();
+        let events = vec![
+            create_event(1, EventType::Heartbeat),
+            // Loaded from the WAL without the payload check
+            Event {
+                data: Some(serde_json::json!({ "level": "warn" })),
+                ..create_event(2, EventType::Log)
+            },
+        ];
+
+        let outcome = processor.process_events(&telemetry_map, &events).await;
+
+        let BatchOutcome::PartiallyFailed(failed) = outcome else {
+            panic!("only the invalid event should fail");
+        };
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, 1);
+        assert!(!failed[0].1.is_retryable());
+        assert!(telemetry_map.get(&key(1)).is_some());
+        assert!(telemetry_map.get(&key(2)).is_none());
+    }
 }
@@ -0,0 +1,289 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::event::EventType;
+
+/// Number of one-minute buckets kept per source (covers the last hour).
+const MINUTE_BUCKETS: usize = 60;
+/// Number of one-hour buckets kept per source (covers the last day).
+const HOUR_BUCKETS: usize = 24;
+
+/// Max distinct event types counted per bucket, events of further types are
+/// only counted in `other_types`.
+const MAX_TYPES_PER_BUCKET: usize = 32;
+
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_HOUR: i64 = 3600;
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid stats window '{0}': expected a duration like 1m, 5m, 1h or 24h (max 24h)")]
+pub struct ParseStatsWindowError(String);
+
+/// A look-back window for rolling statistics.
+/// Windows up to one hour have minute resolution, longer windows are rounded
+/// up to whole hours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsWindow {
+    minutes: u32,
+}
+
+impl StatsWindow {
+    /// Longest supported window (24 hours).
+    pub const MAX_MINUTES: u32 = (HOUR_BUCKETS * 60) as u32;
+
+    pub fn from_minutes(minutes: u32) -> Result<Self, ParseStatsWindowError> {
+        if minutes == 0 || minutes > Self::MAX_MINUTES {
+            return Err(ParseStatsWindowError(format!("{}m", minutes)));
+        }
+        Ok(Self { minutes })
+    }
+
+    pub fn minutes(&self) -> u32 {
+        self.minutes
+    }
+}
+
+impl FromStr for StatsWindow {
+    type Err = ParseStatsWindowError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseStatsWindowError(s.to_string());
+        let (value, multiplier) = match s.strip_suffix('m') {
+            Some(value) => (value, 1),
+            None => (s.strip_suffix('h').ok_or_else(err)?, 60),
+        };
+        let value: u32 = value.parse().map_err(|_| err())?;
+        Self::from_minutes(value.checked_mul(multiplier).ok_or_else(err)?).map_err(|_| err())
+    }
+}
+
+impl Display for StatsWindow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.minutes.is_multiple_of(60) {
+            write!(f, "{}h", self.minutes / 60)
+        } else {
+            write!(f, "{}m", self.minutes)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StatsWindow {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Event counts observed within a [`StatsWindow`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WindowCounts {
+    pub total_events: u64,
+    pub event_types: HashMap<EventType, u64>,
+    /// Events whose type didn't fit in `event_types` of their bucket
+    pub other_types: u64,
+}
+
+impl WindowCounts {
+    /// Merges another set of counts into this one.
+    pub fn merge(&mut self, other: &WindowCounts) {
+        self.total_events += other.total_events;
+        self.other_types += other.other_types;
+        for (event_type, count) in &other.event_types {
+            *self.event_types.entry(event_type.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+/// A single time bucket. `start` is the bucket index since the Unix epoch
+/// (in minutes or hours depending on the ring it belongs to).
+#[derive(Debug, Clone, Default)]
+struct Bucket {
+    start: i64,
+    counts: WindowCounts,
+}
+
+/// Ring of fixed-size time buckets.
+#[derive(Debug, Clone)]
+struct BucketRing {
+    buckets: Vec<Bucket>,
+    bucket_seconds: i64,
+}
+
+impl BucketRing {
+    fn new(len: usize, bucket_seconds: i64) -> Self {
+        Self {
+            buckets: vec![Bucket { start: i64::MIN, ..Default::default() }; len],
+            bucket_seconds,
+        }
+    }
+
+    fn record(&mut self, event_type: &EventType, at: DateTime<Utc>, count: u64) {
+        let start = at.timestamp().div_euclid(self.bucket_seconds);
+        let idx = start.rem_euclid(self.buckets.len() as i64) as usize;
+        let bucket = &mut self.buckets[idx];
+
+        if bucket.start > start {
+            // The slot was already reused by a newer bucket, the event is too old to be
+            // counted
+            return;
+        }
+        if bucket.start < start {
+            *bucket = Bucket { start, counts: WindowCounts::default() };
+        }
+
+        let counts = &mut bucket.counts;
+        counts.total_events += count;
+        if let Some(total) = counts.event_types.get_mut(event_type) {
+            *total += count;
+        } else if counts.event_types.len() < MAX_TYPES_PER_BUCKET {
+            counts.event_types.insert(event_type.clone(), count);
+        } else {
+            counts.other_types += count;
+        }
+    }
+
+    /// Sums the `len` most recent buckets ending at `now`.
+    fn sum(&self, now: DateTime<Utc>, len: i64) -> WindowCounts {
+        let current = now.timestamp().div_euclid(self.bucket_seconds);
+        let mut counts = WindowCounts::default();
+        for bucket in self.buckets.iter().filter(|b| b.start > current - len && b.start <= current)
+        {
+            counts.merge(&bucket.counts);
+        }
+        counts
+    }
+}
+
+/// Rolling per-type event counts for one source.
+/// Memory is bounded by the number of buckets: a minute ring covering the last
+/// hour and an hour ring covering the last day.
+#[derive(Debug, Clone)]
+pub struct RollingCounts {
+    minutes: BucketRing,
+    hours: BucketRing,
+}
+
+impl Default for RollingCounts {
+    fn default() -> Self {
+        Self {
+            minutes: BucketRing::new(MINUTE_BUCKETS, SECONDS_PER_MINUTE),
+            hours: BucketRing::new(HOUR_BUCKETS, SECONDS_PER_HOUR),
+        }
+    }
+}
+
+impl RollingCounts {
+    /// Records `count` events of the given type at the given time. Events
+    /// dated after `now`, by a source clock running ahead, are counted in the
+    /// current bucket, so they can't take over the slots of older buckets
+    /// still in the window.
+    pub fn record(
+        &mut self,
+        event_type: &EventType,
+        at: DateTime<Utc>,
+        now: DateTime<Utc>,
+        count: u64,
+    ) {
+        let at = at.min(now);
+        self.minutes.record(event_type, at, count);
+        self.hours.record(event_type, at, count);
+    }
+
+    /// Returns the counts within `window` ending at `now`.
+    pub fn window(&self, window: StatsWindow, now: DateTime<Utc>) -> WindowCounts {
+        let minutes = window.minutes() as i64;
+        if minutes <= MINUTE_BUCKETS as i64 {
+            self.minutes.sum(now, minutes)
+        } else {
+            self.hours.sum(now, (minutes + 59) / 60)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_parses_windows() {
+        assert_eq!("5m".parse::<StatsWindow>().map(|w| w.minutes()).ok(), Some(5));
+        assert_eq!("1h".parse::<StatsWindow>().map(|w| w.minutes()).ok(), Some(60));
+        assert_eq!("24h".parse::<StatsWindow>().map(|w| w.minutes()).ok(), Some(1440));
+        assert!("0m".parse::<StatsWindow>().is_err());
+        assert!("25h".parse::<StatsWindow>().is_err());
+        assert!("5s".parse::<StatsWindow>().is_err());
+        assert!("abc".parse::<StatsWindow>().is_err());
+    }
+
+    #[test]
+    fn test_counts_within_window() {
+        let now = Utc::now();
+        let mut counts = RollingCounts::default();
+        counts.record(&EventType::Heartbeat, now, now, 1);
+        counts.record(&EventType::Heartbeat, now - Duration::minutes(3), now, 1);
+        counts.record(&EventType::Custom("Login".to_string()), now - Duration::minutes(30), now, 1);
+        counts.record(&EventType::Heartbeat, now - Duration::hours(5), now, 1);
+
+        let window = |s: &str| s.parse::<StatsWindow>().map(|w| counts.window(w, now)).ok();
+
+        assert_eq!(window("1m").map(|c| c.total_events), Some(1));
+        assert_eq!(window("5m").map(|c| c.total_events), Some(2));
+        assert_eq!(window("1h").map(|c| c.total_events), Some(3));
+        assert_eq!(window("24h").map(|c| c.total_events), Some(4));
+        assert_eq!(
+            window("5m").and_then(|c| c.event_types.get(&EventType::Heartbeat).copied()),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_ignores_events_older_than_ring() {
+        let now = Utc::now();
+        let mut counts = RollingCounts::default();
+        counts.record(&EventType::Heartbeat, now, now, 1);
+        // Same minute slot, but one hour earlier: must not reset or pollute the
+        // current bucket
+        counts.record(&EventType::Heartbeat, now - Duration::hours(1), now, 1);
+
+        let window = StatsWindow::from_minutes(1).map(|w| counts.window(w, now)).ok();
+        assert_eq!(window.map(|c| c.total_events), Some(1));
+    }
+
+    #[test]
+    fn test_counts_future_events_now() {
+        let now = Utc::now();
+        let mut counts = RollingCounts::default();
+        counts.record(&EventType::Heartbeat, now - Duration::minutes(50), now, 1);
+        // 10 minutes ahead lands in the slot of 50 minutes ago
+        counts.record(&EventType::Heartbeat, now + Duration::minutes(10), now, 1);
+
+        let window = |s: &str| s.parse::<StatsWindow>().map(|w| counts.window(w, now)).ok();
+        assert_eq!(window("1m").map(|c| c.total_events), Some(1));
+        assert_eq!(window("1h").map(|c| c.total_events), Some(2));
+    }
+
+    #[test]
+    fn test_caps_types_per_bucket() {
+        let now = Utc::now();
+        let mut counts = RollingCounts::default();
+        for i in 0..MAX_TYPES_PER_BUCKET + 2 {
+            counts.record(&EventType::Custom(format!("Type{}", i)), now, now, 1);
+        }
+        counts.record(&EventType::Custom("Type0".to_string()), now, now, 1);
+
+        let Some(window) = StatsWindow::from_minutes(1).map(|w| counts.window(w, now)).ok() else {
+            panic!("window should be valid");
+        };
+        assert_eq!(window.total_events, MAX_TYPES_PER_BUCKET as u64 + 3);
+        assert_eq!(window.event_types.len(), MAX_TYPES_PER_BUCKET);
+        assert_eq!(window.event_types.get(&EventType::Custom("Type0".to_string())), Some(&2));
+        assert_eq!(window.other_types, 2);
+    }
+}
@@ -1,76 +1,543 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::{HashSet, VecDeque},
+    hash::{BuildHasher, DefaultHasher, Hash, Hasher, RandomState},
+    panic::AssertUnwindSafe,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use futures::Future;
+use futures::FutureExt;
+use tokio::{
+    sync::{Notify, mpsc},
+    task::JoinHandle,
+};
 use tokio_stream::{StreamExt, wrappers::ReceiverStream};
 use tracing::Instrument;
 
 use crate::{
     common_types::{EventProcessors, EventReceiver, TelemetryMap},
-    config::Config,
+    config::{Config, OverflowPolicy, ProcessorConfig, ProcessorPluginConfig, RetryConfig},
+    dlq::DeadLetterQueue,
+    event::Event,
     metrics::{
-        EVENTS_PROCESSED_TOTAL, PROCESSOR_PLUGIN_DURATION_SECONDS, PROCESSOR_PLUGIN_ERRORS_TOTAL,
+        DLQ_REPLAYS_TOTAL, EVENTS_PROCESSED_TOTAL, PROCESSOR_PLUGIN_DURATION_SECONDS,
+        PROCESSOR_PLUGIN_ERRORS_TOTAL, PROCESSOR_PLUGIN_FAILED_EVENTS_TOTAL,
+        PROCESSOR_PLUGIN_RETRIES_TOTAL, PROCESSOR_QUEUE_BACKLOG,
+        PROCESSOR_QUEUE_DROPPED_EVENTS_TOTAL, PROCESSOR_WORKER_BATCHES_TOTAL,
+        PROCESSOR_WORKER_EVENTS_TOTAL,
     },
-    processing::error::ProcessingError,
+    processing::{BatchOutcome, EventProcessor, error::ProcessingError},
+    spill::SpillBuffer,
+    wal::WriteAheadLog,
 };
 
-// Retry helper
-async fn execute_with_retries<F, Fut>(
-    plugin_name: &str,
-    retry_attempts: u32,
-    retry_delay: u64,
-    mut operation: F,
-) -> Result<(), ProcessingError>
-where
-    F: FnMut() -> Fut,
-    Fut: Future<Output = Result<(), ProcessingError>>,
-{
+/// An event on its way to a processor worker.
+#[derive(Debug)]
+pub struct QueuedEvent {
+    pub event: Event,
+    /// Sequence number in the WAL, committed once all plugins are done
+    pub wal_seq: Option<u64>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    #[error("{0}")]
+    Wal(String),
+    #[error("{0}")]
+    Spill(String),
+    #[error("Spill buffer full")]
+    SpillFull,
+    #[error("Event channel closed")]
+    Closed,
+}
+
+/// Channels of the processor workers, and the buffer their events spill to.
+#[derive(Debug)]
+struct Channels {
+    senders: Vec<mpsc::Sender<QueuedEvent>>,
+    spill: Option<Arc<SpillBuffer>>,
+}
+
+impl Drop for Channels {
+    fn drop(&mut self) {
+        // No more events: the drainers close their channels once drained
+        if let Some(spill) = &self.spill {
+            spill.close();
+        }
+    }
+}
+
+/// Sends events to the processor workers. Events are routed by source, so
+/// the events of a source are batched by one worker and stay in order.
+#[derive(Debug, Clone)]
+pub struct EventRouter {
+    channels: Arc<Channels>,
+    /// Where events are appended before they are queued, if enabled
+    wal: Option<Arc<WriteAheadLog>>,
+}
+
+impl EventRouter {
+    /// Channels of `workers` processor workers sharing `capacity` events.
+    /// With a spill buffer, events that don't fit are spilled to disk and
+    /// drained back by a task per worker, so this must be called within a
+    /// Tokio runtime.
+    pub fn channel(
+        workers: usize,
+        capacity: usize,
+        wal: Option<Arc<WriteAheadLog>>,
+        spill: Option<SpillBuffer>,
+    ) -> (Self, Vec<EventReceiver>) {
+        let workers = workers.max(1);
+        let capacity = capacity.div_ceil(workers).max(1);
+        let (senders, receivers): (Vec<_>, _) =
+            (0..workers).map(|_| mpsc::channel(capacity)).unzip();
+        let spill = spill.map(Arc::new);
+        if let Some(spill) = &spill {
+            for (worker, sender) in senders.iter().enumerate() {
+                let spill = spill.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move { spill.drain(worker, sender).await });
+            }
+        }
+        (Self { channels: Arc::new(Channels { senders, spill }), wal }, receivers)
+    }
+
+    /// Worker of the source of an event.
+    fn worker(&self, event: &Event) -> usize {
+        // Hash with fixed keys, so a source always goes to the same worker
+        let mut hasher = DefaultHasher::new();
+        event.source_key().hash(&mut hasher);
+        (hasher.finish() % self.channels.senders.len() as u64) as usize
+    }
+
+    /// Sends an event to the worker of its source, waiting for room in its
+    /// channel unless it is spilled. With the WAL enabled, the event is
+    /// appended to it first.
+    pub async fn send(&self, event: Event) -> Result<(), SendError> {
+        let wal_seq = match &self.wal {
+            Some(wal) => Some(wal.append(&event).await.map_err(SendError::Wal)?),
+            None => None,
+        };
+        let result = self.queue(QueuedEvent { event, wal_seq }).await;
+        if let (Err(_), Some(wal), Some(wal_seq)) = (&result, &self.wal, wal_seq) {
+            // The client retries the event, so don't replay it too
+            wal.commit(&[wal_seq]);
+        }
+        result
+    }
+
+    /// Sends an event replayed from the WAL, without appending it again.
+    pub async fn replay(&self, wal_seq: u64, event: Event) -> Result<(), SendError> {
+        self.queue(QueuedEvent { event, wal_seq: Some(wal_seq) }).await
+    }
+
+    async fn queue(&self, queued: QueuedEvent) -> Result<(), SendError> {
+        let worker = self.worker(&queued.event);
+        let sender = self.channels.senders.get(worker).ok_or(SendError::Closed)?;
+        match &self.channels.spill {
+            Some(spill) => spill.send(worker, sender, queued).await,
+            None => sender.send(queued).await.map_err(|_| SendError::Closed),
+        }
+    }
+}
+
+/// How a plugin retries a failed batch: exponential backoff between attempts,
+/// optionally randomized and bounded by the time since the first attempt.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    multiplier: f64,
+    max_delay: Duration,
+    jitter: f64,
+    max_elapsed: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// The policy of a plugin, falling back to the global retry settings.
+    pub fn new(defaults: &ProcessorConfig, config: &RetryConfig) -> Self {
+        Self {
+            max_attempts: config.max_attempts.unwrap_or(defaults.retry_attempts).max(1),
+            initial_delay: Duration::from_millis(
+                config.initial_delay.unwrap_or(defaults.retry_delay),
+            ),
+            multiplier: config.multiplier,
+            max_delay: Duration::from_millis(config.max_delay),
+            jitter: config.jitter,
+            max_elapsed: config.max_elapsed.map(Duration::from_millis),
+        }
+    }
+
+    /// Delay before the retry following the given (1-based) attempt.
+    fn delay(&self, attempt: u32) -> Duration {
+        let exponent = i32::try_from(attempt.saturating_sub(1)).unwrap_or(i32::MAX);
+        let backoff = self.initial_delay.as_secs_f64() * self.multiplier.powi(exponent);
+        let capped = backoff.min(self.max_delay.as_secs_f64());
+        // Uniform factor in [1 - jitter, 1 + jitter]
+        let factor = 1.0 + self.jitter * (2.0 * random_fraction() - 1.0);
+        Duration::from_secs_f64((capped * factor).max(0.0))
+    }
+}
+
+/// A random number in [0, 1), good enough to spread retries apart.
+fn random_fraction() -> f64 {
+    let hash = RandomState::new().build_hasher().finish();
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Events of a batch a plugin failed to process for good.
+#[derive(Debug)]
+pub struct RetryFailure {
+    /// Indices of the failed events in the batch
+    pub events: Vec<usize>,
+    pub error: ProcessingError,
+    pub attempts: u32,
+    /// Why no further attempt was made
+    pub reason: &'static str,
+}
+
+/// Runs a plugin on a batch with its retry policy. Only the events that failed
+/// with a retryable error are retried, the processed ones are not sent again.
+/// A panic in the plugin fails the events like a permanent error. Returns the
+/// events that failed for good, grouped by error.
+async fn run_with_retries(
+    plugin: &dyn EventProcessor,
+    telemetry_map: &TelemetryMap,
+    events: &[Event],
+    policy: &RetryPolicy,
+) -> Vec<RetryFailure> {
+    let name = plugin.name();
+    let start = Instant::now();
+    let mut pending: Vec<usize> = (0..events.len()).collect();
+    let mut failures = Vec::new();
     let mut attempts = 0;
 
-    loop {
+    while !pending.is_empty() {
         attempts += 1;
 
-        match operation().await {
-            Ok(result) => return Ok(result),
-            Err(err) if attempts < retry_attempts => {
-                tracing::warn!(
-                    "Plugin {} failed to process event: {}. Retrying in {} ms (attempt {}/{})",
-                    plugin_name,
-                    err,
-                    retry_delay,
-                    attempts,
-                    retry_attempts
-                );
-                tokio::time::sleep(Duration::from_millis(retry_delay)).await;
+        let retried: Vec<Event>;
+        let batch = if pending.len() == events.len() {
+            events
+        } else {
+            retried = pending.iter().filter_map(|&i| events.get(i).cloned()).collect();
+            &retried
+        };
+        let outcome = AssertUnwindSafe(plugin.process_events(telemetry_map, batch))
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|_| {
+                BatchOutcome::Failed(ProcessingError::permanent(name, "plugin panicked", None))
+            });
+        // Failed events by their index in `events`
+        let failed: Vec<(Vec<usize>, ProcessingError)> = match outcome {
+            BatchOutcome::Processed => break,
+            BatchOutcome::Failed(err) => vec![(pending.clone(), err)],
+            BatchOutcome::PartiallyFailed(errors) => errors
+                .into_iter()
+                .filter_map(|(i, err)| pending.get(i).map(|&index| (vec![index], err)))
+                .collect(),
+        };
+
+        let delay = policy.delay(attempts);
+        let within_time = policy.max_elapsed.is_none_or(|max| start.elapsed() + delay <= max);
+        let can_retry = attempts < policy.max_attempts && within_time;
+        pending.clear();
+        let mut retried_error = None;
+        for (indices, err) in failed {
+            if err.is_retryable() && can_retry {
+                pending.extend(indices);
+                retried_error.get_or_insert(err);
+                continue;
+            }
+            let reason = if !err.is_retryable() {
+                "permanent error"
+            } else if attempts >= policy.max_attempts {
+                "attempts exhausted"
+            } else {
+                "retry time exhausted"
+            };
+            failures.push(RetryFailure { events: indices, error: err, attempts, reason });
+        }
+        pending.sort_unstable();
+        pending.dedup();
+
+        if let Some(err) = retried_error {
+            tracing::warn!(
+                "Plugin {} failed to process {} event(s): {}. Retrying in {} ms (attempt {}/{})",
+                name,
+                pending.len(),
+                err,
+                delay.as_millis(),
+                attempts,
+                policy.max_attempts
+            );
+            metrics::counter!(PROCESSOR_PLUGIN_RETRIES_TOTAL, "plugin" => name.to_owned())
+                .increment(1);
+            tokio::time::sleep(delay).await;
+        }
+    }
+    failures
+}
+
+/// A processor plugin with the settings of its queue and workers.
+pub struct QueuedProcessor {
+    pub processor: Arc<dyn EventProcessor + Send + Sync>,
+    pub config: ProcessorPluginConfig,
+}
+
+impl std::fmt::Debug for QueuedProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QueuedProcessor")
+            .field("processor", &self.processor.name())
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+/// A batch of events shared by the queues of all plugins. Once every plugin
+/// is done with it, the events no plugin failed or dropped count as
+/// processed, and the events are committed in the WAL.
+struct Batch {
+    events: Vec<Event>,
+    pending: AtomicUsize,
+    /// Indices of the events a plugin failed or dropped
+    failed: Mutex<HashSet<usize>>,
+    /// WAL and sequence numbers of the events, if logged
+    wal: Option<(Arc<WriteAheadLog>, Vec<u64>)>,
+}
+
+impl Batch {
+    fn new(events: Vec<Event>, plugins: usize) -> Self {
+        Self {
+            events,
+            pending: AtomicUsize::new(plugins),
+            failed: Mutex::new(HashSet::new()),
+            wal: None,
+        }
+    }
+
+    fn with_wal(self, wal: Option<Arc<WriteAheadLog>>, wal_seqs: Vec<u64>) -> Self {
+        Self { wal: wal.filter(|_| !wal_seqs.is_empty()).map(|wal| (wal, wal_seqs)), ..self }
+    }
+
+    /// Records that a plugin is done with the batch, failing the given events.
+    fn finish(&self, failed_events: impl IntoIterator<Item = usize>) {
+        let mut failed = self.failed.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        failed.extend(failed_events);
+        if self.pending.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return;
+        }
+        let processed = self.events.len().saturating_sub(failed.len());
+        metrics::counter!(EVENTS_PROCESSED_TOTAL).increment(processed as u64);
+        // Failed events are committed too: their retries are over and they are
+        // logged to the DLQ log and counted as failed, and written to the DLQ
+        // if enabled. Keeping them pending would pin the committed offset.
+        if let Some((wal, wal_seqs)) = &self.wal {
+            wal.commit(wal_seqs);
+        }
+        if failed.is_empty() {
+            tracing::info!("Batch of {} processed successfully", self.events.len());
+        } else {
+            tracing::warn!(
+                "Failed to process {} of {} events of the batch",
+                failed.len(),
+                self.events.len()
+            );
+        }
+    }
+}
+
+/// Bounded queue of the batches waiting for one processor plugin, applying
+/// its overflow policy when full.
+struct BatchQueue {
+    plugin: &'static str,
+    worker: String,
+    capacity: usize,
+    overflow: OverflowPolicy,
+    batches: Mutex<VecDeque<Arc<Batch>>>,
+    closed: AtomicBool,
+    /// Wakes workers waiting for a batch
+    pushed: Notify,
+    /// Wakes the producer waiting for room
+    popped: Notify,
+}
+
+impl BatchQueue {
+    fn new(plugin: &'static str, worker: usize, config: &ProcessorPluginConfig) -> Self {
+        Self {
+            plugin,
+            worker: worker.to_string(),
+            capacity: config.queue_capacity.max(1),
+            overflow: config.overflow,
+            batches: Mutex::new(VecDeque::new()),
+            closed: AtomicBool::new(false),
+            pushed: Notify::new(),
+            popped: Notify::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Arc<Batch>>> {
+        // A worker panicking while holding the lock leaves the queue intact
+        self.batches.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record_backlog(&self, batches: &VecDeque<Arc<Batch>>) {
+        let events: usize = batches.iter().map(|batch| batch.events.len()).sum();
+        metrics::gauge!(PROCESSOR_QUEUE_BACKLOG, "plugin" => self.plugin, "worker" => self.worker.clone())
+            .set(events as f64);
+    }
+
+    fn drop_batch(&self, batch: &Batch) {
+        let policy = match self.overflow {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropNewest => "drop_newest",
+            OverflowPolicy::DropOldest => "drop_oldest",
+        };
+        tracing::warn!(
+            plugin = self.plugin,
+            policy,
+            "Processor queue full, dropping batch of {} events",
+            batch.events.len()
+        );
+        metrics::counter!(PROCESSOR_QUEUE_DROPPED_EVENTS_TOTAL, "plugin" => self.plugin, "policy" => policy)
+            .increment(batch.events.len() as u64);
+        batch.finish(0..batch.events.len());
+    }
+
+    /// Queues a batch. With the `block` policy this waits for room.
+    async fn push(&self, batch: Arc<Batch>) {
+        loop {
+            let popped = self.popped.notified();
+            tokio::pin!(popped);
+            popped.as_mut().enable();
+            {
+                let mut batches = self.lock();
+                if batches.len() < self.capacity {
+                    batches.push_back(batch);
+                    self.record_backlog(&batches);
+                    self.pushed.notify_one();
+                    return;
+                }
+                match self.overflow {
+                    OverflowPolicy::Block => {}
+                    OverflowPolicy::DropNewest => {
+                        drop(batches);
+                        self.drop_batch(&batch);
+                        return;
+                    }
+                    OverflowPolicy::DropOldest => {
+                        let oldest = batches.pop_front();
+                        batches.push_back(batch);
+                        self.record_backlog(&batches);
+                        drop(batches);
+                        if let Some(oldest) = oldest {
+                            self.drop_batch(&oldest);
+                        }
+                        self.pushed.notify_one();
+                        return;
+                    }
+                }
             }
-            Err(err) => {
-                tracing::error!(
-                    target: "dlq_log",
-                    plugin = plugin_name,
-                    attempts = attempts,
-                    error = %err,
-                    "DLQ: Operation failed permanently after {} attempts. Logging failed batch summary.", attempts
-                );
-                metrics::counter!(PROCESSOR_PLUGIN_ERRORS_TOTAL, "plugin" => plugin_name.to_owned())
-                    .increment(1);
-                return Err(err);
+            popped.await;
+        }
+    }
+
+    /// Takes the next batch, waiting for one. Returns `None` once the queue
+    /// is closed and drained.
+    async fn pop(&self) -> Option<Arc<Batch>> {
+        loop {
+            let pushed = self.pushed.notified();
+            tokio::pin!(pushed);
+            pushed.as_mut().enable();
+            {
+                let mut batches = self.lock();
+                if let Some(batch) = batches.pop_front() {
+                    self.record_backlog(&batches);
+                    self.popped.notify_one();
+                    return Some(batch);
+                }
+                if self.closed.load(Ordering::Acquire) {
+                    return None;
+                }
             }
+            pushed.await;
         }
     }
+
+    /// Lets the workers exit once the queued batches are processed.
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.pushed.notify_waiters();
+    }
 }
 
+#[derive(Clone)]
 pub struct EventProcessorManager {
     telemetry_map: TelemetryMap,
     plugins: EventProcessors,
     config: Arc<Config>,
+    /// Where batches that failed for good are kept, if enabled
+    dlq: Option<Arc<DeadLetterQueue>>,
+    /// Where processed events are committed, if enabled
+    wal: Option<Arc<WriteAheadLog>>,
 }
 
 impl EventProcessorManager {
-    pub fn new(telemetry_map: TelemetryMap, plugins: EventProcessors, config: Arc<Config>) -> Self {
-        EventProcessorManager { telemetry_map, plugins, config }
+    pub fn new(
+        telemetry_map: TelemetryMap,
+        plugins: EventProcessors,
+        config: Arc<Config>,
+        dlq: Option<Arc<DeadLetterQueue>>,
+        wal: Option<Arc<WriteAheadLog>>,
+    ) -> Self {
+        EventProcessorManager { telemetry_map, plugins, config, dlq, wal }
+    }
+
+    /// Spawns the workers of a plugin, processing batches from its queue.
+    fn spawn_workers(
+        &self,
+        plugin: &QueuedProcessor,
+        queue: &Arc<BatchQueue>,
+    ) -> Vec<JoinHandle<()>> {
+        (0..plugin.config.concurrency.max(1))
+            .map(|_| {
+                let processor = plugin.processor.clone();
+                let queue = queue.clone();
+                let telemetry_map = self.telemetry_map.clone();
+                let retry_policy = RetryPolicy::new(&self.config.processor, &plugin.config.retry);
+                let dlq = self.dlq.clone();
+                tokio::spawn(async move {
+                    while let Some(batch) = queue.pop().await {
+                        let process_span = tracing::info_span!(
+                            "process_event_batch",
+                            plugin = processor.name(),
+                            batch_size = batch.events.len()
+                        );
+                        let failed = process_batch(
+                            processor.as_ref(),
+                            &telemetry_map,
+                            &batch.events,
+                            &retry_policy,
+                            dlq.as_ref(),
+                        )
+                        .instrument(process_span)
+                        .await;
+                        batch.finish(failed);
+                    }
+                })
+            })
+            .collect()
     }
 
-    #[tracing::instrument(skip_all)]
-    pub async fn run(&self, receiver: EventReceiver) {
+    /// Batches events from the channel of a worker and fans the batches out
+    /// to the queue of each plugin, so a slow plugin only holds up the others
+    /// once its queue is full (with the `block` policy). Returns when the
+    /// channel is closed and all queued batches are processed.
+    #[tracing::instrument(skip(self, receiver))]
+    pub async fn run(&self, worker: usize, receiver: EventReceiver) {
         let batch_size = self.config.processor.batch_size;
         let batch_timeout = self.config.processor.batch_timeout;
 
@@ -80,6 +547,14 @@ impl EventProcessorManager {
             "Starting processor"
         );
 
+        let mut queues = Vec::with_capacity(self.plugins.len());
+        let mut workers = Vec::new();
+        for plugin in self.plugins.iter() {
+            let queue = Arc::new(BatchQueue::new(plugin.processor.name(), worker, &plugin.config));
+            workers.extend(self.spawn_workers(plugin, &queue));
+            queues.push(queue);
+        }
+
         let receiver_stream = ReceiverStream::new(receiver)
             .chunks_timeout(batch_size, Duration::from_millis(batch_timeout));
 
@@ -90,69 +565,660 @@ impl EventProcessorManager {
                 tracing::debug!("Received empty batch of events");
                 continue;
             }
+            let (events_batch, wal_seqs): (Vec<_>, Vec<_>) =
+                events_batch.into_iter().map(|queued| (queued.event, queued.wal_seq)).unzip();
+            let wal_seqs: Vec<u64> = wal_seqs.into_iter().flatten().collect();
+            if queues.is_empty() {
+                if let Some(wal) = &self.wal {
+                    wal.commit(&wal_seqs);
+                }
+                continue;
+            }
 
-            let process_span =
-                tracing::info_span!("process_event_batch", batch_size = events_batch.len());
+            let worker_label = worker.to_string();
+            metrics::counter!(PROCESSOR_WORKER_EVENTS_TOTAL, "worker" => worker_label.clone())
+                .increment(events_batch.len() as u64);
+            metrics::counter!(PROCESSOR_WORKER_BATCHES_TOTAL, "worker" => worker_label)
+                .increment(1);
+            tracing::debug!("Queueing batch of {} events", events_batch.len());
+            let batch = Arc::new(
+                Batch::new(events_batch, queues.len()).with_wal(self.wal.clone(), wal_seqs),
+            );
+            for queue in &queues {
+                queue.push(batch.clone()).await;
+            }
+        }
 
-            async {
-                tracing::info!("Processing batch of events");
+        tracing::info!("Event receiver channel closed. Waiting for queued batches.");
+        for queue in &queues {
+            queue.close();
+        }
+        for worker in workers {
+            if let Err(err) = worker.await {
+                tracing::error!("Processor worker failed: {}", err);
+            }
+        }
 
-                let mut batch_processed = true; // Flag to track if batch was processed successfully
+        tracing::info!(
+            "Event receiver channel closed and all batches processed. Exiting processor loop."
+        );
+    }
+}
 
-                for plugin in self.plugins.iter() {
-                    let start = std::time::Instant::now();
-                    let name = plugin.name();
-                    let retry_attempts = self.config.processor.retry_attempts;
-                    let retry_delay = self.config.processor.retry_delay;
+/// Processes a batch with a plugin, retrying failed events on retryable
+/// errors. Events that failed for good are written to the DLQ, if enabled.
+/// Returns the indices of the failed events.
+async fn process_batch(
+    plugin: &dyn EventProcessor,
+    telemetry_map: &TelemetryMap,
+    events: &[Event],
+    retry_policy: &RetryPolicy,
+    dlq: Option<&Arc<DeadLetterQueue>>,
+) -> Vec<usize> {
+    let start = std::time::Instant::now();
+    let name = plugin.name();
 
-                    tracing::debug!("Processing with plugin: {}", name);
+    tracing::debug!("Processing with plugin: {}", name);
 
-                    let operation = || plugin.process_event(&self.telemetry_map, &events_batch);
+    let failures = run_with_retries(plugin, telemetry_map, events, retry_policy).await;
+    let status = match failures.iter().map(|failure| failure.events.len()).sum::<usize>() {
+        0 => "success",
+        failed if failed < events.len() => "partial",
+        _ => "error",
+    };
+    metrics::histogram!(
+      PROCESSOR_PLUGIN_DURATION_SECONDS,
+      "plugin" => name.to_owned(),
+      "status" => status
+    )
+    .record(start.elapsed());
+    if failures.is_empty() {
+        tracing::info!("Plugin {} processed events successfully", name);
+        return Vec::new();
+    }
 
-                    let result =
-                        execute_with_retries(name, retry_attempts, retry_delay, operation).await;
+    let mut failed_events = Vec::new();
+    for failure in failures {
+        metrics::counter!(PROCESSOR_PLUGIN_ERRORS_TOTAL, "plugin" => name.to_owned()).increment(1);
+        metrics::counter!(PROCESSOR_PLUGIN_FAILED_EVENTS_TOTAL, "plugin" => name.to_owned())
+            .increment(failure.events.len() as u64);
 
-                    match result {
-                        Ok(_) => {
-                            metrics::histogram!(
-                              PROCESSOR_PLUGIN_DURATION_SECONDS,
-                              "plugin" => name.to_owned(),
-                              "status" => "success"
-                            )
-                            .record(start.elapsed());
+        let failed: Vec<Event> =
+            failure.events.iter().filter_map(|&i| events.get(i).cloned()).collect();
+        let (count, attempts, error) = (failed.len(), failure.attempts, failure.error.to_string());
+        let dlq_id = match dlq {
+            Some(dlq) => {
+                let error = failure.error;
+                Some(dlq.blocking(move |dlq| dlq.push(name, &failed, &error, attempts)).await)
+            }
+            None => None,
+        };
+        if let Some(Err(err)) = &dlq_id {
+            tracing::error!("Failed to write batch to the DLQ: {}", err);
+        }
+        let dlq_id = dlq_id.and_then(Result::ok);
+        tracing::error!(
+            target: "dlq_log",
+            plugin = name,
+            attempts = attempts,
+            reason = failure.reason,
+            events = count,
+            batch_size = events.len(),
+            dlq_id = dlq_id,
+            error = %error,
+            "DLQ: Operation failed permanently after {} attempts. Logging failed batch summary.", attempts
+        );
+        failed_events.extend(failure.events);
+    }
+    failed_events
+}
 
-                            tracing::info!("Plugin {} processed events successfully", name);
-                        }
-                        Err(_) => {
-                            // Mark batch as not processed successfully
-                            batch_processed = false;
-                            metrics::histogram!(
-                              PROCESSOR_PLUGIN_DURATION_SECONDS,
-                              "plugin" => name.to_owned(),
-                              "status" => "error"
-                            )
-                            .record(start.elapsed());
-
-                            tracing::error!(
-                                "Plugin failed processing batch after all retries (see DLQ log)."
-                            )
-                        }
+/// Replays a batch from the DLQ into a plugin, with the plugin's retry
+/// policy. Returns the events that failed again, which are not written to the
+/// DLQ here.
+pub async fn replay_batch(
+    plugin: &QueuedProcessor,
+    telemetry_map: &TelemetryMap,
+    events: &[Event],
+    config: &Config,
+) -> Vec<RetryFailure> {
+    let name = plugin.processor.name();
+    let retry_policy = RetryPolicy::new(&config.processor, &plugin.config.retry);
+    let failures =
+        run_with_retries(plugin.processor.as_ref(), telemetry_map, events, &retry_policy).await;
+    let status = if failures.is_empty() { "success" } else { "error" };
+    metrics::counter!(DLQ_REPLAYS_TOTAL, "plugin" => name.to_owned(), "status" => status)
+        .increment(1);
+    failures
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use dashmap::DashMap;
+    use tokio::sync::{Semaphore, mpsc};
+
+    use super::*;
+    use crate::{
+        config::{SpillConfig, WalConfig},
+        event::{EventType, SourceId},
+    };
+
+    /// Parses a config with the given `processor` table.
+    fn create_config(processor: &str) -> Config {
+        let Ok(config) = toml::from_str::<Config>(&format!(
+            r#"
+            http = {{ host = "127.0.0.1", port = 0 }}
+            processor = {}
+            validation = {{}}
+            processing = {{}}
+            "#,
+            processor
+        )) else {
+            panic!("config should parse");
+        };
+        config
+    }
+
+    fn create_event(source_id: u64) -> Event {
+        Event {
+            source_id: source_id.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    /// Runs a worker of the manager per receiver.
+    fn spawn_workers(
+        manager: &EventProcessorManager,
+        receivers: Vec<EventReceiver>,
+    ) -> Vec<tokio::task::JoinHandle<()>> {
+        receivers
+            .into_iter()
+            .enumerate()
+            .map(|(worker, receiver)| {
+                let manager = manager.clone();
+                tokio::spawn(async move { manager.run(worker, receiver).await })
+            })
+            .collect()
+    }
+
+    /// Counts processed events, waiting for a permit per batch if gated.
+    struct CountingProcessor {
+        name: &'static str,
+        processed: Arc<AtomicUsize>,
+        gate: Option<Arc<Semaphore>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventProcessor for CountingProcessor {
+        async fn process_event(
+            &self,
+            _telemetry_map: &TelemetryMap,
+            events: &[Event],
+        ) -> Result<(), ProcessingError> {
+            if let Some(gate) = &self.gate
+                && let Ok(permit) = gate.acquire().await
+            {
+                permit.forget();
+            }
+            self.processed.fetch_add(events.len(), Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn name(&self) -> &'static str {
+            self.name
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_plugin_does_not_hold_up_others() {
+        let config = create_config("{ channel_capacity = 10, batch_size = 1, batch_timeout = 10 }");
+        let fast = Arc::new(AtomicUsize::new(0));
+        let slow = Arc::new(AtomicUsize::new(0));
+        let gate = Arc::new(Semaphore::new(0));
+        let plugins = vec![
+            QueuedProcessor {
+                processor: Arc::new(CountingProcessor {
+                    name: "Fast",
+                    processed: fast.clone(),
+                    gate: None,
+                }),
+                config: ProcessorPluginConfig::default(),
+            },
+            QueuedProcessor {
+                processor: Arc::new(CountingProcessor {
+                    name: "Slow",
+                    processed: slow.clone(),
+                    gate: Some(gate.clone()),
+                }),
+                config: ProcessorPluginConfig {
+                    queue_capacity: 1,
+                    overflow: OverflowPolicy::DropNewest,
+                    ..Default::default()
+                },
+            },
+        ];
+        let manager = EventProcessorManager::new(
+            Arc::new(DashMap::new()),
+            Arc::new(plugins),
+            Arc::new(config),
+            None,
+            None,
+        );
+        let (sender, receiver) = mpsc::channel(10);
+        let workers = spawn_workers(&manager, vec![receiver]);
+
+        for _ in 0..5 {
+            let event = create_event(1);
+            assert!(sender.send(QueuedEvent { event, wal_seq: None }).await.is_ok());
+        }
+        for _ in 0..200 {
+            if fast.load(Ordering::SeqCst) == 5 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(fast.load(Ordering::SeqCst), 5);
+        assert_eq!(slow.load(Ordering::SeqCst), 0);
+
+        // At most one batch in progress and one queued, the others were dropped
+        gate.add_permits(5);
+        drop(sender);
+        for worker in workers {
+            assert!(worker.await.is_ok());
+        }
+        assert!((1..=2).contains(&slow.load(Ordering::SeqCst)));
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_keeps_newest_batches() {
+        let config = ProcessorPluginConfig {
+            queue_capacity: 2,
+            overflow: OverflowPolicy::DropOldest,
+            ..Default::default()
+        };
+        let queue = BatchQueue::new("Test", 0, &config);
+        for size in 1..=3 {
+            let events = (0..size).map(|_| create_event(1)).collect();
+            queue.push(Arc::new(Batch::new(events, 1))).await;
+        }
+        queue.close();
+
+        let mut sizes = Vec::new();
+        while let Some(batch) = queue.pop().await {
+            sizes.push(batch.events.len());
+        }
+        assert_eq!(sizes, vec![2, 3]);
+    }
+
+    /// Fails with the given error until `failures` attempts were made.
+    struct FlakyProcessor {
+        attempts: AtomicUsize,
+        failures: usize,
+        permanent: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl EventProcessor for FlakyProcessor {
+        async fn process_event(
+            &self,
+            _telemetry_map: &TelemetryMap,
+            _events: &[Event],
+        ) -> Result<(), ProcessingError> {
+            if self.attempts.fetch_add(1, Ordering::SeqCst) >= self.failures {
+                return Ok(());
+            }
+            if self.permanent {
+                Err(ProcessingError::permanent(self.name(), "schema mismatch", None))
+            } else {
+                Err(ProcessingError::new(self.name(), "connection reset", None))
+            }
+        }
+
+        fn name(&self) -> &'static str {
+            "Flaky"
+        }
+    }
+
+    fn create_policy(max_attempts: u32) -> RetryPolicy {
+        let defaults = ProcessorConfig {
+            channel_capacity: 1,
+            batch_size: 1,
+            batch_timeout: 1,
+            retry_attempts: 3,
+            retry_delay: 1,
+            workers: 1,
+        };
+        let retry = RetryConfig { max_attempts: Some(max_attempts), ..Default::default() };
+        RetryPolicy::new(&defaults, &retry)
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_retryable_errors() {
+        let telemetry_map: TelemetryMap = Arc::new(DashMap::new());
+        let events = [create_event(1)];
+        let flaky = FlakyProcessor { attempts: AtomicUsize::new(0), failures: 2, permanent: false };
+        let failed = process_batch(&flaky, &telemetry_map, &events, &create_policy(3), None).await;
+        assert!(failed.is_empty());
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 3);
+
+        let flaky = FlakyProcessor { attempts: AtomicUsize::new(0), failures: 2, permanent: false };
+        let failures = run_with_retries(&flaky, &telemetry_map, &events, &create_policy(2)).await;
+        assert!(matches!(failures.as_slice(), [f] if f.reason == "attempts exhausted"));
+        assert_eq!(flaky.attempts.load(Ordering::SeqCst), 2);
+
+        let broken = FlakyProcessor { attempts: AtomicUsize::new(0), failures: 1, permanent: true };
+        let failures = run_with_retries(&broken, &telemetry_map, &events, &create_policy(3)).await;
+        assert!(matches!(failures.as_slice(), [f] if f.reason == "permanent error"));
+        assert_eq!(broken.attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_backoff_grows_up_to_max_delay() {
+        let mut policy = create_policy(10);
+        policy.initial_delay = Duration::from_millis(100);
+        policy.max_delay = Duration::from_millis(500);
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.delay(attempt).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        policy.jitter = 0.5;
+        for _ in 0..100 {
+            assert!((250..=750).contains(&policy.delay(10).as_millis()));
+        }
+    }
+
+    /// Rejects events of source 13 for good and fails events of source 7 on
+    /// the first attempt, recording the sources of each attempt.
+    struct PickyProcessor {
+        attempts: Mutex<Vec<Vec<SourceId>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventProcessor for PickyProcessor {
+        async fn process_event(
+            &self,
+            _telemetry_map: &TelemetryMap,
+            _events: &[Event],
+        ) -> Result<(), ProcessingError> {
+            Ok(())
+        }
+
+        async fn process_events(
+            &self,
+            _telemetry_map: &TelemetryMap,
+            events: &[Event],
+        ) -> BatchOutcome {
+            let Ok(mut attempts) = self.attempts.lock() else {
+                return BatchOutcome::Failed(ProcessingError::new(self.name(), "poisoned", None));
+            };
+            let first = attempts.is_empty();
+            attempts.push(events.iter().map(|event| event.source_id.clone()).collect());
+            let failures: Vec<_> = events
+                .iter()
+                .enumerate()
+                .filter_map(|(i, event)| match event.source_id {
+                    SourceId::Numeric(13) => {
+                        Some((i, ProcessingError::permanent(self.name(), "constraint", None)))
                     }
-                }
+                    SourceId::Numeric(7) if first => {
+                        Some((i, ProcessingError::new(self.name(), "timeout", None)))
+                    }
+                    _ => None,
+                })
+                .collect();
+            if failures.is_empty() {
+                BatchOutcome::Processed
+            } else {
+                BatchOutcome::PartiallyFailed(failures)
+            }
+        }
 
-                if batch_processed {
-                    metrics::counter!(EVENTS_PROCESSED_TOTAL).increment(events_batch.len() as u64);
-                    tracing::info!("Batch of {} processed successfully", events_batch.len());
-                } else {
-                    tracing::warn!("Failed to process batch of {} events", events_batch.len());
-                }
+        fn name(&self) -> &'static str {
+            "Picky"
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_failed_events() {
+        let telemetry_map: TelemetryMap = Arc::new(DashMap::new());
+        let picky = PickyProcessor { attempts: Mutex::new(Vec::new()) };
+        let events: Vec<_> = [1, 7, 13, 2].into_iter().map(create_event).collect();
+
+        let failed = process_batch(&picky, &telemetry_map, &events, &create_policy(3), None).await;
+        assert_eq!(failed, vec![2]);
+        let Ok(attempts) = picky.attempts.lock() else {
+            panic!("lock should not be poisoned");
+        };
+        assert_eq!(
+            *attempts,
+            vec![[1, 7, 13, 2].map(SourceId::Numeric).to_vec(), vec![SourceId::Numeric(7)]]
+        );
+    }
+
+    /// Source and timestamp of each processed event.
+    type SeenEvents = Arc<Mutex<Vec<(SourceId, chrono::DateTime<Utc>)>>>;
+
+    /// Records the source and timestamp of each processed event.
+    struct RecordingProcessor {
+        seen: SeenEvents,
+    }
+
+    #[async_trait::async_trait]
+    impl EventProcessor for RecordingProcessor {
+        async fn process_event(
+            &self,
+            _telemetry_map: &TelemetryMap,
+            events: &[Event],
+        ) -> Result<(), ProcessingError> {
+            if let Ok(mut seen) = self.seen.lock() {
+                seen.extend(events.iter().map(|event| (event.source_id.clone(), event.timestamp)));
             }
-            .instrument(process_span)
-            .await
+            tokio::task::yield_now().await;
+            Ok(())
         }
 
-        tracing::info!(
-            "Event receiver channel closed and all batches processed. Exiting processor loop."
+        fn name(&self) -> &'static str {
+            "Recording"
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_workers_keep_order_per_source() {
+        let config = create_config(
+            "{ channel_capacity = 100, batch_size = 3, batch_timeout = 10, workers = 4 }",
+        );
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let plugins = vec![QueuedProcessor {
+            processor: Arc::new(RecordingProcessor { seen: seen.clone() }),
+            config: ProcessorPluginConfig::default(),
+        }];
+        let (router, receivers) = EventRouter::channel(
+            config.processor.workers,
+            config.processor.channel_capacity,
+            None,
+            None,
+        );
+        assert_eq!(receivers.len(), 4);
+        let manager = EventProcessorManager::new(
+            Arc::new(DashMap::new()),
+            Arc::new(plugins),
+            Arc::new(config),
+            None,
+            None,
         );
+        let workers = spawn_workers(&manager, receivers);
+
+        let start = Utc::now();
+        for i in 0..200 {
+            let mut event = create_event(i % 10);
+            event.timestamp = start + chrono::Duration::milliseconds(i as i64);
+            assert!(router.send(event).await.is_ok());
+        }
+        drop(router);
+        for worker in workers {
+            assert!(worker.await.is_ok());
+        }
+
+        let Ok(seen) = seen.lock() else {
+            panic!("lock should not be poisoned");
+        };
+        assert_eq!(seen.len(), 200);
+        for source in 0..10 {
+            let timestamps: Vec<_> = seen
+                .iter()
+                .filter(|(id, _)| *id == SourceId::Numeric(source))
+                .map(|(_, timestamp)| *timestamp)
+                .collect();
+            assert_eq!(timestamps.len(), 20);
+            assert!(timestamps.is_sorted());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_commits_wal_once_processed() {
+        let config = create_config(
+            "{ channel_capacity = 100, batch_size = 4, batch_timeout = 10, workers = 2 }",
+        );
+        let dir =
+            std::env::temp_dir().join(format!("telemetron-wal-commit-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let wal_config = WalConfig { enabled: true, dir, segment_entries: 5, ..Default::default() };
+        let Ok((wal, _)) = WriteAheadLog::open(&wal_config) else {
+            panic!("WAL should open");
+        };
+        let wal = Arc::new(wal);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let plugins = vec![QueuedProcessor {
+            processor: Arc::new(RecordingProcessor { seen: seen.clone() }),
+            config: ProcessorPluginConfig::default(),
+        }];
+        let (router, receivers) = EventRouter::channel(
+            config.processor.workers,
+            config.processor.channel_capacity,
+            Some(wal.clone()),
+            None,
+        );
+        let manager = EventProcessorManager::new(
+            Arc::new(DashMap::new()),
+            Arc::new(plugins),
+            Arc::new(config),
+            None,
+            Some(wal),
+        );
+        let workers = spawn_workers(&manager, receivers);
+
+        for i in 0..20 {
+            assert!(router.send(create_event(i % 3)).await.is_ok());
+        }
+        drop(router);
+        for worker in workers {
+            assert!(worker.await.is_ok());
+        }
+
+        assert!(seen.lock().is_ok_and(|seen| seen.len() == 20));
+        // Dropping the last handle waits for the WAL writer to apply the commits
+        drop(manager);
+        let Ok((_, replay)) = WriteAheadLog::open(&wal_config) else {
+            panic!("WAL should reopen");
+        };
+        assert!(replay.is_empty());
+        let _ = std::fs::remove_dir_all(wal_config.dir);
+    }
+
+    #[tokio::test]
+    async fn test_commits_failed_events() {
+        let config =
+            create_config("{ channel_capacity = 100, batch_size = 3, batch_timeout = 10 }");
+        let dir =
+            std::env::temp_dir().join(format!("telemetron-wal-failed-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let wal_config = WalConfig { enabled: true, dir, ..Default::default() };
+        let Ok((wal, _)) = WriteAheadLog::open(&wal_config) else {
+            panic!("WAL should open");
+        };
+        let wal = Arc::new(wal);
+        let plugins = vec![QueuedProcessor {
+            processor: Arc::new(PickyProcessor { attempts: Mutex::new(Vec::new()) }),
+            config: ProcessorPluginConfig::default(),
+        }];
+        let (router, receivers) =
+            EventRouter::channel(1, config.processor.channel_capacity, Some(wal.clone()), None);
+        let manager = EventProcessorManager::new(
+            Arc::new(DashMap::new()),
+            Arc::new(plugins),
+            Arc::new(config),
+            None,
+            Some(wal),
+        );
+        let workers = spawn_workers(&manager, receivers);
+
+        for source_id in [1, 13, 2] {
+            assert!(router.send(create_event(source_id)).await.is_ok());
+        }
+        drop(router);
+        for worker in workers {
+            assert!(worker.await.is_ok());
+        }
+
+        // The event Picky failed for good doesn't hold back the offset
+        drop(manager);
+        let Ok((_, replay)) = WriteAheadLog::open(&wal_config) else {
+            panic!("WAL should reopen");
+        };
+        assert!(replay.is_empty());
+        let _ = std::fs::remove_dir_all(wal_config.dir);
+    }
+
+    #[tokio::test]
+    async fn test_spills_events_while_channel_is_full() {
+        let config = create_config("{ channel_capacity = 2, batch_size = 1, batch_timeout = 10 }");
+        let dir = std::env::temp_dir().join(format!("telemetron-spill-{}", std::process::id()));
+        let spill_config = SpillConfig { enabled: true, dir, ..Default::default() };
+        let Ok(spill) = SpillBuffer::open(&spill_config, 1) else {
+            panic!("spill buffer should open");
+        };
+        let processed = Arc::new(AtomicUsize::new(0));
+        let gate = Arc::new(Semaphore::new(0));
+        let plugins = vec![QueuedProcessor {
+            processor: Arc::new(CountingProcessor {
+                name: "Gated",
+                processed: processed.clone(),
+                gate: Some(gate.clone()),
+            }),
+            config: ProcessorPluginConfig { queue_capacity: 1, ..Default::default() },
+        }];
+        let (router, receivers) =
+            EventRouter::channel(1, config.processor.channel_capacity, None, Some(spill));
+        let manager = EventProcessorManager::new(
+            Arc::new(DashMap::new()),
+            Arc::new(plugins),
+            Arc::new(config),
+            None,
+            None,
+        );
+        let workers = spawn_workers(&manager, receivers);
+
+        // The gated plugin holds up the worker, so its channel fills up
+        for i in 0..30 {
+            assert!(router.send(create_event(i)).await.is_ok());
+        }
+        let spilled = std::fs::read_dir(spill_config.dir.join("worker-0"))
+            .map(|entries| entries.count())
+            .unwrap_or_default();
+        assert!(spilled > 0);
+
+        gate.add_permits(30);
+        drop(router);
+        for worker in workers {
+            assert!(worker.await.is_ok());
+        }
+        assert_eq!(processed.load(Ordering::SeqCst), 30);
+        let _ = std::fs::remove_dir_all(spill_config.dir);
     }
 }
@@ -0,0 +1,427 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Mutex, mpsc},
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::QuarantineConfig,
+    event::{Event, EventValidationError},
+    metrics::{QUARANTINE_EVENTS, QUARANTINE_EVICTED_EVENTS_TOTAL, QUARANTINED_EVENTS_TOTAL},
+    tenant::TenantId,
+};
+
+/// A validator failure of a quarantined event.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rejection {
+    pub validator: String,
+    pub reason: String,
+}
+
+impl Rejection {
+    pub fn from_failures(failures: &[(&'static str, EventValidationError)]) -> Vec<Self> {
+        failures
+            .iter()
+            .map(|(validator, err)| Self {
+                validator: validator.to_string(),
+                reason: err.to_string(),
+            })
+            .collect()
+    }
+}
+
+/// An event rejected by the validators, with the request it was sent with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedEvent {
+    pub id: u64,
+    pub tenant: TenantId,
+    pub quarantined_at: DateTime<Utc>,
+    pub received_at: DateTime<Utc>,
+    pub client_addr: Option<SocketAddr>,
+    pub rejections: Vec<Rejection>,
+    /// The event as sent by the source, redacted if `redacted` is set
+    pub event: Event,
+    /// Whether the event was redacted before it was kept, so it is not
+    /// redacted again on re-submission
+    #[serde(default)]
+    pub redacted: bool,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    next_id: u64,
+    by_id: BTreeMap<u64, QuarantinedEvent>,
+    /// Ids of the events of each tenant, oldest first
+    by_tenant: HashMap<TenantId, BTreeSet<u64>>,
+}
+
+impl Entries {
+    fn insert(&mut self, entry: QuarantinedEvent) {
+        self.by_tenant.entry(entry.tenant.clone()).or_default().insert(entry.id);
+        self.by_id.insert(entry.id, entry);
+    }
+
+    fn remove(&mut self, id: u64) -> Option<QuarantinedEvent> {
+        let entry = self.by_id.remove(&id)?;
+        if let Some(ids) = self.by_tenant.get_mut(&entry.tenant) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.by_tenant.remove(&entry.tenant);
+            }
+        }
+        Some(entry)
+    }
+}
+
+/// Change to the event files, applied in order by the writer thread.
+#[derive(Debug)]
+enum FileOp {
+    Write(Box<QuarantinedEvent>),
+    Delete(u64),
+}
+
+/// Bounded store of events rejected by the validators, so they can be
+/// re-submitted once the validator config is fixed instead of being lost. The
+/// capacity applies per tenant, so one tenant can't evict the events of
+/// others. When full, the oldest events of the tenant are evicted. Events are
+/// kept in memory and, with a directory configured, one file per event so they
+/// survive restarts. Files are written by a writer thread, off the request
+/// path.
+#[derive(Debug)]
+pub struct QuarantineStore {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    /// Sends file changes to the writer thread, if a directory is configured
+    files: Option<mpsc::Sender<FileOp>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl QuarantineStore {
+    /// Opens the store, loading the events kept in its directory. Files that
+    /// can't be loaded are skipped with a warning and left in place.
+    pub fn open(config: &QuarantineConfig) -> Result<Self, String> {
+        let mut entries = Entries::default();
+        if let Some(dir) = &config.dir {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+            for path in Self::event_files(dir)? {
+                let entry =
+                    fs::read_to_string(&path).map_err(|e| e.to_string()).and_then(|content| {
+                        serde_json::from_str::<QuarantinedEvent>(&content)
+                            .map_err(|e| e.to_string())
+                    });
+                match entry {
+                    Ok(entry) => entries.insert(entry),
+                    Err(err) => {
+                        tracing::warn!("Skipping quarantined event {}: {}", path.display(), err);
+                    }
+                }
+            }
+            entries.next_id = entries.by_id.keys().next_back().map_or(0, |id| id + 1);
+        }
+        for entry in entries.by_id.values_mut() {
+            // The tenant is not part of the event as sent by the source
+            entry.event.tenant = entry.tenant.clone();
+        }
+
+        let (files, writer) = match &config.dir {
+            Some(dir) => {
+                let (files, ops) = mpsc::channel();
+                let dir = dir.clone();
+                let writer = thread::Builder::new()
+                    .name("quarantine-writer".to_string())
+                    .spawn(move || write_files(&dir, ops))
+                    .map_err(|e| format!("Failed to start the quarantine writer: {}", e))?;
+                (Some(files), Some(writer))
+            }
+            None => (None, None),
+        };
+        let store = Self { capacity: config.capacity, entries: Mutex::new(entries), files, writer };
+        if let Ok(mut entries) = store.entries.lock() {
+            let tenants: Vec<TenantId> = entries.by_tenant.keys().cloned().collect();
+            for tenant in &tenants {
+                store.evict(&mut entries, tenant);
+            }
+            metrics::gauge!(QUARANTINE_EVENTS).set(entries.by_id.len() as f64);
+        }
+        Ok(store)
+    }
+
+    fn event_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+        let read_dir =
+            fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        Ok(read_dir
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect())
+    }
+
+    /// Evicts the oldest events of a tenant above the capacity.
+    fn evict(&self, entries: &mut Entries, tenant: &TenantId) {
+        while entries.by_tenant.get(tenant).is_some_and(|ids| ids.len() > self.capacity) {
+            let Some(id) = entries.by_tenant.get(tenant).and_then(|ids| ids.first().copied())
+            else {
+                break;
+            };
+            entries.remove(id);
+            tracing::warn!(id, %tenant, "Quarantine full, evicting oldest event of the tenant");
+            metrics::counter!(QUARANTINE_EVICTED_EVENTS_TOTAL).increment(1);
+            self.delete_file(id);
+        }
+    }
+
+    fn send_file_op(&self, op: FileOp) {
+        if let Some(files) = &self.files
+            && files.send(op).is_err()
+        {
+            tracing::error!("Quarantine writer is not running, event files are not updated");
+        }
+    }
+
+    fn write_file(&self, entry: &QuarantinedEvent) {
+        self.send_file_op(FileOp::Write(Box::new(entry.clone())));
+    }
+
+    fn delete_file(&self, id: u64) {
+        self.send_file_op(FileOp::Delete(id));
+    }
+
+    /// Quarantines a rejected event. Returns its id.
+    pub fn insert(
+        &self,
+        event: Event,
+        rejections: Vec<Rejection>,
+        received_at: DateTime<Utc>,
+        client_addr: Option<SocketAddr>,
+        redacted: bool,
+    ) -> Result<u64, String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+        // Ids grow with time, so ids of deleted events are not reused after a restart
+        let id = entries.next_id.max(Utc::now().timestamp_micros().unsigned_abs());
+        entries.next_id = id + 1;
+        let validator = rejections.first().map(|r| r.validator.clone()).unwrap_or_default();
+        let entry = QuarantinedEvent {
+            id,
+            tenant: event.tenant.clone(),
+            quarantined_at: Utc::now(),
+            received_at,
+            client_addr,
+            rejections,
+            event,
+            redacted,
+        };
+        self.write_file(&entry);
+        let tenant = entry.tenant.clone();
+        entries.insert(entry);
+        self.evict(&mut entries, &tenant);
+
+        metrics::counter!(QUARANTINED_EVENTS_TOTAL, "validator" => validator).increment(1);
+        metrics::gauge!(QUARANTINE_EVENTS).set(entries.by_id.len() as f64);
+        Ok(id)
+    }
+
+    /// Puts back an event taken out of the store, e.g. because it was rejected
+    /// again on re-submission.
+    pub fn restore(&self, entry: QuarantinedEvent) -> Result<(), String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+        self.write_file(&entry);
+        let tenant = entry.tenant.clone();
+        entries.insert(entry);
+        self.evict(&mut entries, &tenant);
+        metrics::gauge!(QUARANTINE_EVENTS).set(entries.by_id.len() as f64);
+        Ok(())
+    }
+
+    /// Events of a tenant, oldest first, and the total number of its events.
+    pub fn list(
+        &self,
+        tenant: &TenantId,
+        limit: usize,
+    ) -> Result<(Vec<QuarantinedEvent>, usize), String> {
+        let entries = self.entries.lock().map_err(|e| e.to_string())?;
+        let Some(ids) = entries.by_tenant.get(tenant) else {
+            return Ok((Vec::new(), 0));
+        };
+        let listed =
+            ids.iter().take(limit).filter_map(|id| entries.by_id.get(id)).cloned().collect();
+        Ok((listed, ids.len()))
+    }
+
+    /// An event of a tenant.
+    pub fn get(&self, tenant: &TenantId, id: u64) -> Result<Option<QuarantinedEvent>, String> {
+        let entries = self.entries.lock().map_err(|e| e.to_string())?;
+        Ok(entries.by_id.get(&id).filter(|entry| &entry.tenant == tenant).cloned())
+    }
+
+    /// Takes an event of a tenant out of the store.
+    pub fn remove(&self, tenant: &TenantId, id: u64) -> Result<Option<QuarantinedEvent>, String> {
+        let mut entries = self.entries.lock().map_err(|e| e.to_string())?;
+        if entries.by_id.get(&id).is_none_or(|entry| &entry.tenant != tenant) {
+            return Ok(None);
+        }
+        let entry = entries.remove(id);
+        self.delete_file(id);
+        metrics::gauge!(QUARANTINE_EVENTS).set(entries.by_id.len() as f64);
+        Ok(entry)
+    }
+
+    /// Ids of the events of a tenant, oldest first.
+    pub fn ids(&self, tenant: &TenantId) -> Result<Vec<u64>, String> {
+        let entries = self.entries.lock().map_err(|e| e.to_string())?;
+        Ok(entries
+            .by_tenant
+            .get(tenant)
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default())
+    }
+}
+
+impl Drop for QuarantineStore {
+    /// Waits for the writer thread to apply the pending file changes.
+    fn drop(&mut self) {
+        self.files.take();
+        if let Some(writer) = self.writer.take()
+            && writer.join().is_err()
+        {
+            tracing::error!("Quarantine writer panicked");
+        }
+    }
+}
+
+fn event_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{:020}.json", id))
+}
+
+/// Applies the file changes of a store until it is dropped.
+fn write_files(dir: &Path, ops: mpsc::Receiver<FileOp>) {
+    for op in ops {
+        match op {
+            FileOp::Write(entry) => {
+                let path = event_path(dir, entry.id);
+                let result = serde_json::to_string_pretty(&entry)
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| fs::write(&path, content).map_err(|e| e.to_string()));
+                if let Err(err) = result {
+                    tracing::error!(
+                        "Failed to write quarantined event to {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+            FileOp::Delete(id) => {
+                let path = event_path(dir, id);
+                if let Err(err) = fs::remove_file(&path)
+                    && err.kind() != std::io::ErrorKind::NotFound
+                {
+                    tracing::error!(
+                        "Failed to delete quarantined event {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::EventType;
+
+    fn create_event(tenant: &str) -> Event {
+        Event {
+            source_id: 1.into(),
+            r#type: EventType::Custom("FirmwareUpdate".to_string()),
+            timestamp: Utc::now(),
+            data: Some(serde_json::json!({ "version": "2.0" })),
+            labels: Default::default(),
+            tenant: TenantId::new(tenant),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    fn quarantine(store: &QuarantineStore, tenant: &str) -> u64 {
+        let rejections = vec![Rejection {
+            validator: "EventTypeValidator".to_string(),
+            reason: "Disallowed event type: FirmwareUpdate".to_string(),
+        }];
+        let Ok(id) = store.insert(create_event(tenant), rejections, Utc::now(), None, false) else {
+            panic!("event should be quarantined");
+        };
+        id
+    }
+
+    #[test]
+    fn test_evicts_oldest_and_scopes_by_tenant() {
+        let config = QuarantineConfig { enabled: true, capacity: 1, dir: None };
+        let Ok(store) = QuarantineStore::open(&config) else {
+            panic!("store should open");
+        };
+        let a = TenantId::new("a");
+        let first = quarantine(&store, "a");
+        let other = quarantine(&store, "b");
+        let second = quarantine(&store, "a");
+
+        assert!(matches!(store.get(&a, first), Ok(None)));
+        assert!(matches!(store.get(&a, second), Ok(Some(_))));
+        assert!(matches!(store.get(&TenantId::new("b"), other), Ok(Some(_))));
+        assert!(matches!(store.remove(&a, other), Ok(None)));
+        assert!(matches!(store.list(&a, 10), Ok((events, 1)) if events.len() == 1));
+        assert!(matches!(store.remove(&a, second), Ok(Some(entry)) if entry.id == second));
+        assert!(matches!(store.ids(&a), Ok(ids) if ids.is_empty()));
+    }
+
+    #[test]
+    fn test_reloads_events_from_dir() {
+        let dir =
+            std::env::temp_dir().join(format!("telemetron-quarantine-{}", std::process::id()));
+        let config = QuarantineConfig { enabled: true, capacity: 10, dir: Some(dir.clone()) };
+        let Ok(store) = QuarantineStore::open(&config) else {
+            panic!("store should open");
+        };
+        let kept = quarantine(&store, "a");
+        let deleted = quarantine(&store, "a");
+        let Ok(redacted) = store.insert(create_event("a"), Vec::new(), Utc::now(), None, true)
+        else {
+            panic!("event should be quarantined");
+        };
+        assert!(matches!(store.remove(&TenantId::new("a"), deleted), Ok(Some(_))));
+        // Waits for the files to be written
+        drop(store);
+
+        let Ok(reopened) = QuarantineStore::open(&config) else {
+            panic!("store should reopen");
+        };
+        let Ok(Some(entry)) = reopened.get(&TenantId::new("a"), kept) else {
+            panic!("event should be reloaded");
+        };
+        assert_eq!(entry.event.tenant, TenantId::new("a"));
+        assert_eq!(entry.event.data, Some(serde_json::json!({ "version": "2.0" })));
+        assert_eq!(entry.rejections.len(), 1);
+        assert!(!entry.redacted);
+        assert!(
+            matches!(reopened.get(&TenantId::new("a"), redacted), Ok(Some(entry)) if entry.redacted)
+        );
+        assert!(
+            matches!(reopened.ids(&TenantId::new("a")), Ok(ids) if ids == vec![kept, redacted])
+        );
+        assert!(quarantine(&reopened, "a") > deleted);
+        drop(reopened);
+
+        // A corrupt file doesn't keep the store from opening
+        let _ = fs::write(dir.join("corrupt.json"), "{");
+        assert!(
+            matches!(QuarantineStore::open(&config), Ok(store) if store.ids(&TenantId::new("a")).is_ok_and(|ids| ids.len() == 3))
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -1,120 +1,481 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use axum::{
-    Json, Router,
-    extract::{Path, State},
-    http::{HeaderValue, StatusCode, header},
+    Json, Router, ServiceExt,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware,
     response::IntoResponse,
     routing::{get, post},
 };
+use chrono::Utc;
 use dashmap::DashMap;
 use metrics_exporter_prometheus::PrometheusHandle;
-use tokio::{net::TcpListener, sync::mpsc};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+use tokio::net::TcpListener;
+use tower::Layer;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
 use tracing::Level;
 
 use crate::{
-    common_types::{EventProcessors, EventValidators},
-    config::Config,
+    common_types::{EventProcessors, EventTransformers, EventValidators},
+    config::{Config, FsyncPolicy, ValidationMode},
+    dlq::{Claim, DeadLetter, DeadLetterQueue},
     error::Error,
-    event::Event,
-    metrics::{HTTP_REQUESTS_DURATION_SECONDS, HTTP_REQUESTS_TOTAL},
-    processor::EventProcessorManager,
+    event::{Event, EventValidationError, PersistedEvent, SourceId},
+    metrics::{
+        HTTP_REQUESTS_DURATION_SECONDS, HTTP_REQUESTS_TOTAL, TENANT_EVENTS_TOTAL,
+        TRANSFORMER_DROPPED_EVENTS_TOTAL, WAL_REPLAYED_EVENTS_TOTAL, render_source_stats,
+    },
+    processing::{
+        heartbeat::HeartbeatMonitor,
+        source_telemetry::{LabelCounts, SourceTelemetry},
+        time_window::{StatsWindow, WindowCounts},
+    },
+    processor::{EventProcessorManager, EventRouter, SendError, replay_batch},
+    quarantine::{QuarantineStore, Rejection},
+    spill::SpillBuffer,
     state::AppState,
+    tenant::{self, SourceKey, TenantId, TenantRegistry},
+    transform::{RequestContext, Transformed, redaction::Redactor},
+    validation::managed::{ListChange, ListKind, ListStateFile, ManagedLists},
+    wal::WriteAheadLog,
 };
 
-/// Handler for the `/ingest` endpoint.
-/// It validates the incoming event using the configured validators and sends it
-/// to the channel.
-#[tracing::instrument(skip(state), fields(source_id = event.source_id))]
-async fn ingest_handler(
-    State(state): State<AppState>,
-    event: Json<Event>,
-) -> Result<impl IntoResponse, Error> {
-    let start = Instant::now();
-    tracing::info!("Ingest request");
+/// What became of an event that passed validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Admission {
+    Queued,
+    /// Dropped by a transformer
+    Dropped,
+}
 
-    // Increment the total events counter
-    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => "/ingest").increment(1);
-    let event = event.0;
+impl Admission {
+    fn message(self) -> &'static str {
+        match self {
+            Admission::Queued => "Success",
+            Admission::Dropped => "Dropped",
+        }
+    }
+}
 
+/// Runs the validators on an event. Returns the failures by validator name:
+/// the first one, or all of them in `collect_all` mode.
+async fn validate_event(
+    state: &AppState,
+    event: &Event,
+) -> Vec<(&'static str, EventValidationError)> {
+    let collect_all = state.config.validation.mode == ValidationMode::CollectAll;
+    let mut failures = Vec::new();
     for validator in state.validators.iter() {
         tracing::info!("Validating event with {}", validator.name());
-        if let Err(err) = validator.validate(&event) {
+        if let Err(err) = validator.validate_async(event).await {
             tracing::warn!("Event validation failed: {}", err);
-            metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/ingest", "status" => "4xx").record(start.elapsed());
-            return Err(Error::InvalidEvent(err));
+            failures.push((validator.name(), err));
+            if !collect_all {
+                break;
+            }
         }
     }
-    tracing::info!("Event validated successfully");
+    failures
+}
+
+/// Error response of a rejected event.
+fn rejection_error(
+    state: &AppState,
+    mut failures: Vec<(&'static str, EventValidationError)>,
+) -> Error {
+    match (state.config.validation.mode, failures.pop()) {
+        (ValidationMode::FailFast, Some((_, err))) => Error::InvalidEvent(err),
+        (_, last) => {
+            failures.extend(last);
+            Error::InvalidEventFailures(failures)
+        }
+    }
+}
+
+/// Keeps a rejected event in the quarantine, if enabled. Events a validator
+/// could not check are not quarantined, sources retry them.
+fn quarantine_event(
+    state: &AppState,
+    event: &Event,
+    failures: &[(&'static str, EventValidationError)],
+    context: &RequestContext,
+) {
+    let Some(quarantine) = &state.quarantine else {
+        return;
+    };
+    if failures
+        .iter()
+        .any(|(_, err)| matches!(err, EventValidationError::ValidatorUnavailable { .. }))
+    {
+        return;
+    }
+    // Personal data is redacted before the event is kept. The client address
+    // is not kept then, transformers could add it unredacted on re-submission.
+    let mut event = event.clone();
+    let redacted = match &state.redactor {
+        Some(redactor) => {
+            redactor.redact(&mut event);
+            true
+        }
+        None => false,
+    };
+    let client_addr = if redacted { None } else { context.client_addr };
+    let rejections = Rejection::from_failures(failures);
+    match quarantine.insert(event, rejections, context.received_at, client_addr, redacted) {
+        Ok(id) => tracing::info!(id, "Rejected event quarantined"),
+        Err(err) => tracing::error!("Failed to quarantine event: {}", err),
+    }
+}
+
+/// Runs the transformers and the redaction on a validated event, admits it for
+/// its tenant and sends it to the channel. A `redacted` event, re-submitted
+/// from the quarantine, is not redacted again.
+async fn enqueue_event(
+    state: &AppState,
+    mut event: Event,
+    context: &RequestContext,
+    redacted: bool,
+) -> Result<Admission, Error> {
+    for transformer in state.transformers.iter() {
+        if let Transformed::Drop(reason) = transformer.transform(&mut event, context) {
+            tracing::info!("Event dropped by {}: {}", transformer.name(), reason);
+            metrics::counter!(TRANSFORMER_DROPPED_EVENTS_TOTAL, "transformer" => transformer.name())
+                .increment(1);
+            return Ok(Admission::Dropped);
+        }
+    }
+    for validator in state.validators.iter() {
+        if let Err(err) = validator.validate_transformed(&event) {
+            tracing::warn!("Transformed event rejected by {}: {}", validator.name(), err);
+            return Err(err.into());
+        }
+    }
+    if let Some(redactor) = state.redactor.as_ref().filter(|_| !redacted) {
+        redactor.redact(&mut event);
+    }
+    // Processors read the payload parsed on ingestion, transformers and the
+    // redaction may have changed `data` since
+    if (!state.transformers.is_empty() || state.redactor.is_some())
+        && let Err(err) = event.parse_payload()
+    {
+        tracing::warn!("Transformed event rejected: {}", err);
+        return Err(Error::BadRequest(err.to_string()));
+    }
+
+    let reservation = match state.tenants.admit(&event.tenant, &event) {
+        Ok(reservation) => reservation,
+        Err(rejection) => {
+            tracing::warn!("Event rejected for tenant {}: {}", event.tenant, rejection);
+            return Err(rejection.into());
+        }
+    };
+    let tenant = event.tenant.to_string();
 
     match state.sender.send(event).await {
         Ok(_) => {
+            reservation.commit();
             tracing::info!("Event sent to channel");
-            metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/ingest", "status" => "2xx").record(start.elapsed());
-            Ok((StatusCode::ACCEPTED, "Success"))
+            metrics::counter!(TENANT_EVENTS_TOTAL, "tenant" => tenant).increment(1);
+            Ok(Admission::Queued)
         }
+        Err(err @ SendError::SpillFull) => Err(Error::ServiceUnavailable(err.to_string())),
         Err(err) => {
             tracing::error!("Failed to send event to channel: {}", err);
-            metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/ingest", "status" => "5xx").record(start.elapsed());
             Err(Error::Internal("Failed to send event to channel".into()))
         }
     }
 }
 
+/// Status class of a response, for the request duration metric.
+fn status_class<T>(result: &Result<T, Error>) -> &'static str {
+    match result {
+        Ok(_) => "2xx",
+        Err(
+            Error::Internal(_)
+            | Error::Io(_)
+            | Error::Server(_)
+            | Error::BadGateway(_)
+            | Error::ServiceUnavailable(_),
+        ) => "5xx",
+        Err(Error::InvalidEvent(EventValidationError::ValidatorUnavailable { .. })) => "5xx",
+        Err(Error::InvalidEventFailures(failures))
+            if failures
+                .iter()
+                .any(|(_, e)| matches!(e, EventValidationError::ValidatorUnavailable { .. })) =>
+        {
+            "5xx"
+        }
+        Err(_) => "4xx",
+    }
+}
+
+/// Handler for the `/ingest` endpoint.
+/// It validates the incoming event using the configured validators, runs the
+/// configured transformers and sends it to the channel. Rejected events are
+/// quarantined if enabled.
+#[tracing::instrument(
+    skip(state, headers, event),
+    fields(tenant = %tenant, source_id = %event.source_id)
+)]
+async fn ingest_handler(
+    State(state): State<AppState>,
+    ConnectInfo(client_addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    tenant: TenantId,
+    event: Json<Event>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    let received_at = Utc::now();
+    tracing::info!("Ingest request");
+
+    // Increment the total events counter
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => "/ingest").increment(1);
+    let mut event = event.0;
+    event.tenant = tenant;
+
+    if let Err(err) = state.config.source_ids.check(&event.source_id) {
+        tracing::warn!("Event source id rejected: {}", err);
+        metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/ingest", "status" => "4xx").record(start.elapsed());
+        return Err(Error::InvalidEvent(err));
+    }
+
+    let context = RequestContext { received_at, client_addr: Some(client_addr), headers };
+    let failures = validate_event(&state, &event).await;
+    if !failures.is_empty() {
+        quarantine_event(&state, &event, &failures, &context);
+        metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/ingest", "status" => "4xx").record(start.elapsed());
+        return Err(rejection_error(&state, failures));
+    }
+    tracing::info!("Event validated successfully");
+    if let Some(monitor) = &state.heartbeat {
+        monitor.record(&event, received_at);
+    }
+
+    let result = enqueue_event(&state, event, &context, false).await;
+    metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/ingest", "status" => status_class(&result))
+        .record(start.elapsed());
+    result.map(|admission| (StatusCode::ACCEPTED, admission.message()))
+}
+
+/// Prefix of query parameters that refer to event labels.
+const LABEL_PARAM_PREFIX: &str = "label.";
+
+/// Query parameters accepted by the stats endpoints.
+#[derive(Debug, Deserialize)]
+struct StatsQuery {
+    /// Rolling window to report, e.g. `5m` or `1h`
+    window: Option<String>,
+    /// Label to group counts by, e.g. `label.region`
+    group_by: Option<String>,
+    /// Label filters, e.g. `label.region=eu`
+    #[serde(flatten)]
+    label_filters: HashMap<String, String>,
+}
+
+/// Label filters and grouping requested on a stats endpoint.
+#[derive(Debug)]
+struct LabelQuery {
+    filters: Vec<(String, String)>,
+    group_by: Option<String>,
+}
+
+impl LabelQuery {
+    /// Counts the events of a source matching the query.
+    fn count(&self, telemetry: &SourceTelemetry) -> LabelCounts {
+        telemetry.count_by_labels(&self.filters, self.group_by.as_deref())
+    }
+
+    fn to_json(&self, counts: &LabelCounts) -> serde_json::Value {
+        let filters: HashMap<&str, &str> =
+            self.filters.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+        serde_json::json!({
+            "filters": filters,
+            "group_by": self.group_by,
+            "events_count": counts.events_count,
+            "groups": counts.groups,
+        })
+    }
+}
+
+impl StatsQuery {
+    fn label_query(&self) -> Result<Option<LabelQuery>, Error> {
+        let strip_label_prefix = |param: &str| {
+            param
+                .strip_prefix(LABEL_PARAM_PREFIX)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+                .ok_or_else(|| Error::BadRequest(format!("Unknown query parameter: {}", param)))
+        };
+
+        let group_by = self.group_by.as_deref().map(strip_label_prefix).transpose()?;
+        let mut filters = self
+            .label_filters
+            .iter()
+            .map(|(param, value)| Ok((strip_label_prefix(param)?, value.clone())))
+            .collect::<Result<Vec<_>, Error>>()?;
+        filters.sort();
+
+        if group_by.is_none() && filters.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(LabelQuery { filters, group_by }))
+    }
+
+    fn window(&self) -> Result<Option<StatsWindow>, Error> {
+        self.window
+            .as_deref()
+            .map(|window| {
+                window.parse::<StatsWindow>().map_err(|e| Error::BadRequest(e.to_string()))
+            })
+            .transpose()
+    }
+}
+
 /// Handler for the `/stats` endpoint.
-/// It returns the total number of sources and events processed.
-async fn stats_handler(State(state): State<AppState>) -> Result<impl IntoResponse, Error> {
+/// It returns the total number of sources and events processed for the
+/// tenant, and the counts within the requested rolling window if any.
+async fn stats_handler(
+    State(state): State<AppState>,
+    tenant: TenantId,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, Error> {
     let start = Instant::now();
     metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => "/stats").increment(1);
 
     tracing::info!("Stats");
 
-    let sources_count = state.telemetry_map.len();
-    let events_count: u64 =
-        state.telemetry_map.iter().map(|entry| entry.value().total_events).sum();
+    let (window, label_query) = match query.window().and_then(|w| Ok((w, query.label_query()?))) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/stats", "status" => "4xx")
+                .record(start.elapsed());
+            return Err(err);
+        }
+    };
+
+    let tenant_entries = || state.telemetry_map.iter().filter(|entry| entry.key().tenant == tenant);
+    let sources_count = tenant_entries().count();
+    let events_count: u64 = tenant_entries().map(|entry| entry.value().total_events).sum();
 
     // TODO: add more stats
-    let stats = Json(serde_json::json!({
+    let mut stats = serde_json::json!({
         "sources_count": sources_count,
         "events_count": events_count,
-    }));
+    });
+
+    if let Some(window) = window {
+        let now = Utc::now();
+        let mut counts = WindowCounts::default();
+        let mut active_sources = 0;
+        for entry in tenant_entries() {
+            let source_counts = entry.value().windows.window(window, now);
+            if source_counts.total_events > 0 {
+                active_sources += 1;
+            }
+            counts.merge(&source_counts);
+        }
+        stats["window"] = serde_json::json!({
+            "duration": window.to_string(),
+            "active_sources": active_sources,
+            "events_count": counts.total_events,
+            "event_types": counts.event_types,
+            "other_types": counts.other_types,
+        });
+    }
+
+    if let Some(label_query) = label_query {
+        let mut counts = LabelCounts::default();
+        let mut matching_sources = 0;
+        for entry in tenant_entries() {
+            let source_counts = label_query.count(entry.value());
+            if source_counts.events_count > 0 {
+                matching_sources += 1;
+            }
+            counts.merge(&source_counts);
+        }
+        stats["labels"] = label_query.to_json(&counts);
+        stats["labels"]["sources_count"] = matching_sources.into();
+    }
 
     metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/stats", "status" => "2xx")
         .record(start.elapsed());
 
-    Ok(stats)
+    Ok(Json(stats))
 }
 
 /// Handler for the `/stats/{source_id}` endpoint.
-/// It returns the telemetry data for the specified source id.
+/// It returns the telemetry data for the specified source id of the tenant.
 async fn stats_by_source_id_handler(
     State(state): State<AppState>,
-    Path(source_id): Path<u64>,
+    tenant: TenantId,
+    Path(source_id): Path<String>,
+    Query(query): Query<StatsQuery>,
 ) -> Result<impl IntoResponse, Error> {
     let start = Instant::now();
     metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => "/stats/{source_id}").increment(1);
     tracing::info!("Stats by source id: {}", source_id);
 
-    let entry = state.telemetry_map.get(&source_id);
+    let parsed = source_id
+        .parse::<SourceId>()
+        .map_err(|e| Error::BadRequest(e.to_string()))
+        .and_then(|source_id| Ok((source_id, query.window()?, query.label_query()?)));
+    let (source_id, window, label_query) = match parsed {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            metrics::histogram!(
+                HTTP_REQUESTS_DURATION_SECONDS,
+                "endpoint" => "/stats/{source_id}",
+                "status" => "4xx"
+            )
+            .record(start.elapsed());
+            return Err(err);
+        }
+    };
+
+    let entry = state.telemetry_map.get(&SourceKey::new(tenant, source_id.clone()));
 
     match entry {
         Some(entry) => {
             let telemetry = entry.value();
-            let stats = Json(serde_json::json!({
+            let mut stats = serde_json::json!({
                 "source_id": source_id,
                 "total_events": telemetry.total_events,
                 "first_event": telemetry.first_timestamp,
                 "last_event": telemetry.last_timestamp,
                 "event_types": telemetry.events_by_type,
-            }));
+                "metrics": telemetry.metrics,
+                "other_metrics": telemetry.other_metrics,
+                "logs_by_level": telemetry.logs_by_level,
+                "errors_by_code": telemetry.errors_by_code,
+                "other_errors": telemetry.other_errors,
+            });
+            if let Some(window) = window {
+                let counts = telemetry.windows.window(window, Utc::now());
+                stats["window"] = serde_json::json!({
+                    "duration": window.to_string(),
+                    "total_events": counts.total_events,
+                    "event_types": counts.event_types,
+                    "other_types": counts.other_types,
+                });
+            }
+            if let Some(label_query) = label_query {
+                stats["labels"] = label_query.to_json(&label_query.count(telemetry));
+            }
             metrics::histogram!(
                 HTTP_REQUESTS_DURATION_SECONDS,
                 "endpoint" => "/stats/{source_id}",
                 "status" => "2xx"
             )
             .record(start.elapsed());
-            Ok(stats)
+            Ok(Json(stats))
         }
         None => {
             tracing::warn!("Source id {} not found", source_id);
@@ -129,6 +490,553 @@ async fn stats_by_source_id_handler(
     }
 }
 
+/// Handler for the `/sources/down` endpoint.
+/// It returns the sources of the tenant the heartbeat monitor currently
+/// considers down.
+async fn sources_down_handler(
+    State(state): State<AppState>,
+    tenant: TenantId,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => "/sources/down").increment(1);
+    tracing::info!("Down sources");
+
+    let Some(monitor) = &state.heartbeat else {
+        metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/sources/down", "status" => "4xx")
+            .record(start.elapsed());
+        return Err(Error::NotFound("Heartbeat monitor is not enabled".to_string()));
+    };
+
+    let sources = monitor.down_sources(Some(&tenant));
+    let body = Json(serde_json::json!({
+        "sources_count": sources.len(),
+        "sources": sources,
+    }));
+
+    metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => "/sources/down", "status" => "2xx")
+        .record(start.elapsed());
+
+    Ok(body)
+}
+
+/// Endpoint label of the admin list endpoints.
+const ADMIN_LISTS_ENDPOINT: &str = "/admin/validators/{validator}/{list}";
+
+/// Checks that an admin request may proceed. The admin API looks disabled
+/// unless it is enabled in the config, and requires the configured bearer
+/// token if any.
+fn check_admin(state: &AppState, headers: &HeaderMap) -> Result<(), Error> {
+    let admin = &state.config.admin;
+    if !admin.enabled {
+        return Err(Error::NotFound("Admin API is not enabled".to_string()));
+    }
+    match &admin.token {
+        Some(token) => check_admin_token(token, headers),
+        None => Ok(()),
+    }
+}
+
+/// Checks the bearer token of a request against the admin token.
+fn check_admin_token(token: &str, headers: &HeaderMap) -> Result<(), Error> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // Compare in constant time so the token can't be guessed byte by byte
+    let valid = bearer.is_some_and(|bearer| bool::from(bearer.as_bytes().ct_eq(token.as_bytes())));
+    if !valid {
+        return Err(Error::Unauthorized("Missing or invalid admin token".to_string()));
+    }
+    Ok(())
+}
+
+/// Resolves the managed list addressed by an admin request.
+fn admin_list<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+    validator: &str,
+    list: &str,
+) -> Result<(&'a dyn ManagedLists, ListKind), Error> {
+    check_admin(state, headers)?;
+
+    let kind = list.parse::<ListKind>().map_err(Error::BadRequest)?;
+    let lists = state
+        .validators
+        .iter()
+        .find(|v| v.name() == validator)
+        .and_then(|v| v.lists())
+        .ok_or_else(|| {
+            Error::NotFound(format!(
+                "Validator {} is not enabled or has no managed lists",
+                validator
+            ))
+        })?;
+    Ok((lists, kind))
+}
+
+/// Effective entries of a list and its runtime changes.
+fn list_json(lists: &dyn ManagedLists, validator: &str, kind: ListKind) -> serde_json::Value {
+    let overrides = lists.overrides().remove(&kind).unwrap_or_default();
+    serde_json::json!({
+        "validator": validator,
+        "list": kind,
+        "entries": lists.entries(kind),
+        "added": overrides.added,
+        "removed": overrides.removed,
+    })
+}
+
+/// Records the duration of an admin request by its outcome.
+fn record_admin_request<T>(endpoint: &'static str, start: Instant, result: &Result<T, Error>) {
+    metrics::histogram!(HTTP_REQUESTS_DURATION_SECONDS, "endpoint" => endpoint, "status" => status_class(result))
+        .record(start.elapsed());
+}
+
+/// Handler for `GET /admin/validators/{validator}/{list}`.
+/// It returns the effective entries of an allow or deny list of a validator.
+async fn admin_get_list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((validator, list)): Path<(String, String)>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => ADMIN_LISTS_ENDPOINT).increment(1);
+    tracing::info!("Admin list {} of {}", list, validator);
+
+    let result = admin_list(&state, &headers, &validator, &list)
+        .map(|(lists, kind)| Json(list_json(lists, &validator, kind)));
+    record_admin_request(ADMIN_LISTS_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `POST /admin/validators/{validator}/{list}`.
+/// It adds and removes entries of an allow or deny list of a validator in one
+/// atomic change, and persists the changes to the state file if configured.
+async fn admin_update_list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((validator, list)): Path<(String, String)>,
+    Json(change): Json<ListChange>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => ADMIN_LISTS_ENDPOINT).increment(1);
+    tracing::info!(?change, "Admin update of list {} of {}", list, validator);
+
+    let result = admin_list(&state, &headers, &validator, &list).and_then(|(lists, kind)| {
+        lists.update(kind, &change).map_err(Error::BadRequest)?;
+        if let Some(list_state) = &state.list_state {
+            list_state.save(&state.validators).map_err(Error::Internal)?;
+        }
+        Ok(Json(list_json(lists, &validator, kind)))
+    });
+    record_admin_request(ADMIN_LISTS_ENDPOINT, start, &result);
+    result
+}
+
+/// Endpoint labels of the quarantine endpoints.
+const QUARANTINE_ENDPOINT: &str = "/admin/quarantine";
+const QUARANTINE_EVENT_ENDPOINT: &str = "/admin/quarantine/{id}";
+const QUARANTINE_RESUBMIT_ENDPOINT: &str = "/admin/quarantine/{id}/resubmit";
+const QUARANTINE_RESUBMIT_ALL_ENDPOINT: &str = "/admin/quarantine/resubmit";
+
+/// Default number of events returned by `GET /admin/quarantine`.
+const DEFAULT_QUARANTINE_LIMIT: usize = 100;
+
+/// Query parameters accepted by the quarantine list endpoint.
+#[derive(Debug, Deserialize)]
+struct QuarantineQuery {
+    limit: Option<usize>,
+}
+
+/// Resolves the quarantine of an admin request.
+fn admin_quarantine<'a>(
+    state: &'a AppState,
+    headers: &HeaderMap,
+) -> Result<&'a QuarantineStore, Error> {
+    check_admin(state, headers)?;
+    state
+        .quarantine
+        .as_deref()
+        .ok_or_else(|| Error::NotFound("Quarantine is not enabled".to_string()))
+}
+
+fn quarantined_not_found(id: u64) -> Error {
+    Error::NotFound(format!("Quarantined event {} not found", id))
+}
+
+/// Takes a quarantined event out of the store and runs it through the
+/// validators and transformers again. An event that is rejected again, or
+/// can't be queued, is put back.
+async fn resubmit_event(
+    state: &AppState,
+    quarantine: &QuarantineStore,
+    tenant: &TenantId,
+    id: u64,
+) -> Result<Admission, Error> {
+    let mut entry = quarantine
+        .remove(tenant, id)
+        .map_err(Error::Internal)?
+        .ok_or_else(|| quarantined_not_found(id))?;
+
+    let failures = validate_event(state, &entry.event).await;
+    if !failures.is_empty() {
+        tracing::info!(id, "Re-submitted event rejected again");
+        entry.rejections = Rejection::from_failures(&failures);
+        quarantine.restore(entry).map_err(Error::Internal)?;
+        return Err(rejection_error(state, failures));
+    }
+
+    // The request headers are not kept, transformers only see the client
+    // address, and only of events kept unredacted
+    let context = RequestContext {
+        received_at: entry.received_at,
+        client_addr: entry.client_addr,
+        headers: HeaderMap::new(),
+    };
+    match enqueue_event(state, entry.event.clone(), &context, entry.redacted).await {
+        Ok(admission) => {
+            tracing::info!(id, "Re-submitted event accepted");
+            Ok(admission)
+        }
+        Err(err) => {
+            quarantine.restore(entry).map_err(Error::Internal)?;
+            Err(err)
+        }
+    }
+}
+
+/// Handler for `GET /admin/quarantine`.
+/// It returns the oldest quarantined events of the tenant.
+async fn quarantine_list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    tenant: TenantId,
+    Query(query): Query<QuarantineQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => QUARANTINE_ENDPOINT).increment(1);
+    tracing::info!("Quarantine list");
+
+    let limit = query.limit.unwrap_or(DEFAULT_QUARANTINE_LIMIT);
+    let result = admin_quarantine(&state, &headers).and_then(|quarantine| {
+        let (events, total) = quarantine.list(&tenant, limit).map_err(Error::Internal)?;
+        Ok(Json(serde_json::json!({ "total": total, "events": events })))
+    });
+    record_admin_request(QUARANTINE_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `GET /admin/quarantine/{id}`.
+/// It returns a quarantined event with its rejections.
+async fn quarantine_get_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    tenant: TenantId,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => QUARANTINE_EVENT_ENDPOINT).increment(1);
+    tracing::info!("Quarantined event {}", id);
+
+    let result = admin_quarantine(&state, &headers).and_then(|quarantine| {
+        let entry = quarantine.get(&tenant, id).map_err(Error::Internal)?;
+        entry.map(Json).ok_or_else(|| quarantined_not_found(id))
+    });
+    record_admin_request(QUARANTINE_EVENT_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `DELETE /admin/quarantine/{id}`.
+/// It discards a quarantined event.
+async fn quarantine_delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    tenant: TenantId,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => QUARANTINE_EVENT_ENDPOINT).increment(1);
+    tracing::info!("Delete quarantined event {}", id);
+
+    let result = admin_quarantine(&state, &headers).and_then(|quarantine| {
+        let entry = quarantine.remove(&tenant, id).map_err(Error::Internal)?;
+        entry.map(|_| StatusCode::NO_CONTENT).ok_or_else(|| quarantined_not_found(id))
+    });
+    record_admin_request(QUARANTINE_EVENT_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `POST /admin/quarantine/{id}/resubmit`.
+/// It runs a quarantined event through validation again, e.g. after an
+/// allowlist was fixed, and queues it if it passes.
+async fn quarantine_resubmit_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    tenant: TenantId,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => QUARANTINE_RESUBMIT_ENDPOINT).increment(1);
+    tracing::info!("Re-submit quarantined event {}", id);
+
+    let result = match admin_quarantine(&state, &headers) {
+        Ok(quarantine) => resubmit_event(&state, quarantine, &tenant, id).await,
+        Err(err) => Err(err),
+    };
+    record_admin_request(QUARANTINE_RESUBMIT_ENDPOINT, start, &result);
+    result.map(|admission| (StatusCode::ACCEPTED, admission.message()))
+}
+
+/// Handler for `POST /admin/quarantine/resubmit`.
+/// It re-submits all quarantined events of the tenant, oldest first, and
+/// returns how many were queued, dropped and rejected again.
+async fn quarantine_resubmit_all_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    tenant: TenantId,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => QUARANTINE_RESUBMIT_ALL_ENDPOINT)
+        .increment(1);
+    tracing::info!("Re-submit all quarantined events");
+
+    let result = async {
+        let quarantine = admin_quarantine(&state, &headers)?;
+        let (mut queued, mut dropped, mut rejected) = (0, 0, 0);
+        for id in quarantine.ids(&tenant).map_err(Error::Internal)? {
+            match resubmit_event(&state, quarantine, &tenant, id).await {
+                Ok(Admission::Queued) => queued += 1,
+                Ok(Admission::Dropped) => dropped += 1,
+                // Taken by a concurrent request
+                Err(Error::NotFound(_)) => {}
+                Err(Error::InvalidEvent(_) | Error::InvalidEventFailures(_)) => rejected += 1,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Json(serde_json::json!({ "queued": queued, "dropped": dropped, "rejected": rejected })))
+    }
+    .await;
+    record_admin_request(QUARANTINE_RESUBMIT_ALL_ENDPOINT, start, &result);
+    result
+}
+
+/// Endpoint labels of the DLQ endpoints.
+const DLQ_ENDPOINT: &str = "/admin/dlq";
+const DLQ_ENTRY_ENDPOINT: &str = "/admin/dlq/{id}";
+const DLQ_REPLAY_ENDPOINT: &str = "/admin/dlq/{id}/replay";
+
+/// Default number of batches returned by `GET /admin/dlq`.
+const DEFAULT_DLQ_LIMIT: usize = 100;
+
+/// Query parameters accepted by the DLQ endpoints.
+#[derive(Debug, Deserialize)]
+struct DlqQuery {
+    limit: Option<usize>,
+    /// Only batches of this plugin, or for a replay the plugin to replay into
+    plugin: Option<String>,
+}
+
+/// Resolves the DLQ of an admin request.
+fn admin_dlq(state: &AppState, headers: &HeaderMap) -> Result<Arc<DeadLetterQueue>, Error> {
+    check_admin(state, headers)?;
+    state.dlq.clone().ok_or_else(|| Error::NotFound("DLQ is not enabled".to_string()))
+}
+
+fn dead_letter_not_found(id: u64) -> Error {
+    Error::NotFound(format!("DLQ batch {} not found", id))
+}
+
+/// Claims a batch and replays it into a plugin, by default the one it failed
+/// in, and deletes it from the DLQ once processed. A batch that fails again
+/// stays in the DLQ; if only some events fail, they replace it as new
+/// batches. While a batch is replayed, another replay of it is refused.
+async fn replay_dead_letter(
+    state: &AppState,
+    dlq: Arc<DeadLetterQueue>,
+    id: u64,
+    plugin: Option<&str>,
+) -> Result<serde_json::Value, Error> {
+    let entry = match dlq.blocking(move |dlq| dlq.claim(id)).await.map_err(Error::Internal)? {
+        Claim::Claimed(entry) => entry,
+        Claim::InProgress => {
+            return Err(Error::Conflict(format!("DLQ batch {} is being replayed", id)));
+        }
+        Claim::NotFound => return Err(dead_letter_not_found(id)),
+    };
+    let result = replay_claimed(state, &dlq, entry, plugin).await;
+    if result.is_err() {
+        // The batch, or what is left of it, can be replayed again
+        if let Err(err) = dlq.blocking(move |dlq| dlq.release(id)).await {
+            tracing::error!(id, "Failed to release DLQ batch: {}", err);
+        }
+    }
+    result
+}
+
+async fn replay_claimed(
+    state: &AppState,
+    dlq: &Arc<DeadLetterQueue>,
+    entry: DeadLetter,
+    plugin: Option<&str>,
+) -> Result<serde_json::Value, Error> {
+    let id = entry.id;
+    let name = plugin.unwrap_or(&entry.plugin).to_string();
+    let plugin = state
+        .processors
+        .iter()
+        .find(|plugin| plugin.processor.name() == name)
+        .ok_or_else(|| Error::NotFound(format!("Processor plugin {} is not enabled", name)))?;
+
+    let events: Vec<Event> = entry.events.into_iter().map(PersistedEvent::into_event).collect();
+    let failures = replay_batch(plugin, &state.telemetry_map, &events, &state.config).await;
+    let failed: usize = failures.iter().map(|failure| failure.events.len()).sum();
+    let Some(first) = failures.first() else {
+        dlq.blocking(move |dlq| dlq.remove(&[id])).await.map_err(Error::Internal)?;
+        tracing::info!(id, plugin = name, "Replayed DLQ batch");
+        return Ok(serde_json::json!({ "id": id, "plugin": name, "events": events.len() }));
+    };
+    let message = format!(
+        "Replay into {} failed for {} of {} event(s) after {} attempt(s): {}",
+        name,
+        failed,
+        events.len(),
+        first.attempts,
+        first.error
+    );
+    if failed == events.len() {
+        return Err(Error::BadGateway(message));
+    }
+
+    // Keep only the events that failed again, the others must not be replayed twice
+    let mut kept = Vec::new();
+    for failure in failures {
+        let failed: Vec<Event> =
+            failure.events.iter().filter_map(|&i| events.get(i).cloned()).collect();
+        let plugin = name.clone();
+        kept.push(
+            dlq.blocking(move |dlq| dlq.push(&plugin, &failed, &failure.error, failure.attempts))
+                .await
+                .map_err(Error::Internal)?,
+        );
+    }
+    dlq.blocking(move |dlq| dlq.remove(&[id])).await.map_err(Error::Internal)?;
+    tracing::warn!(id, plugin = name, ?kept, "Partially replayed DLQ batch");
+    Err(Error::BadGateway(format!("{}; failed events kept as batch(es) {:?}", message, kept)))
+}
+
+/// Handler for `GET /admin/dlq`.
+/// It returns the oldest batches in the DLQ, without their events.
+async fn dlq_list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DlqQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => DLQ_ENDPOINT).increment(1);
+    tracing::info!("DLQ list");
+
+    let limit = query.limit.unwrap_or(DEFAULT_DLQ_LIMIT);
+    let result = match admin_dlq(&state, &headers) {
+        Ok(dlq) => dlq
+            .blocking(move |dlq| dlq.list(query.plugin.as_deref(), limit))
+            .await
+            .map(|(batches, total)| Json(serde_json::json!({ "total": total, "batches": batches })))
+            .map_err(Error::Internal),
+        Err(err) => Err(err),
+    };
+    record_admin_request(DLQ_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `DELETE /admin/dlq`.
+/// It purges all batches, or those of a plugin, and returns how many.
+async fn dlq_purge_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DlqQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => DLQ_ENDPOINT).increment(1);
+    tracing::info!(plugin = query.plugin, "DLQ purge");
+
+    let result = match admin_dlq(&state, &headers) {
+        Ok(dlq) => dlq
+            .blocking(move |dlq| dlq.remove(&dlq.ids(query.plugin.as_deref())?))
+            .await
+            .map(|purged| Json(serde_json::json!({ "purged": purged })))
+            .map_err(Error::Internal),
+        Err(err) => Err(err),
+    };
+    record_admin_request(DLQ_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `GET /admin/dlq/{id}`.
+/// It returns a batch in the DLQ with its events and error chain.
+async fn dlq_get_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => DLQ_ENTRY_ENDPOINT).increment(1);
+    tracing::info!("DLQ batch {}", id);
+
+    let result = match admin_dlq(&state, &headers) {
+        Ok(dlq) => match dlq.blocking(move |dlq| dlq.get(id)).await {
+            Ok(entry) => entry.map(Json).ok_or_else(|| dead_letter_not_found(id)),
+            Err(err) => Err(Error::Internal(err)),
+        },
+        Err(err) => Err(err),
+    };
+    record_admin_request(DLQ_ENTRY_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `DELETE /admin/dlq/{id}`.
+/// It purges a batch from the DLQ.
+async fn dlq_delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => DLQ_ENTRY_ENDPOINT).increment(1);
+    tracing::info!("Delete DLQ batch {}", id);
+
+    let result = match admin_dlq(&state, &headers) {
+        Ok(dlq) => match dlq.blocking(move |dlq| dlq.remove(&[id])).await {
+            Ok(0) => Err(dead_letter_not_found(id)),
+            Ok(_) => Ok(StatusCode::NO_CONTENT),
+            Err(err) => Err(Error::Internal(err)),
+        },
+        Err(err) => Err(err),
+    };
+    record_admin_request(DLQ_ENTRY_ENDPOINT, start, &result);
+    result
+}
+
+/// Handler for `POST /admin/dlq/{id}/replay`.
+/// It processes a batch in the DLQ again with the plugin it failed in, or the
+/// one given by `?plugin=`, e.g. once the sink is back.
+async fn dlq_replay_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+    Query(query): Query<DlqQuery>,
+) -> Result<impl IntoResponse, Error> {
+    let start = Instant::now();
+    metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => DLQ_REPLAY_ENDPOINT).increment(1);
+    tracing::info!(plugin = query.plugin, "Replay DLQ batch {}", id);
+
+    let result = match admin_dlq(&state, &headers) {
+        Ok(dlq) => replay_dead_letter(&state, dlq, id, query.plugin.as_deref()).await,
+        Err(err) => Err(err),
+    };
+    record_admin_request(DLQ_REPLAY_ENDPOINT, start, &result);
+    result.map(Json)
+}
+
 /// Handler for the `/404` endpoint.
 async fn not_found_handler() -> impl IntoResponse {
     metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => "/404").increment(1);
@@ -139,15 +1047,31 @@ async fn not_found_handler() -> impl IntoResponse {
 
 /// Handler for the `/metrics` endpoint.
 /// It returns the Prometheus metrics in the OpenMetrics format.
-async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+async fn metrics_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Error> {
     metrics::counter!(HTTP_REQUESTS_TOTAL, "endpoint" => "/metrics").increment(1);
 
-    let body = state.prometheus_handle.render();
+    let source_stats_config = &state.config.metrics.source_stats;
+    // Per-source series expose the source ids of all tenants
+    if source_stats_config.enabled && state.tenants.is_enabled() {
+        let Some(token) = &state.config.admin.token else {
+            return Err(Error::Unauthorized("Admin token is not configured".to_string()));
+        };
+        check_admin_token(token, &headers)?;
+    }
+
+    let mut body = state.prometheus_handle.render();
+
+    if source_stats_config.enabled {
+        body.push_str(&render_source_stats(&state.telemetry_map, source_stats_config, Utc::now()));
+    }
     let headers = [(
         header::CONTENT_TYPE,
         HeaderValue::from_static("application/openmetrics-text; version=1.0.0; charset=utf-8"),
     )];
-    (StatusCode::OK, headers, body)
+    Ok((StatusCode::OK, headers, body))
 }
 
 /// Handler for the `/healthz` endpoint.
@@ -195,35 +1119,179 @@ async fn wait_for_shutdown() {
 pub async fn run_server(
     config: Arc<Config>,
     validators: EventValidators,
+    transformers: EventTransformers,
     processors: EventProcessors,
     prometheus_handle: PrometheusHandle,
 ) -> Result<(), Error> {
     tracing::info!("Starting Telemetron");
 
+    // Log accepted events before they are queued, replaying the ones not
+    // processed before the last shutdown or crash
+    let (wal, wal_replay) = if config.wal.enabled {
+        let (wal, replay) = WriteAheadLog::open(&config.wal).map_err(Error::Internal)?;
+        tracing::info!("WAL enabled in {}", config.wal.dir.display());
+        (Some(Arc::new(wal)), replay)
+    } else {
+        (None, Vec::new())
+    };
+    if let Some(wal) = wal.clone().filter(|_| config.wal.fsync == FsyncPolicy::Interval) {
+        let period = Duration::from_millis(config.wal.fsync_interval.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(err) = wal.sync().await {
+                    tracing::error!("{}", err);
+                }
+            }
+        });
+    }
+
+    // Spill events to disk when the channel of their worker is full
+    let spill = if config.spill.enabled {
+        let spill =
+            SpillBuffer::open(&config.spill, config.processor.workers).map_err(Error::Internal)?;
+        tracing::info!("Spilling events to {} when channels are full", config.spill.dir.display());
+        Some(spill)
+    } else {
+        None
+    };
+
     // Create a channel for sending events
-    let (sender, receiver) = mpsc::channel::<Event>(config.processor.channel_capacity);
+    let (sender, receivers) = EventRouter::channel(
+        config.processor.workers,
+        config.processor.channel_capacity,
+        wal.clone(),
+        spill,
+    );
 
     // Create a map to store events by source id
     let telemetry_map = Arc::new(DashMap::new());
 
+    // Resolve tenants before routing, path-based resolution rewrites the URI
+    let tenants = Arc::new(TenantRegistry::new(&config.tenancy));
+    if tenants.is_enabled() {
+        let names: Vec<String> = tenants.tenants().iter().map(ToString::to_string).collect();
+        tracing::info!("Multi-tenancy enabled for tenants: {}", names.join(", "));
+    }
+
+    if config.admin.enabled && config.admin.token.is_none() {
+        tracing::warn!("Admin API enabled without a token. Anyone reaching the server can use it.");
+    }
+
+    // Re-apply validator list changes made at runtime before a restart
+    let list_state = match &config.admin.state_file {
+        Some(path) if config.admin.enabled => {
+            let list_state = ListStateFile::new(path.clone());
+            list_state.restore(&validators).map_err(Error::Internal)?;
+            tracing::info!("Restored validator list changes from {}", path.display());
+            Some(Arc::new(list_state))
+        }
+        _ => None,
+    };
+
+    let dlq = if config.dlq.enabled {
+        let dlq = DeadLetterQueue::open(&config.dlq).map_err(Error::Internal)?;
+        tracing::info!("DLQ enabled in {}", config.dlq.dir.display());
+        Some(Arc::new(dlq))
+    } else {
+        None
+    };
+
+    // Redact events after all transformers ran, so it covers the fields they add
+    let redactor = if config.redaction.enabled {
+        let redactor = Redactor::new(config.redaction.clone()).map_err(Error::Internal)?;
+        tracing::info!("Redaction enabled");
+        Some(Arc::new(redactor))
+    } else {
+        None
+    };
+
+    let heartbeat = if config.heartbeat.enabled {
+        tracing::info!("Heartbeat monitor enabled");
+        Some(Arc::new(HeartbeatMonitor::new(&config.heartbeat)))
+    } else {
+        None
+    };
+
+    let quarantine = if config.quarantine.enabled {
+        let quarantine = QuarantineStore::open(&config.quarantine).map_err(Error::Internal)?;
+        tracing::info!(
+            "Quarantine enabled with capacity {} per tenant",
+            config.quarantine.capacity
+        );
+        Some(Arc::new(quarantine))
+    } else {
+        None
+    };
+
     // Initialize the application state
-    let app_state =
-        AppState::new(sender.clone(), telemetry_map.clone(), validators, prometheus_handle);
-
-    // Create another config clone - to be moved into the processor
-    let config_clone = config.clone();
-    // Spawn the processor
-    let processor_handle = tokio::spawn(async move {
-        let processor = EventProcessorManager::new(telemetry_map, processors, config_clone);
-        processor.run(receiver).await;
-    });
+    let app_state = AppState::new(
+        sender.clone(),
+        telemetry_map.clone(),
+        validators,
+        transformers,
+        prometheus_handle,
+        config.clone(),
+        tenants.clone(),
+        list_state,
+        quarantine,
+        processors.clone(),
+        dlq.clone(),
+        heartbeat,
+        redactor,
+    );
+
+    // Start background work of the validator plugins and the heartbeat monitor
+    for plugin in app_state.validators.iter() {
+        plugin.start();
+    }
+    if let Some(monitor) = &app_state.heartbeat {
+        monitor.start();
+    }
+
+    // Spawn the processor workers, each batching the events of its sources
+    let processor =
+        EventProcessorManager::new(telemetry_map, processors, config.clone(), dlq, wal.clone());
+    let processor_handles: Vec<_> = receivers
+        .into_iter()
+        .enumerate()
+        .map(|(worker, receiver)| {
+            let processor = processor.clone();
+            tokio::spawn(async move { processor.run(worker, receiver).await })
+        })
+        .collect();
+    tracing::info!("Started {} processor worker(s)", processor_handles.len());
+
+    if !wal_replay.is_empty() {
+        tracing::info!("Replaying {} unprocessed events from the WAL", wal_replay.len());
+        metrics::counter!(WAL_REPLAYED_EVENTS_TOTAL).increment(wal_replay.len() as u64);
+        for (wal_seq, event) in wal_replay {
+            sender.replay(wal_seq, event).await.map_err(|e| Error::Internal(e.to_string()))?;
+        }
+    }
 
     let routes = Router::new()
         .route("/ingest", post(ingest_handler))
         .route("/stats", get(stats_handler))
         .route("/stats/{source_id}", get(stats_by_source_id_handler))
+        .route("/sources/down", get(sources_down_handler))
         .route("/metrics", get(metrics_handler))
         .route("/healthz", get(healthz_handler))
+        .route(
+            "/admin/validators/{validator}/{list}",
+            get(admin_get_list_handler).post(admin_update_list_handler),
+        )
+        .route(QUARANTINE_ENDPOINT, get(quarantine_list_handler))
+        .route(QUARANTINE_RESUBMIT_ALL_ENDPOINT, post(quarantine_resubmit_all_handler))
+        .route(
+            QUARANTINE_EVENT_ENDPOINT,
+            get(quarantine_get_handler).delete(quarantine_delete_handler),
+        )
+        .route(QUARANTINE_RESUBMIT_ENDPOINT, post(quarantine_resubmit_handler))
+        .route(DLQ_ENDPOINT, get(dlq_list_handler).delete(dlq_purge_handler))
+        .route(DLQ_ENTRY_ENDPOINT, get(dlq_get_handler).delete(dlq_delete_handler))
+        .route(DLQ_REPLAY_ENDPOINT, post(dlq_replay_handler))
         .fallback(not_found_handler)
         .layer(
             TraceLayer::new_for_http()
@@ -231,25 +1299,37 @@ pub async fn run_server(
                 .on_response(DefaultOnResponse::new().level(Level::INFO)),
         )
         .with_state(app_state);
+    let app = middleware::from_fn_with_state(tenants, tenant::resolve_tenant).layer(routes);
 
     let listener = TcpListener::bind(format!("{}:{}", config.http.host, config.http.port)).await?;
 
     tracing::info!("Listening on {}", listener.local_addr()?);
 
-    axum::serve(listener, routes.into_make_service())
-        .with_graceful_shutdown(wait_for_shutdown())
-        .await?;
+    axum::serve(
+        listener,
+        ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app),
+    )
+    .with_graceful_shutdown(wait_for_shutdown())
+    .await?;
 
     // Close the sender channel
     tracing::info!("Closing event sender channel");
     drop(sender);
 
-    // Wait for the processor to finish
-    if let Err(err) = processor_handle.await {
-        tracing::error!("Processor task failed: {}", err);
-        return Err(Error::Internal("Processor task failed".into()));
+    // Wait for the processor workers to finish
+    for handle in processor_handles {
+        if let Err(err) = handle.await {
+            tracing::error!("Processor task failed: {}", err);
+            return Err(Error::Internal("Processor task failed".into()));
+        }
     }
     tracing::info!("Processor task finished successfully");
+    // Wait for the WAL writer to apply the last commits
+    if let Some(wal) = &wal
+        && let Err(err) = wal.sync().await
+    {
+        tracing::error!("{}", err);
+    }
     tracing::info!("Telemetron shutdown complete");
 
     Ok(())
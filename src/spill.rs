@@ -0,0 +1,532 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc as std_mpsc,
+    },
+    thread,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Notify, mpsc, mpsc::error::TrySendError, oneshot};
+
+use crate::{
+    config::SpillConfig,
+    event::PersistedEvent,
+    metrics::{
+        SPILL_BUFFERED_BYTES, SPILL_DRAIN_LAG_SECONDS, SPILL_REJECTED_EVENTS_TOTAL,
+        SPILLED_BYTES_TOTAL, SPILLED_EVENTS_TOTAL,
+    },
+    processor::{QueuedEvent, SendError},
+};
+
+/// A spilled event, with its WAL sequence number.
+#[derive(Debug, Serialize, Deserialize)]
+struct SpillRecord {
+    wal_seq: Option<u64>,
+    spilled_at: DateTime<Utc>,
+    #[serde(flatten)]
+    event: PersistedEvent,
+}
+
+/// Spilled events of one worker.
+#[derive(Debug, Default)]
+struct SpillState {
+    /// Spilled events written and not yet drained, and their bytes
+    events: usize,
+    bytes: u64,
+    /// Spilled events the writer thread has not written yet
+    writing: usize,
+    /// Segment files, oldest first
+    segments: VecDeque<u64>,
+}
+
+/// A spilled event for the writer thread to append to the segments of its
+/// worker.
+#[derive(Debug)]
+struct SpillWrite {
+    worker: usize,
+    line: String,
+    reply: oneshot::Sender<Result<(), String>>,
+}
+
+/// Segment the writer thread appends the events of a worker to.
+#[derive(Debug, Default)]
+struct SegmentWriter {
+    /// Open segment, its file and size
+    current: Option<(u64, File, u64)>,
+    next_segment: u64,
+}
+
+#[derive(Debug)]
+struct WorkerSpill {
+    worker: String,
+    dir: PathBuf,
+    state: Mutex<SpillState>,
+    /// Wakes the drainer when events are spilled or the buffer is closed
+    spilled: Notify,
+}
+
+impl WorkerSpill {
+    fn segment_path(&self, segment: u64) -> PathBuf {
+        self.dir.join(format!("segment-{:020}.jsonl", segment))
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, SpillState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Deletes the segment files once all events are drained.
+    fn clear(&self, state: &mut SpillState) {
+        for segment in state.segments.drain(..) {
+            let path = self.segment_path(segment);
+            if let Err(err) = fs::remove_file(&path) {
+                tracing::error!("Failed to delete spill segment {}: {}", path.display(), err);
+            }
+        }
+    }
+}
+
+/// Bounded on-disk buffer for events that don't fit in the channel of their
+/// processor worker. Once a worker's channel is full, its events are appended
+/// as JSON lines to segment files until a drainer has fed all of them back
+/// into the channel, so the events of a worker stay in order. Segments are
+/// written by a writer thread, off the ingest path. The buffer only rides out
+/// bursts, it is cleared on startup.
+#[derive(Debug)]
+pub struct SpillBuffer {
+    max_bytes: u64,
+    /// Bytes of spilled events not yet drained, across all workers
+    bytes: AtomicU64,
+    workers: Arc<[WorkerSpill]>,
+    closed: AtomicBool,
+    /// Sends spilled events to the writer thread
+    writes: Option<std_mpsc::Sender<SpillWrite>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl SpillBuffer {
+    /// Opens the buffer of `workers` processor workers, deleting events
+    /// spilled before a restart. Only the segment files of the buffer are
+    /// deleted, other files in its directory are left alone.
+    pub fn open(config: &SpillConfig, workers: usize) -> Result<Self, String> {
+        let dir = &config.dir;
+        if dir.exists() {
+            Self::delete_segments(dir)?;
+        }
+        let workers = (0..workers.max(1))
+            .map(|worker| {
+                let dir = dir.join(format!("worker-{}", worker));
+                fs::create_dir_all(&dir)
+                    .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+                Ok(WorkerSpill {
+                    worker: worker.to_string(),
+                    dir,
+                    state: Mutex::new(SpillState::default()),
+                    spilled: Notify::new(),
+                })
+            })
+            .collect::<Result<Arc<[_]>, String>>()?;
+        let (writes, receiver) = std_mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("spill-writer".to_string())
+            .spawn({
+                let workers = workers.clone();
+                let segment_bytes = config.segment_bytes.max(1);
+                move || write_segments(&workers, segment_bytes, receiver)
+            })
+            .map_err(|e| format!("Failed to start the spill writer: {}", e))?;
+        metrics::gauge!(SPILL_BUFFERED_BYTES).set(0.0);
+        Ok(Self {
+            max_bytes: config.max_bytes,
+            bytes: AtomicU64::new(0),
+            workers,
+            closed: AtomicBool::new(false),
+            writes: Some(writes),
+            writer: Some(writer),
+        })
+    }
+
+    /// Deletes the `worker-*/segment-*.jsonl` files in a directory, including
+    /// those of workers no longer configured.
+    fn delete_segments(dir: &Path) -> Result<(), String> {
+        let read_dir =
+            fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let worker_dirs = read_dir.filter_map(|entry| entry.ok()).filter(|entry| {
+            entry.file_name().to_str().is_some_and(|name| name.starts_with("worker-"))
+                && entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+        });
+        for worker_dir in worker_dirs {
+            let worker_dir = worker_dir.path();
+            let read_dir = fs::read_dir(&worker_dir)
+                .map_err(|e| format!("Failed to read {}: {}", worker_dir.display(), e))?;
+            let segments = read_dir.filter_map(|entry| entry.ok()).filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.starts_with("segment-") && name.ends_with(".jsonl"))
+            });
+            for segment in segments {
+                let path = segment.path();
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Queues an event on the channel of its worker, or spills it if the
+    /// channel is full or earlier events of the worker are still spilled.
+    /// Returns once a spilled event is written.
+    pub async fn send(
+        &self,
+        worker: usize,
+        sender: &mpsc::Sender<QueuedEvent>,
+        queued: QueuedEvent,
+    ) -> Result<(), SendError> {
+        let spill = self.workers.get(worker).ok_or(SendError::Closed)?;
+        let (reply, written) = oneshot::channel();
+        let len = {
+            let mut state = spill.state.lock().map_err(|e| SendError::Spill(e.to_string()))?;
+            let queued = if state.events == 0 && state.writing == 0 {
+                match sender.try_send(queued) {
+                    Ok(()) => return Ok(()),
+                    Err(TrySendError::Full(queued)) => queued,
+                    Err(TrySendError::Closed(_)) => return Err(SendError::Closed),
+                }
+            } else {
+                queued
+            };
+
+            let record = SpillRecord {
+                wal_seq: queued.wal_seq,
+                spilled_at: Utc::now(),
+                event: PersistedEvent::from(queued.event),
+            };
+            let line =
+                serde_json::to_string(&record).map_err(|e| SendError::Spill(e.to_string()))?;
+            let line = line + "\n";
+            let len = line.len() as u64;
+            if self.bytes.fetch_add(len, Ordering::AcqRel) + len > self.max_bytes {
+                self.bytes.fetch_sub(len, Ordering::AcqRel);
+                metrics::counter!(SPILL_REJECTED_EVENTS_TOTAL).increment(1);
+                return Err(SendError::SpillFull);
+            }
+            // Handed over under the lock, so the events of a worker are written in order
+            let write = SpillWrite { worker, line, reply };
+            if self.writes.as_ref().is_none_or(|writes| writes.send(write).is_err()) {
+                self.bytes.fetch_sub(len, Ordering::AcqRel);
+                return Err(SendError::Spill("Spill writer is not running".to_string()));
+            }
+            state.writing += 1;
+            len
+        };
+
+        let result = written.await.unwrap_or_else(|_| Err("Spill writer stopped".to_string()));
+        if let Err(err) = result {
+            self.bytes.fetch_sub(len, Ordering::AcqRel);
+            return Err(SendError::Spill(err));
+        }
+        metrics::counter!(SPILLED_EVENTS_TOTAL, "worker" => spill.worker.clone()).increment(1);
+        metrics::counter!(SPILLED_BYTES_TOTAL, "worker" => spill.worker.clone()).increment(len);
+        metrics::gauge!(SPILL_BUFFERED_BYTES).set(self.bytes.load(Ordering::Acquire) as f64);
+        Ok(())
+    }
+
+    /// Feeds the spilled events of a worker back into its channel, oldest
+    /// first, waiting for room. Returns once the buffer is closed and all
+    /// events are drained, or the channel is closed.
+    pub async fn drain(&self, worker: usize, sender: mpsc::Sender<QueuedEvent>) {
+        let Some(spill) = self.workers.get(worker) else {
+            return;
+        };
+        let mut reader: Option<BufReader<File>> = None;
+        loop {
+            let (front, writing) = {
+                let mut state = spill.lock_state();
+                if state.events == 0 && state.writing == 0 && !state.segments.is_empty() {
+                    spill.clear(&mut state);
+                    reader = None;
+                }
+                (state.segments.front().copied().filter(|_| state.events > 0), state.writing)
+            };
+            let Some(segment) = front else {
+                if self.closed.load(Ordering::Acquire) && writing == 0 {
+                    return;
+                }
+                spill.spilled.notified().await;
+                continue;
+            };
+
+            let current = match &mut reader {
+                Some(current) => current,
+                None => {
+                    let path = spill.segment_path(segment);
+                    match File::open(&path) {
+                        Ok(file) => reader.insert(BufReader::new(file)),
+                        Err(err) => {
+                            tracing::error!("Failed to open {}: {}", path.display(), err);
+                            self.skip_segment(spill, &mut reader);
+                            continue;
+                        }
+                    }
+                }
+            };
+            let mut line = String::new();
+            match current.read_line(&mut line) {
+                Ok(_) if line.ends_with('\n') => {}
+                // End of the segment, or a line cut off by a failed write
+                Ok(_) => {
+                    self.skip_segment(spill, &mut reader);
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!("Failed to read spill segment {}: {}", segment, err);
+                    self.skip_segment(spill, &mut reader);
+                    continue;
+                }
+            }
+
+            let drained = match serde_json::from_str::<SpillRecord>(&line) {
+                Ok(record) => {
+                    let lag = (Utc::now() - record.spilled_at).to_std().unwrap_or_default();
+                    metrics::histogram!(SPILL_DRAIN_LAG_SECONDS, "worker" => spill.worker.clone())
+                        .record(lag);
+                    Some(QueuedEvent { event: record.event.into_event(), wal_seq: record.wal_seq })
+                }
+                Err(err) => {
+                    tracing::error!("Dropping unreadable spilled event: {}", err);
+                    None
+                }
+            };
+            if let Some(queued) = drained
+                && sender.send(queued).await.is_err()
+            {
+                tracing::error!("Event channel closed, stopped draining spilled events");
+                return;
+            }
+
+            let len = line.len() as u64;
+            let bytes = self.bytes.fetch_sub(len, Ordering::AcqRel).saturating_sub(len);
+            metrics::gauge!(SPILL_BUFFERED_BYTES).set(bytes as f64);
+            let mut state = spill.lock_state();
+            state.events = state.events.saturating_sub(1);
+            state.bytes = state.bytes.saturating_sub(len);
+        }
+    }
+
+    /// Moves the drainer past its segment once read, deleting it.
+    fn skip_segment(&self, spill: &WorkerSpill, reader: &mut Option<BufReader<File>>) {
+        *reader = None;
+        let mut state = spill.lock_state();
+        if state.segments.len() < 2 {
+            // The events counted as spilled are not on disk
+            tracing::error!("Spill segment ended early, dropping {} events", state.events);
+            let bytes = self.bytes.fetch_sub(state.bytes, Ordering::AcqRel);
+            metrics::gauge!(SPILL_BUFFERED_BYTES).set(bytes.saturating_sub(state.bytes) as f64);
+            state.events = 0;
+            state.bytes = 0;
+            spill.clear(&mut state);
+            return;
+        }
+        if let Some(segment) = state.segments.pop_front() {
+            let path = spill.segment_path(segment);
+            if let Err(err) = fs::remove_file(&path) {
+                tracing::error!("Failed to delete spill segment {}: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Lets the drainers return once all spilled events are drained.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        for spill in self.workers.iter() {
+            spill.spilled.notify_one();
+        }
+    }
+}
+
+impl Drop for SpillBuffer {
+    /// Waits for the writer thread to write the spilled events.
+    fn drop(&mut self) {
+        self.writes.take();
+        if let Some(writer) = self.writer.take()
+            && writer.join().is_err()
+        {
+            tracing::error!("Spill writer panicked");
+        }
+    }
+}
+
+impl SegmentWriter {
+    /// Appends a line, starting a new segment when the current one is full or
+    /// was deleted once drained.
+    fn append(
+        &mut self,
+        spill: &WorkerSpill,
+        line: &str,
+        segment_bytes: u64,
+    ) -> Result<(), String> {
+        let last = spill.lock_state().segments.back().copied();
+        let full = |(segment, _, size): &(u64, File, u64)| {
+            *size >= segment_bytes || Some(*segment) != last
+        };
+        if self.current.as_ref().is_none_or(full) {
+            let segment = self.next_segment;
+            self.next_segment += 1;
+            let path = spill.segment_path(segment);
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+            spill.lock_state().segments.push_back(segment);
+            self.current = Some((segment, file, 0));
+        }
+        let Some((_, file, size)) = &mut self.current else {
+            return Err("Spill segment not open".to_string());
+        };
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            // The next event starts a new segment, after the partial line
+            self.current = None;
+            return Err(format!("Failed to spill event: {}", err));
+        }
+        *size += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Appends the spilled events of a buffer until it is dropped, waking the
+/// drainer of a worker once its events are written.
+fn write_segments(
+    workers: &[WorkerSpill],
+    segment_bytes: u64,
+    writes: std_mpsc::Receiver<SpillWrite>,
+) {
+    let mut writers: Vec<SegmentWriter> =
+        workers.iter().map(|_| SegmentWriter::default()).collect();
+    for write in writes {
+        let (Some(spill), Some(writer)) =
+            (workers.get(write.worker), writers.get_mut(write.worker))
+        else {
+            let _ = write.reply.send(Err("No such spill worker".to_string()));
+            continue;
+        };
+        let result = writer.append(spill, &write.line, segment_bytes);
+        {
+            let mut state = spill.lock_state();
+            state.writing = state.writing.saturating_sub(1);
+            if result.is_ok() {
+                state.events += 1;
+                state.bytes += write.line.len() as u64;
+            }
+        }
+        spill.spilled.notify_one();
+        let _ = write.reply.send(result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        event::{Event, EventType},
+        tenant::TenantId,
+    };
+
+    fn create_event(source_id: u64) -> QueuedEvent {
+        let event = Event {
+            source_id: source_id.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: TenantId::new("a"),
+            sample_weight: 10,
+            payload: None,
+        };
+        QueuedEvent { event, wal_seq: Some(source_id) }
+    }
+
+    fn create_config(name: &str, max_bytes: u64) -> SpillConfig {
+        let dir =
+            std::env::temp_dir().join(format!("telemetron-spill-{}-{}", name, std::process::id()));
+        SpillConfig { enabled: true, dir, max_bytes, segment_bytes: 500 }
+    }
+
+    #[tokio::test]
+    async fn test_drains_spilled_events_in_order() {
+        let config = create_config("drain", 1024 * 1024);
+        let Ok(spill) = SpillBuffer::open(&config, 1) else {
+            panic!("spill buffer should open");
+        };
+        let spill = Arc::new(spill);
+        let (sender, mut receiver) = mpsc::channel(2);
+        for source_id in 0..10 {
+            assert!(spill.send(0, &sender, create_event(source_id)).await.is_ok());
+        }
+        // Two fit in the channel, the rest went to several segments
+        assert!(spill.bytes.load(Ordering::Acquire) > 500);
+
+        let drainer = tokio::spawn({
+            let spill = spill.clone();
+            let sender = sender.clone();
+            async move { spill.drain(0, sender).await }
+        });
+        for source_id in 0..10 {
+            let Some(queued) = receiver.recv().await else {
+                panic!("event should be drained");
+            };
+            assert_eq!(queued.wal_seq, Some(source_id));
+            assert_eq!(queued.event.sample_weight, 10);
+        }
+
+        spill.close();
+        assert!(drainer.await.is_ok());
+        assert_eq!(spill.bytes.load(Ordering::Acquire), 0);
+        let Ok(entries) = fs::read_dir(config.dir.join("worker-0")) else {
+            panic!("worker dir should exist");
+        };
+        assert_eq!(entries.count(), 0);
+        let _ = fs::remove_dir_all(config.dir);
+    }
+
+    #[test]
+    fn test_only_deletes_own_segments_on_open() {
+        let config = create_config("open", 1024);
+        let _ = fs::remove_dir_all(&config.dir);
+        let worker_dir = config.dir.join("worker-3");
+        assert!(fs::create_dir_all(&worker_dir).is_ok());
+        assert!(fs::write(worker_dir.join("segment-00000000000000000000.jsonl"), "{}\n").is_ok());
+        assert!(fs::write(worker_dir.join("notes.txt"), "keep").is_ok());
+        assert!(fs::write(config.dir.join("segment-00000000000000000000.jsonl"), "keep").is_ok());
+
+        let Ok(_spill) = SpillBuffer::open(&config, 1) else {
+            panic!("spill buffer should open");
+        };
+        assert!(!worker_dir.join("segment-00000000000000000000.jsonl").exists());
+        assert!(worker_dir.join("notes.txt").exists());
+        assert!(config.dir.join("segment-00000000000000000000.jsonl").exists());
+        let _ = fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_events_when_full() {
+        let config = create_config("full", 400);
+        let Ok(spill) = SpillBuffer::open(&config, 1) else {
+            panic!("spill buffer should open");
+        };
+        let (sender, _receiver) = mpsc::channel(1);
+        assert!(spill.send(0, &sender, create_event(0)).await.is_ok());
+        assert!(spill.send(0, &sender, create_event(1)).await.is_ok());
+        assert!(matches!(spill.send(0, &sender, create_event(2)).await, Err(SendError::SpillFull)));
+        let _ = fs::remove_dir_all(config.dir);
+    }
+}
@@ -1,22 +1,74 @@
+use std::sync::Arc;
+
 use metrics_exporter_prometheus::PrometheusHandle;
 
-use crate::common_types::{EventSender, EventValidators, TelemetryMap};
+use crate::{
+    common_types::{
+        EventProcessors, EventSender, EventTransformers, EventValidators, TelemetryMap,
+    },
+    config::Config,
+    dlq::DeadLetterQueue,
+    processing::heartbeat::HeartbeatMonitor,
+    quarantine::QuarantineStore,
+    tenant::TenantRegistry,
+    transform::redaction::Redactor,
+    validation::managed::ListStateFile,
+};
 
 #[derive(Debug, Clone)]
 pub struct AppState {
     pub telemetry_map: TelemetryMap,
     pub sender: EventSender,
     pub validators: EventValidators,
+    pub transformers: EventTransformers,
     pub prometheus_handle: PrometheusHandle,
+    pub config: Arc<Config>,
+    pub tenants: Arc<TenantRegistry>,
+    /// Where runtime validator list changes are persisted, if anywhere
+    pub list_state: Option<Arc<ListStateFile>>,
+    /// Where rejected events are kept, if enabled
+    pub quarantine: Option<Arc<QuarantineStore>>,
+    /// Processor plugins, to replay batches from the DLQ into
+    pub processors: EventProcessors,
+    /// Where batches that failed in a processor plugin are kept, if enabled
+    pub dlq: Option<Arc<DeadLetterQueue>>,
+    /// Liveness of the sources, if the heartbeat monitor is enabled
+    pub heartbeat: Option<Arc<HeartbeatMonitor>>,
+    /// Scrubs personal data after the transformers, if enabled
+    pub redactor: Option<Arc<Redactor>>,
 }
 
 impl AppState {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         sender: EventSender,
         telemetry_map: TelemetryMap,
         validators: EventValidators,
+        transformers: EventTransformers,
         prometheus_handle: PrometheusHandle,
+        config: Arc<Config>,
+        tenants: Arc<TenantRegistry>,
+        list_state: Option<Arc<ListStateFile>>,
+        quarantine: Option<Arc<QuarantineStore>>,
+        processors: EventProcessors,
+        dlq: Option<Arc<DeadLetterQueue>>,
+        heartbeat: Option<Arc<HeartbeatMonitor>>,
+        redactor: Option<Arc<Redactor>>,
     ) -> Self {
-        AppState { telemetry_map, sender, validators, prometheus_handle }
+        AppState {
+            telemetry_map,
+            sender,
+            validators,
+            transformers,
+            prometheus_handle,
+            config,
+            tenants,
+            list_state,
+            quarantine,
+            processors,
+            dlq,
+            heartbeat,
+            redactor,
+        }
     }
 }
@@ -0,0 +1,549 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{HeaderMap, Uri, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{TenancyConfig, TenantConfig, TenantResolution},
+    error::Error,
+    event::{Event, EventValidationError, SourceId},
+    metrics::TENANT_REJECTED_EVENTS_TOTAL,
+};
+
+/// Tenant used for all requests when multi-tenancy is disabled.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Path prefix used by the `path` tenant resolution: `/t/{tenant}/...`.
+const TENANT_PATH_PREFIX: &str = "/t/";
+
+/// Name of the tenant (namespace) an event or request belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+}
+
+impl Default for TenantId {
+    fn default() -> Self {
+        Self(DEFAULT_TENANT.to_string())
+    }
+}
+
+impl Display for TenantId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Key of per-source state: sources are only unique within a tenant.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceKey {
+    pub tenant: TenantId,
+    pub source_id: SourceId,
+}
+
+impl SourceKey {
+    pub fn new(tenant: TenantId, source_id: SourceId) -> Self {
+        Self { tenant, source_id }
+    }
+}
+
+/// Reason an event was not admitted for a tenant.
+#[derive(Debug, thiserror::Error)]
+pub enum TenantRejection {
+    #[error(transparent)]
+    Invalid(#[from] EventValidationError),
+    #[error("Rate limit exceeded for tenant {0}")]
+    RateLimited(TenantId),
+    #[error("Source limit of {max} reached for tenant {tenant}")]
+    TooManySources { tenant: TenantId, max: usize },
+    #[error("Unknown tenant {0}")]
+    UnknownTenant(TenantId),
+}
+
+impl TenantRejection {
+    fn reason(&self) -> &'static str {
+        match self {
+            TenantRejection::Invalid(_) => "invalid",
+            TenantRejection::RateLimited(_) => "rate_limited",
+            TenantRejection::TooManySources { .. } => "too_many_sources",
+            TenantRejection::UnknownTenant(_) => "unknown_tenant",
+        }
+    }
+}
+
+impl From<TenantRejection> for Error {
+    fn from(rejection: TenantRejection) -> Self {
+        match rejection {
+            TenantRejection::Invalid(err) => Error::InvalidEvent(err),
+            TenantRejection::UnknownTenant(_) => Error::Unauthorized(rejection.to_string()),
+            quota => Error::TooManyRequests(quota.to_string()),
+        }
+    }
+}
+
+/// Token bucket used for per-tenant rate limits.
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(refill_per_second: u32, capacity: u32) -> Self {
+        let capacity = capacity.max(1) as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_second: refill_per_second as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns an acquired token that wasn't used.
+    fn release(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.capacity);
+    }
+}
+
+/// Policy and quota state of one tenant.
+#[derive(Debug)]
+struct TenantState {
+    config: TenantConfig,
+    rate_limiter: Option<Mutex<TokenBucket>>,
+    /// Distinct sources seen so far, only tracked if `max_sources` is set
+    sources: Mutex<HashSet<SourceId>>,
+}
+
+impl TenantState {
+    fn new(config: TenantConfig) -> Self {
+        let rate_limiter = config
+            .max_events_per_second
+            .map(|rate| Mutex::new(TokenBucket::new(rate, config.burst.unwrap_or(rate))));
+        Self { config, rate_limiter, sources: Mutex::new(HashSet::new()) }
+    }
+}
+
+/// Resolves the tenant of requests and enforces per-tenant policies.
+#[derive(Debug)]
+pub struct TenantRegistry {
+    enabled: bool,
+    resolution: TenantResolution,
+    tenant_header: String,
+    api_key_header: String,
+    api_keys: HashMap<String, TenantId>,
+    tenants: HashMap<TenantId, TenantState>,
+}
+
+impl TenantRegistry {
+    pub fn new(config: &TenancyConfig) -> Self {
+        let no_tenants = match config.resolution {
+            TenantResolution::ApiKey => config.api_keys.is_empty(),
+            TenantResolution::Header | TenantResolution::Path => config.tenants.is_empty(),
+        };
+        if config.enabled && no_tenants {
+            tracing::warn!("Multi-tenancy enabled without tenants. All requests will be rejected.");
+        }
+
+        Self {
+            enabled: config.enabled,
+            resolution: config.resolution,
+            tenant_header: config.tenant_header.to_lowercase(),
+            api_key_header: config.api_key_header.to_lowercase(),
+            api_keys: config
+                .api_keys
+                .iter()
+                .map(|(key, tenant)| (key.clone(), TenantId::new(tenant.clone())))
+                .collect(),
+            tenants: config
+                .tenants
+                .iter()
+                .map(|(name, tenant)| {
+                    (TenantId::new(name.clone()), TenantState::new(tenant.clone()))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns all known tenants.
+    pub fn tenants(&self) -> Vec<TenantId> {
+        let mut tenants: Vec<TenantId> = self.tenants.keys().cloned().collect();
+        tenants.sort();
+        tenants
+    }
+
+    /// Returns the tenant if it is known.
+    fn known(&self, name: &str) -> Option<TenantId> {
+        let tenant = TenantId::new(name);
+        self.tenants.contains_key(&tenant).then_some(tenant)
+    }
+
+    /// Resolves the tenant of a request. For the `path` resolution the tenant
+    /// prefix is stripped from the returned URI.
+    pub fn resolve(&self, headers: &HeaderMap, uri: &Uri) -> (Option<TenantId>, Option<Uri>) {
+        if !self.enabled {
+            return (Some(TenantId::default()), None);
+        }
+
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        match self.resolution {
+            TenantResolution::ApiKey => {
+                (header(&self.api_key_header).and_then(|key| self.api_keys.get(key)).cloned(), None)
+            }
+            TenantResolution::Header => {
+                (header(&self.tenant_header).and_then(|t| self.known(t)), None)
+            }
+            TenantResolution::Path => {
+                let Some(rest) = uri.path().strip_prefix(TENANT_PATH_PREFIX) else {
+                    return (None, None);
+                };
+                let (name, path) = rest.split_once('/').map_or((rest, ""), |(n, p)| (n, p));
+                let Some(tenant) = self.known(name) else {
+                    return (None, None);
+                };
+                let path_and_query = match uri.query() {
+                    Some(query) => format!("/{}?{}", path, query),
+                    None => format!("/{}", path),
+                };
+                (Some(tenant), path_and_query.parse().ok())
+            }
+        }
+    }
+
+    /// Checks the tenant's scope (allowed sources and types) and reserves its
+    /// quotas for an event. The reservation is released unless it is
+    /// committed once the event is queued.
+    pub fn admit(
+        &self,
+        tenant: &TenantId,
+        event: &Event,
+    ) -> Result<Reservation<'_>, TenantRejection> {
+        let result = self.check(tenant, event);
+        if let Err(rejection) = &result {
+            metrics::counter!(
+                TENANT_REJECTED_EVENTS_TOTAL,
+                "tenant" => tenant.to_string(),
+                "reason" => rejection.reason()
+            )
+            .increment(1);
+        }
+        result
+    }
+
+    fn check(&self, tenant: &TenantId, event: &Event) -> Result<Reservation<'_>, TenantRejection> {
+        let mut reservation = Reservation {
+            state: self.tenants.get(tenant),
+            source_id: event.source_id.clone(),
+            token: false,
+            new_source: false,
+        };
+        let Some(state) = reservation.state else {
+            // Only the default tenant is used when tenancy is disabled
+            if self.enabled {
+                return Err(TenantRejection::UnknownTenant(tenant.clone()));
+            }
+            return Ok(reservation);
+        };
+        let config = &state.config;
+
+        if !config.allowed_sources.is_empty() && !config.allowed_sources.contains(&event.source_id)
+        {
+            return Err(EventValidationError::DisallowedSourceId(event.source_id.clone()).into());
+        }
+        if !config.allowed_types.is_empty() && !config.allowed_types.contains(&event.r#type) {
+            return Err(EventValidationError::DisallowedEventType(event.r#type.clone()).into());
+        }
+
+        if let Some(rate_limiter) = &state.rate_limiter
+            && let Ok(mut bucket) = rate_limiter.lock()
+            && !bucket.try_acquire(Instant::now())
+        {
+            return Err(TenantRejection::RateLimited(tenant.clone()));
+        }
+        reservation.token = state.rate_limiter.is_some();
+
+        if let Some(max) = config.max_sources
+            && let Ok(mut sources) = state.sources.lock()
+            && !sources.contains(&event.source_id)
+        {
+            if sources.len() >= max {
+                return Err(TenantRejection::TooManySources { tenant: tenant.clone(), max });
+            }
+            sources.insert(event.source_id.clone());
+            reservation.new_source = true;
+        }
+
+        Ok(reservation)
+    }
+}
+
+/// Tenant quota taken by an admitted event: a rate limit token and, for a new
+/// source, a `max_sources` slot. Released on drop unless committed.
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    state: Option<&'a TenantState>,
+    source_id: SourceId,
+    token: bool,
+    new_source: bool,
+}
+
+impl Reservation<'_> {
+    /// Keeps the reserved quota, once the event is queued.
+    pub fn commit(mut self) {
+        if self.new_source
+            && let Some(state) = self.state
+            && let Ok(mut sources) = state.sources.lock()
+        {
+            // A concurrent reservation of the same source may have released it
+            sources.insert(self.source_id.clone());
+        }
+        self.token = false;
+        self.new_source = false;
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let Some(state) = self.state else {
+            return;
+        };
+        if self.token
+            && let Some(rate_limiter) = &state.rate_limiter
+            && let Ok(mut bucket) = rate_limiter.lock()
+        {
+            bucket.release();
+        }
+        if self.new_source
+            && let Ok(mut sources) = state.sources.lock()
+        {
+            sources.remove(&self.source_id);
+        }
+    }
+}
+
+/// Middleware resolving the tenant of each request before routing. The tenant
+/// is stored in the request extensions and read by the [`TenantId`]
+/// extractor.
+pub async fn resolve_tenant(
+    State(registry): State<Arc<TenantRegistry>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let (tenant, uri) = registry.resolve(request.headers(), request.uri());
+    if let Some(uri) = uri {
+        *request.uri_mut() = uri;
+    }
+    if let Some(tenant) = tenant {
+        request.extensions_mut().insert(tenant);
+    }
+    next.run(request).await
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for TenantId {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<TenantId>().cloned().ok_or_else(|| {
+            Error::Unauthorized("Missing or unknown tenant".to_string()).into_response()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::event::EventType;
+
+    fn create_config(resolution: TenantResolution) -> TenancyConfig {
+        TenancyConfig {
+            enabled: true,
+            resolution,
+            api_keys: HashMap::from([("secret".to_string(), "team-a".to_string())]),
+            tenants: HashMap::from([
+                (
+                    "team-a".to_string(),
+                    TenantConfig {
+                        allowed_types: HashSet::from([EventType::Heartbeat]),
+                        max_sources: Some(1),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "team-b".to_string(),
+                    TenantConfig { max_events_per_second: Some(1), ..Default::default() },
+                ),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    fn create_event(source_id: u64, event_type: EventType) -> Event {
+        Event {
+            source_id: source_id.into(),
+            r#type: event_type,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, axum::http::HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn test_resolves_default_tenant_when_disabled() {
+        let registry = TenantRegistry::new(&TenancyConfig::default());
+        let (tenant, _) = registry.resolve(&HeaderMap::new(), &Uri::from_static("/ingest"));
+        assert_eq!(tenant, Some(TenantId::default()));
+    }
+
+    #[test]
+    fn test_resolves_tenant_from_api_key() {
+        let registry = TenantRegistry::new(&create_config(TenantResolution::ApiKey));
+        let uri = Uri::from_static("/ingest");
+
+        let (tenant, _) = registry.resolve(&headers("x-api-key", "secret"), &uri);
+        assert_eq!(tenant, Some(TenantId::new("team-a")));
+
+        let (tenant, _) = registry.resolve(&headers("x-api-key", "wrong"), &uri);
+        assert_eq!(tenant, None);
+    }
+
+    #[test]
+    fn test_resolves_tenant_from_header() {
+        let registry = TenantRegistry::new(&create_config(TenantResolution::Header));
+        let uri = Uri::from_static("/ingest");
+
+        let (tenant, _) = registry.resolve(&headers("x-tenant-id", "team-b"), &uri);
+        assert_eq!(tenant, Some(TenantId::new("team-b")));
+
+        let (tenant, _) = registry.resolve(&headers("x-tenant-id", "team-c"), &uri);
+        assert_eq!(tenant, None);
+    }
+
+    #[test]
+    fn test_resolves_tenant_from_path() {
+        let registry = TenantRegistry::new(&create_config(TenantResolution::Path));
+
+        let (tenant, uri) =
+            registry.resolve(&HeaderMap::new(), &Uri::from_static("/t/team-a/stats?window=5m"));
+        assert_eq!(tenant, Some(TenantId::new("team-a")));
+        assert_eq!(uri.map(|uri| uri.to_string()), Some("/stats?window=5m".to_string()));
+
+        let (tenant, _) = registry.resolve(&HeaderMap::new(), &Uri::from_static("/stats"));
+        assert_eq!(tenant, None);
+    }
+
+    #[test]
+    fn test_enforces_tenant_scope_and_source_quota() {
+        let registry = TenantRegistry::new(&create_config(TenantResolution::ApiKey));
+        let tenant = TenantId::new("team-a");
+
+        assert!(
+            registry
+                .admit(&tenant, &create_event(1, EventType::Heartbeat))
+                .map(Reservation::commit)
+                .is_ok()
+        );
+        assert!(matches!(
+            registry.admit(&tenant, &create_event(1, EventType::Log)),
+            Err(TenantRejection::Invalid(_))
+        ));
+        assert!(matches!(
+            registry.admit(&tenant, &create_event(2, EventType::Heartbeat)),
+            Err(TenantRejection::TooManySources { .. })
+        ));
+    }
+
+    #[test]
+    fn test_enforces_rate_limit() {
+        let registry = TenantRegistry::new(&create_config(TenantResolution::ApiKey));
+        let tenant = TenantId::new("team-b");
+
+        assert!(
+            registry
+                .admit(&tenant, &create_event(1, EventType::Heartbeat))
+                .map(Reservation::commit)
+                .is_ok()
+        );
+        assert!(matches!(
+            registry.admit(&tenant, &create_event(1, EventType::Heartbeat)),
+            Err(TenantRejection::RateLimited(_))
+        ));
+    }
+
+    #[test]
+    fn test_releases_uncommitted_reservation() {
+        let registry = TenantRegistry::new(&create_config(TenantResolution::ApiKey));
+        let tenant = TenantId::new("team-a");
+
+        // Dropped without commit, e.g. the event could not be queued
+        assert!(registry.admit(&tenant, &create_event(1, EventType::Heartbeat)).is_ok());
+        assert!(
+            registry
+                .admit(&tenant, &create_event(2, EventType::Heartbeat))
+                .map(Reservation::commit)
+                .is_ok()
+        );
+        assert!(matches!(
+            registry.admit(&tenant, &create_event(1, EventType::Heartbeat)),
+            Err(TenantRejection::TooManySources { .. })
+        ));
+
+        let tenant = TenantId::new("team-b");
+        assert!(registry.admit(&tenant, &create_event(1, EventType::Heartbeat)).is_ok());
+        assert!(registry.admit(&tenant, &create_event(1, EventType::Heartbeat)).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_unknown_tenant_when_enabled() {
+        let registry = TenantRegistry::new(&create_config(TenantResolution::ApiKey));
+        let result = registry.admit(&TenantId::new("team-c"), &create_event(1, EventType::Log));
+        assert!(matches!(result, Err(TenantRejection::UnknownTenant(_))));
+
+        let registry = TenantRegistry::new(&TenancyConfig::default());
+        let result = registry.admit(&TenantId::default(), &create_event(1, EventType::Log));
+        assert!(result.is_ok());
+    }
+}
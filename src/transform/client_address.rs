@@ -0,0 +1,133 @@
+use std::net::IpAddr;
+
+use super::{EventTransformer, FieldPath, RequestContext, Transformed};
+use crate::{
+    config::ClientAddressTransformConfig,
+    event::Event,
+    plugins::{PluginError, TransformPluginFactory},
+};
+
+/// Records the IP address of the client that sent the event.
+#[derive(Debug)]
+pub struct ClientAddressTransformer {
+    pub field: FieldPath,
+    pub forwarded_header: Option<String>,
+    pub trusted_proxies: usize,
+    pub overwrite: bool,
+}
+
+impl ClientAddressTransformer {
+    pub fn new(config: ClientAddressTransformConfig) -> Self {
+        Self {
+            field: config.field,
+            forwarded_header: config.forwarded_header,
+            trusted_proxies: config.trusted_proxies,
+            overwrite: config.overwrite,
+        }
+    }
+
+    /// The address `trusted_proxies` hops from the right of the forwarded
+    /// header if present and valid, else the address of the connected peer.
+    /// With fewer entries than trusted proxies the first entry is used.
+    fn client_ip(&self, context: &RequestContext) -> Option<IpAddr> {
+        let forwarded = self
+            .forwarded_header
+            .as_deref()
+            .and_then(|header| context.headers.get(header))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| {
+                let entries: Vec<&str> = value.split(',').collect();
+                entries.get(entries.len().saturating_sub(self.trusted_proxies)).copied()
+            })
+            .and_then(|client| client.trim().parse().ok());
+        forwarded.or_else(|| context.client_addr.map(|addr| addr.ip()))
+    }
+}
+
+impl EventTransformer for ClientAddressTransformer {
+    fn name(&self) -> &'static str {
+        "ClientAddressTransformer"
+    }
+
+    fn transform(&self, event: &mut Event, context: &RequestContext) -> Transformed {
+        if let Some(ip) = self.client_ip(context)
+            && let Err(err) = self.field.set(event, ip.to_string(), self.overwrite)
+        {
+            tracing::warn!("Failed to set client address: {}", err);
+        }
+        Transformed::Keep
+    }
+}
+
+/// Constructs a ClientAddressTransformer from the given parameters.
+fn construct_client_address_transformer(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventTransformer + Send + Sync>, PluginError> {
+    let config: ClientAddressTransformConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "ClientAddressTransformer".to_string(),
+            source: e,
+        })?;
+    Ok(Box::new(ClientAddressTransformer::new(config)))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  TransformPluginFactory {
+        name: "ClientAddressTransformer",
+        constructor: construct_client_address_transformer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{HeaderMap, HeaderValue};
+    use chrono::Utc;
+
+    use super::*;
+    use crate::event::EventType;
+
+    #[test]
+    fn test_prefers_forwarded_address() {
+        let mut transformer = ClientAddressTransformer {
+            field: FieldPath::Label("client_addr".to_string()),
+            forwarded_header: Some("x-forwarded-for".to_string()),
+            trusted_proxies: 1,
+            overwrite: true,
+        };
+        let mut context = RequestContext {
+            received_at: Utc::now(),
+            client_addr: Some(([10, 0, 0, 1], 5000).into()),
+            headers: HeaderMap::new(),
+        };
+        let mut event = Event {
+            source_id: 1.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
+        let client_addr = |event: &Event| event.labels.get("client_addr").cloned();
+
+        assert_eq!(transformer.transform(&mut event, &context), Transformed::Keep);
+        assert_eq!(client_addr(&event).as_deref(), Some("10.0.0.1"));
+
+        // The client spoofed the first entry, the proxy appended the real one
+        context
+            .headers
+            .insert("x-forwarded-for", HeaderValue::from_static("198.51.100.1, 203.0.113.7"));
+        transformer.transform(&mut event, &context);
+        assert_eq!(client_addr(&event).as_deref(), Some("203.0.113.7"));
+
+        transformer.trusted_proxies = 2;
+        context.headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, 203.0.113.7, 10.0.0.2"),
+        );
+        transformer.transform(&mut event, &context);
+        assert_eq!(client_addr(&event).as_deref(), Some("203.0.113.7"));
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::Utc;
+
+use super::{EventTransformer, RequestContext, Transformed};
+use crate::{
+    config::FilterTransformConfig,
+    event::Event,
+    plugins::{PluginError, TransformPluginFactory},
+    validation::rule_expr::Expr,
+};
+
+/// Drops valid events that are not worth processing, e.g. debug logs, using
+/// the rule expression language of the `RuleValidator`.
+#[derive(Debug)]
+pub struct FilterTransformer {
+    pub drop_when: Vec<(String, Expr)>,
+}
+
+impl FilterTransformer {
+    /// Parses and type-checks all expressions.
+    pub fn new(config: FilterTransformConfig) -> Result<Self, String> {
+        let drop_when = config
+            .drop_when
+            .into_iter()
+            .map(|source| match Expr::parse(&source) {
+                Ok(expr) => Ok((source, expr)),
+                Err(e) => Err(format!("Expression '{}' is invalid: {}", source, e)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { drop_when })
+    }
+}
+
+impl EventTransformer for FilterTransformer {
+    fn name(&self) -> &'static str {
+        "FilterTransformer"
+    }
+
+    fn transform(&self, event: &mut Event, _context: &RequestContext) -> Transformed {
+        let now = Utc::now();
+        match self.drop_when.iter().find(|(_, expr)| expr.matches(event, now)) {
+            Some((source, _)) => Transformed::Drop(format!("matches '{}'", source)),
+            None => Transformed::Keep,
+        }
+    }
+}
+
+/// Constructs a FilterTransformer from the given parameters.
+fn construct_filter_transformer(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventTransformer + Send + Sync>, PluginError> {
+    let config: FilterTransformConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "FilterTransformer".to_string(),
+            source: e,
+        })?;
+    let transformer = FilterTransformer::new(config).map_err(|message| {
+        PluginError::InvalidParameters { plugin_name: "FilterTransformer".to_string(), message }
+    })?;
+    Ok(Box::new(transformer))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  TransformPluginFactory {
+        name: "FilterTransformer",
+        constructor: construct_filter_transformer,
+    }
+}
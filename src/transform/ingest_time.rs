@@ -0,0 +1,53 @@
+use super::{EventTransformer, FieldPath, RequestContext, Transformed};
+use crate::{
+    config::IngestTimeTransformConfig,
+    event::Event,
+    plugins::{PluginError, TransformPluginFactory},
+};
+
+/// Records when the server received the event, next to the timestamp the
+/// source reported.
+#[derive(Debug)]
+pub struct IngestTimeTransformer {
+    pub field: FieldPath,
+    pub overwrite: bool,
+}
+
+impl IngestTimeTransformer {
+    pub fn new(config: IngestTimeTransformConfig) -> Self {
+        Self { field: config.field, overwrite: config.overwrite }
+    }
+}
+
+impl EventTransformer for IngestTimeTransformer {
+    fn name(&self) -> &'static str {
+        "IngestTimeTransformer"
+    }
+
+    fn transform(&self, event: &mut Event, context: &RequestContext) -> Transformed {
+        if let Err(err) = self.field.set(event, context.received_at.to_rfc3339(), self.overwrite) {
+            tracing::warn!("Failed to set ingest time: {}", err);
+        }
+        Transformed::Keep
+    }
+}
+
+/// Constructs an IngestTimeTransformer from the given parameters.
+fn construct_ingest_time_transformer(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventTransformer + Send + Sync>, PluginError> {
+    let config: IngestTimeTransformConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "IngestTimeTransformer".to_string(),
+            source: e,
+        })?;
+    Ok(Box::new(IngestTimeTransformer::new(config)))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  TransformPluginFactory {
+        name: "IngestTimeTransformer",
+        constructor: construct_ingest_time_transformer,
+    }
+}
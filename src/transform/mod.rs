@@ -0,0 +1,158 @@
+pub mod client_address;
+pub mod filter;
+pub mod ingest_time;
+pub mod redaction;
+pub mod sampling;
+pub mod static_labels;
+
+use std::{fmt::Debug, net::SocketAddr, str::FromStr};
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::event::Event;
+
+/// The request an event was ingested with.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    /// Time the request was received
+    pub received_at: DateTime<Utc>,
+    /// Address of the connected peer
+    pub client_addr: Option<SocketAddr>,
+    /// Request headers. The tenant of the request (with `api_key` resolution,
+    /// the identity the API key maps to) is already set on the event.
+    pub headers: HeaderMap,
+}
+
+/// What happens to an event after a transformer ran.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transformed {
+    Keep,
+    /// Drop the event, with the reason
+    Drop(String),
+}
+
+pub trait EventTransformer: Send + Sync + Debug {
+    /// Transform a validated event before it is queued for processing, e.g.
+    /// add fields, rename keys in `data` or normalize the type.
+    fn transform(&self, event: &mut Event, context: &RequestContext) -> Transformed;
+
+    /// Transformer name (for logging purposes).
+    fn name(&self) -> &'static str;
+}
+
+/// A field of an event a transformer writes to: a label (`labels.<key>`) or a
+/// key in `data` (`data.<key>`, nested keys separated by `.`).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum FieldPath {
+    Label(String),
+    Data(Vec<String>),
+}
+
+impl FromStr for FieldPath {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid field '{}', expected labels.<key> or data.<key>", s);
+        match s.split_once('.') {
+            Some(("labels", key)) if !key.is_empty() => Ok(Self::Label(key.to_string())),
+            Some(("data", path)) => {
+                let keys: Vec<String> = path.split('.').map(str::to_string).collect();
+                if keys.iter().any(String::is_empty) {
+                    return Err(invalid());
+                }
+                Ok(Self::Data(keys))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl TryFrom<String> for FieldPath {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl FieldPath {
+    /// Sets the field, keeping a present value unless `overwrite` is set.
+    /// Missing `data` objects are created along the path; a non-object value
+    /// in the way is an error and leaves the event unchanged.
+    pub fn set(&self, event: &mut Event, value: String, overwrite: bool) -> Result<(), String> {
+        match self {
+            Self::Label(key) => {
+                if overwrite || !event.labels.contains_key(key) {
+                    event.labels.insert(key.clone(), value);
+                }
+                Ok(())
+            }
+            Self::Data(keys) => {
+                let Some((last, parents)) = keys.split_last() else {
+                    return Ok(());
+                };
+                let not_an_object =
+                    || format!("Parent of data.{} is not an object", keys.join("."));
+                let mut object = event.data.get_or_insert_with(|| Value::Object(Map::new()));
+                for key in parents {
+                    object = object
+                        .as_object_mut()
+                        .ok_or_else(not_an_object)?
+                        .entry(key.clone())
+                        .or_insert_with(|| Value::Object(Map::new()));
+                }
+                let object = object.as_object_mut().ok_or_else(not_an_object)?;
+                if overwrite || !object.contains_key(last) {
+                    object.insert(last.clone(), Value::String(value));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::event::EventType;
+
+    #[test]
+    fn test_sets_fields() {
+        let mut event = Event {
+            source_id: 1.into(),
+            r#type: EventType::Custom("Login".to_string()),
+            timestamp: Utc::now(),
+            data: Some(json!({ "user": "a", "scalar": 1 })),
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
+        let set = |event: &mut Event, field: &str, value: &str, overwrite| {
+            let Ok(field) = field.parse::<FieldPath>() else {
+                panic!("field should parse");
+            };
+            field.set(event, value.to_string(), overwrite)
+        };
+
+        assert!(set(&mut event, "labels.region", "eu", false).is_ok());
+        assert!(set(&mut event, "labels.region", "us", false).is_ok());
+        assert!(set(&mut event, "data.meta.ingested_at", "now", true).is_ok());
+        assert!(set(&mut event, "data.user", "b", false).is_ok());
+        assert!(set(&mut event, "data.scalar.nested", "x", true).is_err());
+        assert_eq!(event.labels.get("region").map(String::as_str), Some("eu"));
+        assert_eq!(
+            event.data,
+            Some(json!({ "user": "a", "scalar": 1, "meta": { "ingested_at": "now" } }))
+        );
+
+        assert!("type".parse::<FieldPath>().is_err());
+        assert!("data.a..b".parse::<FieldPath>().is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde_json::Value;
+use sha2::Sha256;
+
+use super::FieldPath;
+use crate::{
+    config::{BuiltinPattern, RedactionAction, RedactionConfig},
+    event::{Event, TypedPayload},
+    metrics::REDACTIONS_TOTAL,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+impl BuiltinPattern {
+    fn regex(self) -> &'static str {
+        match self {
+            Self::Email => r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+            Self::Ipv4 => r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b",
+            Self::CardNumber => r"\b\d(?:[ -]?\d){12,18}\b",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Email => "email",
+            Self::Ipv4 => "ipv4",
+            Self::CardNumber => "card_number",
+        }
+    }
+}
+
+/// Whether the digits of a candidate card number pass the Luhn check, which
+/// rules out most digit runs that are not card numbers.
+fn is_luhn_valid(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            0 => digit,
+            _ if digit * 2 > 9 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// A pattern redacted wherever it occurs in string values.
+#[derive(Debug)]
+struct Pattern {
+    /// Name reported in the redactions metric
+    name: String,
+    regex: Regex,
+    /// Only redact matches passing the Luhn check
+    luhn: bool,
+}
+
+/// Scrubs personal data from events before they are queued, so it never
+/// reaches processors, sinks or the DLQ log. Configured fields are redacted as
+/// a whole, patterns wherever they occur in `data` strings and label values.
+/// Runs after all transformers, so it also covers the fields they add.
+#[derive(Debug)]
+pub struct Redactor {
+    paths: Vec<FieldPath>,
+    patterns: Vec<Pattern>,
+    action: RedactionAction,
+    mask: String,
+    hasher: Option<HmacSha256>,
+}
+
+impl Redactor {
+    /// Compiles the regexes, reads the hash key and checks that no path breaks
+    /// a typed payload, so invalid settings fail at startup.
+    pub fn new(config: RedactionConfig) -> Result<Self, String> {
+        let builtin = config.patterns.iter().map(|pattern| {
+            Regex::new(pattern.regex())
+                .map(|regex| Pattern {
+                    name: pattern.name().to_string(),
+                    regex,
+                    luhn: *pattern == BuiltinPattern::CardNumber,
+                })
+                .map_err(|e| e.to_string())
+        });
+        let custom = config.regexes.iter().map(|source| {
+            Regex::new(source)
+                .map(|regex| Pattern { name: "regex".to_string(), regex, luhn: false })
+                .map_err(|e| format!("Invalid regex '{}': {}", source, e))
+        });
+        let patterns = builtin.chain(custom).collect::<Result<Vec<_>, _>>()?;
+
+        let hash_key = match (&config.hash_key, &config.hash_key_env) {
+            (Some(key), None) => Some(key.clone()),
+            (None, Some(var)) => Some(
+                std::env::var(var)
+                    .map_err(|e| format!("Failed to read hash key from {}: {}", var, e))?,
+            ),
+            (None, None) => None,
+            (Some(_), Some(_)) => {
+                return Err("Only one of hash_key and hash_key_env can be set".to_string());
+            }
+        };
+        let hasher = match hash_key {
+            Some(key) if !key.is_empty() => {
+                Some(HmacSha256::new_from_slice(key.as_bytes()).map_err(|e| e.to_string())?)
+            }
+            _ if config.action == RedactionAction::Hash => {
+                return Err("The hash action requires a non-empty hash key".to_string());
+            }
+            _ => None,
+        };
+
+        // A redacted required field of a typed payload has to stay valid
+        for path in &config.paths {
+            let FieldPath::Data(keys) = path else {
+                continue;
+            };
+            let [key] = keys.as_slice() else {
+                continue;
+            };
+            let conflict = TypedPayload::REQUIRED_FIELDS.iter().find(|(_, field, any_string)| {
+                key == field && (config.action == RedactionAction::Remove || !any_string)
+            });
+            if let Some((event_type, _, _)) = conflict {
+                return Err(format!(
+                    "Redacting data.{} would break the payload of {} events",
+                    key, event_type
+                ));
+            }
+        }
+
+        if config.paths.is_empty() && patterns.is_empty() {
+            tracing::warn!("Redaction enabled with no paths or patterns.");
+        }
+        Ok(Self { paths: config.paths, patterns, action: config.action, mask: config.mask, hasher })
+    }
+
+    /// Replacement of a redacted value, `None` to remove it.
+    fn replacement(&self, value: &str) -> Option<String> {
+        match (self.action, &self.hasher) {
+            (RedactionAction::Remove, _) => None,
+            (RedactionAction::Hash, Some(hasher)) => {
+                let mut hasher = hasher.clone();
+                hasher.update(value.as_bytes());
+                let digest = hasher.finalize().into_bytes();
+                Some(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+            }
+            _ => Some(self.mask.clone()),
+        }
+    }
+
+    /// Redacts the value at `keys` below `value`, applying the keys to every
+    /// element of arrays on the way. Returns the number of redacted values.
+    fn redact_path(&self, value: &mut Value, keys: &[String]) -> usize {
+        match value {
+            Value::Array(items) => items.iter_mut().map(|item| self.redact_path(item, keys)).sum(),
+            Value::Object(object) => {
+                let Some((key, rest)) = keys.split_first() else {
+                    return 0;
+                };
+                let Some(child) = object.get_mut(key) else {
+                    return 0;
+                };
+                if !rest.is_empty() {
+                    return self.redact_path(child, rest);
+                }
+                let raw = match &*child {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                match self.replacement(&raw) {
+                    Some(replacement) => *child = Value::String(replacement),
+                    None => {
+                        object.remove(key);
+                    }
+                }
+                1
+            }
+            _ => 0,
+        }
+    }
+
+    /// Redacts the matches of a pattern in a string. Returns the number of
+    /// redacted matches.
+    fn redact_matches(&self, pattern: &Pattern, text: &mut String) -> usize {
+        let mut count = 0;
+        let redacted = pattern.regex.replace_all(text, |caps: &regex::Captures| {
+            let matched = &caps[0];
+            if pattern.luhn && !is_luhn_valid(matched) {
+                return matched.to_string();
+            }
+            count += 1;
+            self.replacement(matched).unwrap_or_default()
+        });
+        if count > 0 {
+            *text = redacted.into_owned();
+        }
+        count
+    }
+
+    /// Redacts the matches of a pattern in all strings below `value`.
+    fn redact_strings(&self, pattern: &Pattern, value: &mut Value) -> usize {
+        match value {
+            Value::String(text) => self.redact_matches(pattern, text),
+            Value::Array(items) => {
+                items.iter_mut().map(|item| self.redact_strings(pattern, item)).sum()
+            }
+            Value::Object(object) => {
+                object.values_mut().map(|item| self.redact_strings(pattern, item)).sum()
+            }
+            _ => 0,
+        }
+    }
+
+    /// Redacts the configured paths and patterns of an event.
+    pub fn redact(&self, event: &mut Event) {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+
+        for path in &self.paths {
+            let count = match path {
+                FieldPath::Label(key) => match event.labels.get(key).map(|v| self.replacement(v)) {
+                    Some(Some(replacement)) => {
+                        event.labels.insert(key.clone(), replacement);
+                        1
+                    }
+                    Some(None) => {
+                        event.labels.remove(key);
+                        1
+                    }
+                    None => 0,
+                },
+                FieldPath::Data(keys) => {
+                    event.data.as_mut().map_or(0, |data| self.redact_path(data, keys))
+                }
+            };
+            *counts.entry("path").or_default() += count;
+        }
+
+        for pattern in &self.patterns {
+            let mut count =
+                event.data.as_mut().map_or(0, |data| self.redact_strings(pattern, data));
+            for value in event.labels.values_mut() {
+                count += self.redact_matches(pattern, value);
+            }
+            *counts.entry(&pattern.name).or_default() += count;
+        }
+
+        for (rule, count) in counts {
+            if count > 0 {
+                metrics::counter!(REDACTIONS_TOTAL, "rule" => rule.to_string())
+                    .increment(count as u64);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::*;
+    use crate::event::EventType;
+
+    fn create_config(action: RedactionAction) -> RedactionConfig {
+        RedactionConfig {
+            enabled: true,
+            paths: vec![FieldPath::Data(vec!["users".to_string(), "name".to_string()])],
+            patterns: vec![BuiltinPattern::Email, BuiltinPattern::CardNumber],
+            regexes: vec![],
+            action,
+            mask: "***".to_string(),
+            hash_key: Some("secret".to_string()),
+            hash_key_env: None,
+        }
+    }
+
+    fn redact(redactor: &Redactor) -> Event {
+        let mut event = Event {
+            source_id: 1.into(),
+            r#type: EventType::Custom("Checkout".to_string()),
+            timestamp: Utc::now(),
+            data: Some(json!({
+                "users": [{ "name": "Ann", "id": 1 }, { "name": "Bob" }],
+                "note": "paid with 4111 1111 1111 1111 by ann@example.com, order 1234567890123",
+            })),
+            labels: HashMap::from([("contact".to_string(), "bob@example.com".to_string())]),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
+        redactor.redact(&mut event);
+        event
+    }
+
+    #[test]
+    fn test_masks_paths_and_patterns() {
+        let Ok(redactor) = Redactor::new(create_config(RedactionAction::Mask)) else {
+            panic!("config should be valid");
+        };
+        let event = redact(&redactor);
+        assert_eq!(
+            event.data,
+            Some(json!({
+                "users": [{ "name": "***", "id": 1 }, { "name": "***" }],
+                "note": "paid with *** by ***, order 1234567890123",
+            }))
+        );
+        assert_eq!(event.labels.get("contact").map(String::as_str), Some("***"));
+    }
+
+    #[test]
+    fn test_hashes_and_removes() {
+        let Ok(redactor) = Redactor::new(create_config(RedactionAction::Hash)) else {
+            panic!("config should be valid");
+        };
+        let first = redact(&redactor);
+        let second = redact(&redactor);
+        let name = |event: &Event| event.data.as_ref().map(|data| data["users"][1]["name"].clone());
+        assert_eq!(name(&first), name(&second));
+        assert_ne!(name(&first), Some(json!("Bob")));
+
+        let Ok(redactor) = Redactor::new(create_config(RedactionAction::Remove)) else {
+            panic!("config should be valid");
+        };
+        let event = redact(&redactor);
+        assert_eq!(
+            event.data.as_ref().map(|data| data["users"][0].clone()),
+            Some(json!({ "id": 1 }))
+        );
+
+        let mut config = create_config(RedactionAction::Hash);
+        config.hash_key = None;
+        assert!(Redactor::new(config).is_err());
+    }
+
+    #[test]
+    fn test_rejects_paths_breaking_typed_payloads() {
+        let with_path = |action, path: &str| RedactionConfig {
+            paths: vec![FieldPath::Data(vec![path.to_string()])],
+            ..create_config(action)
+        };
+
+        // Log and Error messages stay strings when masked or hashed
+        assert!(Redactor::new(with_path(RedactionAction::Mask, "message")).is_ok());
+        assert!(Redactor::new(with_path(RedactionAction::Hash, "message")).is_ok());
+        assert!(Redactor::new(with_path(RedactionAction::Remove, "message")).is_err());
+        // A metric value is a number
+        assert!(Redactor::new(with_path(RedactionAction::Mask, "value")).is_err());
+        assert!(Redactor::new(with_path(RedactionAction::Remove, "unit")).is_ok());
+    }
+}
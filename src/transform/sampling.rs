@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+
+use sha2::{Digest, Sha256};
+
+use super::{EventTransformer, RequestContext, Transformed};
+use crate::{
+    config::{SamplingGroupConfig, SamplingTransformConfig, SourceIdRange},
+    event::{Event, EventType, SourceId},
+    plugins::{PluginError, TransformPluginFactory},
+};
+
+/// Keep one in `weight` events, or none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SampleRate {
+    OneIn(u32),
+    DropAll,
+}
+
+impl SampleRate {
+    /// Rates must keep one in a whole number of events, so kept events have
+    /// an integer weight and the scaled statistics stay unbiased.
+    fn parse(rate: f64) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&rate) {
+            return Err(format!("Sample rate {} is not between 0 and 1", rate));
+        }
+        if rate == 0.0 {
+            return Ok(Self::DropAll);
+        }
+        let weight = (1.0 / rate).round();
+        if weight > u32::MAX as f64 || (1.0 / rate - weight).abs() > weight * 1e-6 {
+            return Err(format!(
+                "Sample rate {} is not one in a whole number of events, e.g. 0.5, 0.1 or 0.01",
+                rate
+            ));
+        }
+        Ok(Self::OneIn(weight as u32))
+    }
+
+    fn parse_rates(rates: HashMap<EventType, f64>) -> Result<HashMap<EventType, Self>, String> {
+        rates.into_iter().map(|(event_type, rate)| Ok((event_type, Self::parse(rate)?))).collect()
+    }
+}
+
+#[derive(Debug)]
+struct SamplingGroup {
+    sources: Vec<SourceId>,
+    source_ranges: Vec<SourceIdRange>,
+    rates: HashMap<EventType, SampleRate>,
+}
+
+impl SamplingGroup {
+    fn new(config: SamplingGroupConfig) -> Result<Self, String> {
+        let rates = SampleRate::parse_rates(config.rates)
+            .map_err(|e| format!("Group '{}': {}", config.name, e))?;
+        Ok(Self { sources: config.sources, source_ranges: config.source_ranges, rates })
+    }
+
+    fn contains(&self, source_id: &SourceId) -> bool {
+        self.sources.contains(source_id)
+            || self.source_ranges.iter().any(|range| range.contains(source_id))
+    }
+}
+
+/// Keeps a deterministic sample of events per event type and source group.
+/// Kept events carry their sample weight, so statistics still estimate the
+/// ingested totals.
+#[derive(Debug)]
+pub struct SamplingTransformer {
+    default_rate: SampleRate,
+    rates: HashMap<EventType, SampleRate>,
+    groups: Vec<SamplingGroup>,
+}
+
+impl SamplingTransformer {
+    pub fn new(config: SamplingTransformConfig) -> Result<Self, String> {
+        Ok(Self {
+            default_rate: SampleRate::parse(config.default_rate)?,
+            rates: SampleRate::parse_rates(config.rates)?,
+            groups: config.groups.into_iter().map(SamplingGroup::new).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Rate of the first group of the source that has one for the event type,
+    /// else the top-level rate.
+    fn rate(&self, event: &Event) -> SampleRate {
+        self.groups
+            .iter()
+            .filter(|group| group.contains(&event.source_id))
+            .find_map(|group| group.rates.get(&event.r#type))
+            .or_else(|| self.rates.get(&event.r#type))
+            .copied()
+            .unwrap_or(self.default_rate)
+    }
+
+    /// Uniform hash of the event's source, type and timestamp, stable across
+    /// restarts, so a resubmitted event gets the same decision.
+    fn sample_hash(event: &Event) -> u64 {
+        let mut hasher = Sha256::new();
+        hasher.update(event.tenant.to_string());
+        hasher.update([0]);
+        hasher.update(event.source_id.to_string());
+        hasher.update([0]);
+        hasher.update(event.r#type.to_string());
+        hasher.update([0]);
+        hasher.update(event.timestamp.timestamp_nanos_opt().unwrap_or_default().to_le_bytes());
+        let digest = hasher.finalize();
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        u64::from_le_bytes(bytes)
+    }
+}
+
+impl EventTransformer for SamplingTransformer {
+    fn name(&self) -> &'static str {
+        "SamplingTransformer"
+    }
+
+    fn transform(&self, event: &mut Event, _context: &RequestContext) -> Transformed {
+        match self.rate(event) {
+            SampleRate::DropAll => {
+                Transformed::Drop(format!("{} events are dropped", event.r#type))
+            }
+            SampleRate::OneIn(1) => Transformed::Keep,
+            SampleRate::OneIn(weight) => {
+                if Self::sample_hash(event).is_multiple_of(u64::from(weight)) {
+                    event.sample_weight = event.sample_weight.saturating_mul(weight);
+                    Transformed::Keep
+                } else {
+                    Transformed::Drop(format!("sampled out (1 in {})", weight))
+                }
+            }
+        }
+    }
+}
+
+/// Constructs a SamplingTransformer from the given parameters.
+fn construct_sampling_transformer(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventTransformer + Send + Sync>, PluginError> {
+    let config: SamplingTransformConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "SamplingTransformer".to_string(),
+            source: e,
+        })?;
+    let transformer = SamplingTransformer::new(config).map_err(|message| {
+        PluginError::InvalidParameters { plugin_name: "SamplingTransformer".to_string(), message }
+    })?;
+    Ok(Box::new(transformer))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  TransformPluginFactory {
+        name: "SamplingTransformer",
+        constructor: construct_sampling_transformer,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderMap;
+    use chrono::{Duration, Utc};
+
+    use super::*;
+
+    #[test]
+    fn test_samples_by_type_and_group() {
+        let crash = EventType::Custom("Crash".to_string());
+        let config = SamplingTransformConfig {
+            default_rate: 1.0,
+            rates: HashMap::from([(EventType::Heartbeat, 0.01), (crash.clone(), 1.0)]),
+            groups: vec![SamplingGroupConfig {
+                name: "canary".to_string(),
+                sources: vec![],
+                source_ranges: vec![SourceIdRange { start: 100, end: 199 }],
+                rates: HashMap::from([(EventType::Heartbeat, 1.0), (crash.clone(), 0.0)]),
+            }],
+        };
+        let Ok(sampler) = SamplingTransformer::new(config) else {
+            panic!("config should be valid");
+        };
+        let context = RequestContext {
+            received_at: Utc::now(),
+            client_addr: None,
+            headers: HeaderMap::new(),
+        };
+        let start = Utc::now();
+        let sample = |source_id: u64, event_type: &EventType| {
+            (0..1000)
+                .map(|i| Event {
+                    source_id: source_id.into(),
+                    r#type: event_type.clone(),
+                    timestamp: start + Duration::milliseconds(i),
+                    data: None,
+                    labels: Default::default(),
+                    tenant: Default::default(),
+                    sample_weight: 1,
+                    payload: None,
+                })
+                .filter_map(|mut event| match sampler.transform(&mut event, &context) {
+                    Transformed::Keep => Some(u64::from(event.sample_weight)),
+                    Transformed::Drop(_) => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let heartbeats = sample(1, &EventType::Heartbeat);
+        assert!((1..40).contains(&heartbeats.len()));
+        assert!(heartbeats.iter().all(|&weight| weight == 100));
+        assert_eq!(sample(1, &crash).len(), 1000);
+        assert_eq!(sample(150, &EventType::Heartbeat).len(), 1000);
+        assert!(sample(150, &crash).is_empty());
+        assert_eq!(sample(1, &EventType::Heartbeat), heartbeats);
+    }
+
+    #[test]
+    fn test_rejects_rates_of_fractional_weight() {
+        assert_eq!(SampleRate::parse(0.01), Ok(SampleRate::OneIn(100)));
+        assert_eq!(SampleRate::parse(1.0 / 3.0), Ok(SampleRate::OneIn(3)));
+        assert!(SampleRate::parse(0.3).is_err());
+        assert!(SampleRate::parse(0.75).is_err());
+        assert!(SampleRate::parse(1e-12).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use super::{EventTransformer, RequestContext, Transformed};
+use crate::{
+    config::StaticLabelsTransformConfig,
+    event::Event,
+    plugins::{PluginError, TransformPluginFactory},
+};
+
+/// Adds fixed labels to every event, e.g. the deployment region.
+#[derive(Debug)]
+pub struct StaticLabelsTransformer {
+    pub labels: HashMap<String, String>,
+    pub overwrite: bool,
+}
+
+impl StaticLabelsTransformer {
+    pub fn new(config: StaticLabelsTransformConfig) -> Self {
+        Self { labels: config.labels, overwrite: config.overwrite }
+    }
+}
+
+impl EventTransformer for StaticLabelsTransformer {
+    fn name(&self) -> &'static str {
+        "StaticLabelsTransformer"
+    }
+
+    fn transform(&self, event: &mut Event, _context: &RequestContext) -> Transformed {
+        for (key, value) in &self.labels {
+            if self.overwrite || !event.labels.contains_key(key) {
+                event.labels.insert(key.clone(), value.clone());
+            }
+        }
+        Transformed::Keep
+    }
+}
+
+/// Constructs a StaticLabelsTransformer from the given parameters.
+fn construct_static_labels_transformer(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventTransformer + Send + Sync>, PluginError> {
+    let config: StaticLabelsTransformConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "StaticLabelsTransformer".to_string(),
+            source: e,
+        })?;
+    Ok(Box::new(StaticLabelsTransformer::new(config)))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  TransformPluginFactory {
+        name: "StaticLabelsTransformer",
+        constructor: construct_static_labels_transformer,
+    }
+}
@@ -1,6 +1,9 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use super::{EventValidationError, EventValidator};
+use super::{
+    EventValidationError, EventValidator,
+    managed::{ListChange, ListKind, ListOverrides, ManagedLists, SharedOverlay},
+};
 use crate::{
     config::EventTypeValidationConfig,
     event::{Event, EventType},
@@ -10,6 +13,10 @@ use crate::{
 #[derive(Debug)]
 pub struct EventTypeValidator {
     pub allowed_types: HashSet<EventType>,
+    pub denied_types: HashSet<EventType>,
+    /// Changes of the lists made at runtime
+    allowed_overlay: SharedOverlay<EventType>,
+    denied_overlay: SharedOverlay<EventType>,
 }
 
 impl EventTypeValidator {
@@ -25,7 +32,12 @@ impl EventTypeValidator {
                 config.allowed
             );
         }
-        Self { allowed_types: config.allowed }
+        Self {
+            allowed_types: config.allowed,
+            denied_types: config.denied,
+            allowed_overlay: SharedOverlay::default(),
+            denied_overlay: SharedOverlay::default(),
+        }
     }
 }
 
@@ -35,12 +47,55 @@ impl EventValidator for EventTypeValidator {
     }
 
     fn validate(&self, event: &Event) -> Result<(), EventValidationError> {
-        if self.allowed_types.is_empty() || self.allowed_types.contains(&event.r#type) {
+        // The denylist takes precedence over the allowlist
+        if self.denied_overlay.get().contains(&event.r#type, |t| self.denied_types.contains(t)) {
+            return Err(EventValidationError::DeniedEventType(event.r#type.clone()));
+        }
+        if self.allowed_types.is_empty()
+            || self
+                .allowed_overlay
+                .get()
+                .contains(&event.r#type, |t| self.allowed_types.contains(t))
+        {
             Ok(())
         } else {
             Err(EventValidationError::DisallowedEventType(event.r#type.clone()))
         }
     }
+
+    fn lists(&self) -> Option<&dyn ManagedLists> {
+        Some(self)
+    }
+}
+
+impl EventTypeValidator {
+    fn list(&self, kind: ListKind) -> (&HashSet<EventType>, &SharedOverlay<EventType>) {
+        match kind {
+            ListKind::Allowed => (&self.allowed_types, &self.allowed_overlay),
+            ListKind::Denied => (&self.denied_types, &self.denied_overlay),
+        }
+    }
+}
+
+impl ManagedLists for EventTypeValidator {
+    fn entries(&self, kind: ListKind) -> Vec<String> {
+        let (configured, overlay) = self.list(kind);
+        overlay.get().entries(configured.iter().map(ToString::to_string))
+    }
+
+    fn update(&self, kind: ListKind, change: &ListChange) -> Result<(), String> {
+        if kind == ListKind::Allowed && self.allowed_types.is_empty() {
+            return Err("EventTypeValidator has no allowlist configured".to_string());
+        }
+        self.list(kind).1.apply(change)
+    }
+
+    fn overrides(&self) -> HashMap<ListKind, ListOverrides> {
+        [ListKind::Allowed, ListKind::Denied]
+            .into_iter()
+            .map(|kind| (kind, self.list(kind).1.get().overrides()))
+            .collect()
+    }
 }
 
 /// Constructs an EventTypeValidator from the given parameters.
@@ -77,10 +132,18 @@ mod tests {
     #[test]
     fn test_validates_allowed_event_type() {
         let allowed = HashSet::from([EventType::Heartbeat, EventType::Custom("Test".to_string())]);
-        let config = EventTypeValidationConfig { allowed };
+        let config = EventTypeValidationConfig { allowed, ..Default::default() };
         let validator = EventTypeValidator::new(config);
-        let event =
-            Event { source_id: 1, r#type: EventType::Heartbeat, timestamp: Utc::now(), data: None };
+        let event = Event {
+            source_id: 1.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
 
         assert!(validator.validate(&event).is_ok());
     }
@@ -88,21 +151,70 @@ mod tests {
     #[test]
     fn test_validates_disallowed_event_type() {
         let allowed = HashSet::from([EventType::Custom("Test".to_string())]);
-        let config = EventTypeValidationConfig { allowed };
+        let config = EventTypeValidationConfig { allowed, ..Default::default() };
         let validator = EventTypeValidator::new(config);
-        let event =
-            Event { source_id: 1, r#type: EventType::Heartbeat, timestamp: Utc::now(), data: None };
+        let event = Event {
+            source_id: 1.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
 
         assert!(validator.validate(&event).is_err());
     }
 
+    #[test]
+    fn test_manages_lists_at_runtime() {
+        let allowed = HashSet::from([EventType::Heartbeat]);
+        let config = EventTypeValidationConfig { allowed, ..Default::default() };
+        let validator = EventTypeValidator::new(config);
+        let mut event = Event {
+            source_id: 1.into(),
+            r#type: EventType::Custom("Login".to_string()),
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
+        let change = |add: &[&str], remove: &[&str]| ListChange {
+            add: add.iter().map(|s| s.to_string()).collect(),
+            remove: remove.iter().map(|s| s.to_string()).collect(),
+        };
+
+        assert!(validator.validate(&event).is_err());
+        assert!(validator.update(ListKind::Allowed, &change(&["Login"], &[])).is_ok());
+        assert!(validator.validate(&event).is_ok());
+        assert_eq!(validator.entries(ListKind::Allowed), vec!["Heartbeat", "Login"]);
+
+        event.r#type = EventType::Heartbeat;
+        assert!(validator.update(ListKind::Denied, &change(&["Heartbeat"], &[])).is_ok());
+        assert!(matches!(
+            validator.validate(&event),
+            Err(EventValidationError::DeniedEventType(EventType::Heartbeat))
+        ));
+    }
+
     #[test]
     fn test_validates_empty_allowed_event_types() {
         let allowed = HashSet::new();
-        let config = EventTypeValidationConfig { allowed };
+        let config = EventTypeValidationConfig { allowed, ..Default::default() };
         let validator = EventTypeValidator::new(config);
-        let event =
-            Event { source_id: 1, r#type: EventType::Heartbeat, timestamp: Utc::now(), data: None };
+        let event = Event {
+            source_id: 1.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        };
 
         assert!(validator.validate(&event).is_ok());
     }
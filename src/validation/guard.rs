@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use super::{EventValidationError, EventValidator, managed::ManagedLists};
+use crate::{
+    config::{FailurePolicy, ValidatorChainConfig, ValidatorMode},
+    event::Event,
+    metrics::{VALIDATION_SHADOW_REJECTS_TOTAL, VALIDATOR_UNAVAILABLE_TOTAL},
+};
+
+/// Runs a validator in the chain with its timeout, failure policy and mode. A
+/// validator that can't answer (it timed out or its backing store failed)
+/// either rejects the event or lets it pass, depending on the policy. A
+/// validator in shadow mode never rejects events, its failures are only
+/// logged and counted.
+#[derive(Debug)]
+pub struct GuardedValidator {
+    inner: Box<dyn EventValidator + Send + Sync>,
+    timeout: Option<Duration>,
+    on_failure: FailurePolicy,
+    mode: ValidatorMode,
+}
+
+impl GuardedValidator {
+    pub fn new(
+        inner: Box<dyn EventValidator + Send + Sync>,
+        config: &ValidatorChainConfig,
+    ) -> Self {
+        Self {
+            inner,
+            timeout: config.timeout.map(Duration::from_millis),
+            on_failure: config.on_failure,
+            mode: config.mode,
+        }
+    }
+
+    /// Applies the failure policy and mode to the result of the inner
+    /// validator.
+    fn guard(&self, result: Result<(), EventValidationError>) -> Result<(), EventValidationError> {
+        let result = self.apply_policy(result);
+        match (self.mode, result) {
+            (ValidatorMode::Shadow, Err(err)) => {
+                tracing::info!(validator = self.name(), "Shadow mode, accepting event: {}", err);
+                metrics::counter!(
+                    VALIDATION_SHADOW_REJECTS_TOTAL,
+                    "validator" => self.name(),
+                    "reason" => err.reason()
+                )
+                .increment(1);
+                Ok(())
+            }
+            (_, result) => result,
+        }
+    }
+
+    fn apply_policy(
+        &self,
+        result: Result<(), EventValidationError>,
+    ) -> Result<(), EventValidationError> {
+        let Err(err @ EventValidationError::ValidatorUnavailable { .. }) = result else {
+            return result;
+        };
+        let policy = match self.on_failure {
+            FailurePolicy::FailOpen => "fail_open",
+            FailurePolicy::FailClosed => "fail_closed",
+        };
+        metrics::counter!(VALIDATOR_UNAVAILABLE_TOTAL, "validator" => self.name(), "policy" => policy)
+            .increment(1);
+        match self.on_failure {
+            FailurePolicy::FailOpen => {
+                tracing::warn!("Letting event pass, {}", err);
+                Ok(())
+            }
+            FailurePolicy::FailClosed => Err(err),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventValidator for GuardedValidator {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn validate(&self, event: &Event) -> Result<(), EventValidationError> {
+        self.guard(self.inner.validate(event))
+    }
+
+    async fn validate_async(&self, event: &Event) -> Result<(), EventValidationError> {
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.inner.validate_async(event))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(EventValidationError::ValidatorUnavailable {
+                        validator: self.name(),
+                        message: format!("timed out after {}ms", timeout.as_millis()),
+                    })
+                }),
+            None => self.inner.validate_async(event).await,
+        };
+        self.guard(result)
+    }
+
+    fn validate_transformed(&self, event: &Event) -> Result<(), EventValidationError> {
+        self.guard(self.inner.validate_transformed(event))
+    }
+
+    fn supports_timeout(&self) -> bool {
+        self.inner.supports_timeout()
+    }
+
+    fn start(&self) {
+        self.inner.start();
+    }
+
+    fn lists(&self) -> Option<&dyn ManagedLists> {
+        self.inner.lists()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{
+        config::EventTypeValidationConfig, event::EventType,
+        validation::event_type::EventTypeValidator,
+    };
+
+    /// A validator whose lookups take longer than any test timeout.
+    #[derive(Debug)]
+    struct SlowValidator;
+
+    #[async_trait::async_trait]
+    impl EventValidator for SlowValidator {
+        fn name(&self) -> &'static str {
+            "SlowValidator"
+        }
+
+        fn validate(&self, _event: &Event) -> Result<(), EventValidationError> {
+            Ok(())
+        }
+
+        async fn validate_async(&self, _event: &Event) -> Result<(), EventValidationError> {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(())
+        }
+
+        fn supports_timeout(&self) -> bool {
+            true
+        }
+    }
+
+    fn create_event() -> Event {
+        Event {
+            source_id: 1.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_applies_failure_policy_on_timeout() {
+        let config = |on_failure| ValidatorChainConfig {
+            timeout: Some(10),
+            on_failure,
+            ..Default::default()
+        };
+
+        let closed =
+            GuardedValidator::new(Box::new(SlowValidator), &config(FailurePolicy::FailClosed));
+        assert!(matches!(
+            closed.validate_async(&create_event()).await,
+            Err(EventValidationError::ValidatorUnavailable { validator: "SlowValidator", .. })
+        ));
+
+        let open = GuardedValidator::new(Box::new(SlowValidator), &config(FailurePolicy::FailOpen));
+        assert!(open.validate_async(&create_event()).await.is_ok());
+    }
+
+    #[test]
+    fn test_shadow_mode_accepts_failing_events() {
+        let validator = || {
+            Box::new(EventTypeValidator::new(EventTypeValidationConfig {
+                allowed: HashSet::from([EventType::Log]),
+                denied: HashSet::new(),
+            }))
+        };
+
+        let enforce = GuardedValidator::new(validator(), &ValidatorChainConfig::default());
+        assert!(matches!(
+            enforce.validate(&create_event()),
+            Err(EventValidationError::DisallowedEventType(EventType::Heartbeat))
+        ));
+
+        let config = ValidatorChainConfig { mode: ValidatorMode::Shadow, ..Default::default() };
+        let shadow = GuardedValidator::new(validator(), &config);
+        assert!(shadow.validate(&create_event()).is_ok());
+    }
+}
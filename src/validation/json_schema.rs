@@ -0,0 +1,712 @@
+use std::{collections::HashMap, fs};
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use super::{EventValidationError, EventValidator};
+use crate::{
+    config::{JsonSchemaValidationConfig, SchemaSourceConfig, UnknownTypePolicy},
+    event::{Event, EventType},
+    plugins::{PluginError, ValidationPluginFactory},
+};
+
+/// Keywords that carry no validation semantics and are ignored.
+const ANNOTATION_KEYWORDS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$comment",
+    "title",
+    "description",
+    "default",
+    "examples",
+    "format",
+    "deprecated",
+    "readOnly",
+    "writeOnly",
+];
+
+/// Error in a schema document, `pointer` locates the offending keyword.
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid schema at '{pointer}': {message}")]
+pub struct SchemaError {
+    pub pointer: String,
+    pub message: String,
+}
+
+/// A value not matching a schema, `pointer` locates the offending value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+    pub pointer: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InstanceType {
+    Null,
+    Boolean,
+    Object,
+    Array,
+    Number,
+    Integer,
+    String,
+}
+
+impl InstanceType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "null" => Some(Self::Null),
+            "boolean" => Some(Self::Boolean),
+            "object" => Some(Self::Object),
+            "array" => Some(Self::Array),
+            "number" => Some(Self::Number),
+            "integer" => Some(Self::Integer),
+            "string" => Some(Self::String),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Boolean => "boolean",
+            Self::Object => "object",
+            Self::Array => "array",
+            Self::Number => "number",
+            Self::Integer => "integer",
+            Self::String => "string",
+        }
+    }
+
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            Self::Null => value.is_null(),
+            Self::Boolean => value.is_boolean(),
+            Self::Object => value.is_object(),
+            Self::Array => value.is_array(),
+            Self::Number => value.is_number(),
+            Self::Integer => {
+                value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0)
+            }
+            Self::String => value.is_string(),
+        }
+    }
+}
+
+/// Validation keywords of a schema object.
+#[derive(Debug, Default)]
+pub struct Keywords {
+    types: Option<Vec<InstanceType>>,
+    enum_values: Option<Vec<Value>>,
+    const_value: Option<Value>,
+    properties: Vec<(String, JsonSchema)>,
+    required: Vec<String>,
+    additional_properties: Option<JsonSchema>,
+    min_properties: Option<usize>,
+    max_properties: Option<usize>,
+    items: Option<JsonSchema>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<Regex>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    all_of: Vec<JsonSchema>,
+    any_of: Vec<JsonSchema>,
+    one_of: Vec<JsonSchema>,
+    not: Option<JsonSchema>,
+}
+
+/// A compiled JSON Schema.
+/// Supports the commonly used subset of draft 2020-12: type checks, `enum`,
+/// `const`, object, array, string and number constraints, and the `allOf`,
+/// `anyOf`, `oneOf` and `not` combinators. References (`$ref`) and other
+/// keywords are rejected when the schema is compiled.
+#[derive(Debug)]
+pub enum JsonSchema {
+    /// The `true` schema, any value matches
+    Any,
+    /// The `false` schema, no value matches
+    Never,
+    Rules(Box<Keywords>),
+}
+
+impl JsonSchema {
+    pub fn compile(schema: &Value) -> Result<Self, SchemaError> {
+        Self::compile_at(schema, "")
+    }
+
+    fn compile_at(schema: &Value, pointer: &str) -> Result<Self, SchemaError> {
+        let object = match schema {
+            Value::Bool(true) => return Ok(Self::Any),
+            Value::Bool(false) => return Ok(Self::Never),
+            Value::Object(object) => object,
+            _ => return Err(schema_error(pointer, "a schema must be an object or a boolean")),
+        };
+
+        let mut keywords = Keywords::default();
+        for (keyword, value) in object {
+            let at = format!("{}/{}", pointer, escape_pointer_token(keyword));
+            match keyword.as_str() {
+                "type" => {
+                    let names = match value {
+                        Value::String(name) => vec![name.as_str()],
+                        Value::Array(names) => {
+                            names.iter().map(|name| name.as_str().unwrap_or("")).collect()
+                        }
+                        _ => vec![""],
+                    };
+                    let types = names
+                        .into_iter()
+                        .map(|name| {
+                            InstanceType::parse(name)
+                                .ok_or_else(|| schema_error(&at, "unknown instance type"))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    keywords.types = Some(types);
+                }
+                "enum" => {
+                    let values =
+                        value.as_array().ok_or_else(|| schema_error(&at, "expected an array"))?;
+                    keywords.enum_values = Some(values.clone());
+                }
+                "const" => keywords.const_value = Some(value.clone()),
+                "properties" => {
+                    let properties =
+                        value.as_object().ok_or_else(|| schema_error(&at, "expected an object"))?;
+                    for (name, schema) in properties {
+                        let at = format!("{}/{}", at, escape_pointer_token(name));
+                        keywords.properties.push((name.clone(), Self::compile_at(schema, &at)?));
+                    }
+                }
+                "required" => {
+                    keywords.required = value
+                        .as_array()
+                        .and_then(|names| {
+                            names.iter().map(|name| name.as_str().map(String::from)).collect()
+                        })
+                        .ok_or_else(|| schema_error(&at, "expected an array of strings"))?;
+                }
+                "additionalProperties" => {
+                    keywords.additional_properties = Some(Self::compile_at(value, &at)?);
+                }
+                "minProperties" => keywords.min_properties = Some(non_negative(value, &at)?),
+                "maxProperties" => keywords.max_properties = Some(non_negative(value, &at)?),
+                "items" => keywords.items = Some(Self::compile_at(value, &at)?),
+                "minItems" => keywords.min_items = Some(non_negative(value, &at)?),
+                "maxItems" => keywords.max_items = Some(non_negative(value, &at)?),
+                "minLength" => keywords.min_length = Some(non_negative(value, &at)?),
+                "maxLength" => keywords.max_length = Some(non_negative(value, &at)?),
+                "pattern" => {
+                    let pattern =
+                        value.as_str().ok_or_else(|| schema_error(&at, "expected a string"))?;
+                    keywords.pattern =
+                        Some(Regex::new(pattern).map_err(|e| schema_error(&at, &e.to_string()))?);
+                }
+                "minimum" => keywords.minimum = Some(number(value, &at)?),
+                "maximum" => keywords.maximum = Some(number(value, &at)?),
+                "exclusiveMinimum" => keywords.exclusive_minimum = Some(number(value, &at)?),
+                "exclusiveMaximum" => keywords.exclusive_maximum = Some(number(value, &at)?),
+                "allOf" => keywords.all_of = Self::compile_all(value, &at)?,
+                "anyOf" => keywords.any_of = Self::compile_all(value, &at)?,
+                "oneOf" => keywords.one_of = Self::compile_all(value, &at)?,
+                "not" => keywords.not = Some(Self::compile_at(value, &at)?),
+                keyword if ANNOTATION_KEYWORDS.contains(&keyword) => {}
+                _ => return Err(schema_error(&at, "unsupported keyword")),
+            }
+        }
+
+        Ok(Self::Rules(Box::new(keywords)))
+    }
+
+    fn compile_all(schemas: &Value, pointer: &str) -> Result<Vec<Self>, SchemaError> {
+        let schemas = schemas
+            .as_array()
+            .filter(|schemas| !schemas.is_empty())
+            .ok_or_else(|| schema_error(pointer, "expected a non-empty array"))?;
+        schemas
+            .iter()
+            .enumerate()
+            .map(|(idx, schema)| Self::compile_at(schema, &format!("{}/{}", pointer, idx)))
+            .collect()
+    }
+
+    /// Validates a value, returning the first violation found.
+    pub fn validate(&self, value: &Value) -> Result<(), SchemaViolation> {
+        self.validate_at(value, &mut String::new())
+    }
+
+    fn validate_at(&self, value: &Value, pointer: &mut String) -> Result<(), SchemaViolation> {
+        let keywords = match self {
+            Self::Any => return Ok(()),
+            Self::Never => return Err(violation(pointer, "no value is allowed here".to_string())),
+            Self::Rules(keywords) => keywords,
+        };
+
+        if let Some(types) = &keywords.types
+            && !types.iter().any(|t| t.matches(value))
+        {
+            let expected: Vec<&str> = types.iter().map(InstanceType::name).collect();
+            return Err(violation(pointer, format!("expected {}", expected.join(" or "))));
+        }
+        if let Some(values) = &keywords.enum_values
+            && !values.iter().any(|allowed| json_equal(allowed, value))
+        {
+            return Err(violation(pointer, "value is not one of the allowed values".to_string()));
+        }
+        if let Some(expected) = &keywords.const_value
+            && !json_equal(expected, value)
+        {
+            return Err(violation(pointer, format!("expected {}", expected)));
+        }
+
+        match value {
+            Value::Object(object) => keywords.validate_object(object, pointer)?,
+            Value::Array(items) => keywords.validate_array(items, pointer)?,
+            Value::String(string) => keywords.validate_string(string, pointer)?,
+            Value::Number(number) => {
+                if let Some(number) = number.as_f64() {
+                    keywords.validate_number(number, pointer)?;
+                }
+            }
+            Value::Null | Value::Bool(_) => {}
+        }
+
+        for schema in &keywords.all_of {
+            schema.validate_at(value, pointer)?;
+        }
+        if !keywords.any_of.is_empty()
+            && !keywords.any_of.iter().any(|schema| schema.validate(value).is_ok())
+        {
+            return Err(violation(pointer, "value does not match any schema of anyOf".to_string()));
+        }
+        if !keywords.one_of.is_empty() {
+            let matching =
+                keywords.one_of.iter().filter(|schema| schema.validate(value).is_ok()).count();
+            if matching != 1 {
+                return Err(violation(
+                    pointer,
+                    format!("value matches {} schemas of oneOf, expected exactly 1", matching),
+                ));
+            }
+        }
+        if let Some(schema) = &keywords.not
+            && schema.validate(value).is_ok()
+        {
+            return Err(violation(pointer, "value must not match the schema of not".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+impl Keywords {
+    fn validate_object(
+        &self,
+        object: &Map<String, Value>,
+        pointer: &mut String,
+    ) -> Result<(), SchemaViolation> {
+        if let Some(name) = self.required.iter().find(|name| !object.contains_key(*name)) {
+            return Err(violation(pointer, format!("missing required property '{}'", name)));
+        }
+        if let Some(min) = self.min_properties
+            && object.len() < min
+        {
+            return Err(violation(pointer, format!("expected at least {} properties", min)));
+        }
+        if let Some(max) = self.max_properties
+            && object.len() > max
+        {
+            return Err(violation(pointer, format!("expected at most {} properties", max)));
+        }
+
+        for (name, value) in object {
+            let schema = self
+                .properties
+                .iter()
+                .find(|(property, _)| property == name)
+                .map(|(_, schema)| schema)
+                .or(self.additional_properties.as_ref());
+            if let Some(schema) = schema {
+                with_token(pointer, name, |pointer| schema.validate_at(value, pointer))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_array(&self, items: &[Value], pointer: &mut String) -> Result<(), SchemaViolation> {
+        if let Some(min) = self.min_items
+            && items.len() < min
+        {
+            return Err(violation(pointer, format!("expected at least {} items", min)));
+        }
+        if let Some(max) = self.max_items
+            && items.len() > max
+        {
+            return Err(violation(pointer, format!("expected at most {} items", max)));
+        }
+
+        if let Some(schema) = &self.items {
+            for (idx, item) in items.iter().enumerate() {
+                with_token(pointer, &idx.to_string(), |pointer| schema.validate_at(item, pointer))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_string(&self, string: &str, pointer: &str) -> Result<(), SchemaViolation> {
+        let length = string.chars().count();
+        if let Some(min) = self.min_length
+            && length < min
+        {
+            return Err(violation(pointer, format!("expected at least {} characters", min)));
+        }
+        if let Some(max) = self.max_length
+            && length > max
+        {
+            return Err(violation(pointer, format!("expected at most {} characters", max)));
+        }
+        if let Some(pattern) = &self.pattern
+            && !pattern.is_match(string)
+        {
+            return Err(violation(pointer, format!("expected to match pattern '{}'", pattern)));
+        }
+        Ok(())
+    }
+
+    fn validate_number(&self, number: f64, pointer: &str) -> Result<(), SchemaViolation> {
+        if let Some(min) = self.minimum
+            && number < min
+        {
+            return Err(violation(pointer, format!("expected a value >= {}", min)));
+        }
+        if let Some(max) = self.maximum
+            && number > max
+        {
+            return Err(violation(pointer, format!("expected a value <= {}", max)));
+        }
+        if let Some(min) = self.exclusive_minimum
+            && number <= min
+        {
+            return Err(violation(pointer, format!("expected a value > {}", min)));
+        }
+        if let Some(max) = self.exclusive_maximum
+            && number >= max
+        {
+            return Err(violation(pointer, format!("expected a value < {}", max)));
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a reference token of a JSON pointer (RFC 6901).
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Runs `f` with `token` appended to the pointer.
+fn with_token<T>(pointer: &mut String, token: &str, f: impl FnOnce(&mut String) -> T) -> T {
+    let len = pointer.len();
+    pointer.push('/');
+    pointer.push_str(&escape_pointer_token(token));
+    let result = f(pointer);
+    pointer.truncate(len);
+    result
+}
+
+/// JSON Schema equality for `enum` and `const`: numbers are equal if their
+/// mathematical values are, e.g. `1` and `1.0`.
+fn json_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            let integer = |n: &serde_json::Number| {
+                n.as_i64().map(i128::from).or_else(|| n.as_u64().map(i128::from))
+            };
+            match (integer(a), integer(b)) {
+                (Some(a), Some(b)) => a == b,
+                _ => a.as_f64() == b.as_f64(),
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_equal(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| json_equal(a, b)))
+        }
+        _ => a == b,
+    }
+}
+
+fn schema_error(pointer: &str, message: &str) -> SchemaError {
+    SchemaError { pointer: pointer.to_string(), message: message.to_string() }
+}
+
+fn violation(pointer: &str, message: String) -> SchemaViolation {
+    SchemaViolation { pointer: pointer.to_string(), message }
+}
+
+fn non_negative(value: &Value, pointer: &str) -> Result<usize, SchemaError> {
+    value
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| schema_error(pointer, "expected a non-negative integer"))
+}
+
+fn number(value: &Value, pointer: &str) -> Result<f64, SchemaError> {
+    value.as_f64().ok_or_else(|| schema_error(pointer, "expected a number"))
+}
+
+#[derive(Debug)]
+pub struct JsonSchemaValidator {
+    pub schemas: HashMap<EventType, JsonSchema>,
+    pub unknown_types: UnknownTypePolicy,
+}
+
+impl JsonSchemaValidator {
+    /// Loads and compiles the configured schemas.
+    pub fn new(config: JsonSchemaValidationConfig) -> Result<Self, String> {
+        let mut schemas = HashMap::new();
+        for (event_type, source) in config.schemas {
+            let document = match source {
+                SchemaSourceConfig::Path(path) => {
+                    let content = fs::read_to_string(&path).map_err(|e| {
+                        format!("Failed to read schema file {}: {}", path.display(), e)
+                    })?;
+                    serde_json::from_str(&content).map_err(|e| {
+                        format!("Failed to parse schema file {}: {}", path.display(), e)
+                    })?
+                }
+                SchemaSourceConfig::Inline(document) => document,
+            };
+            let schema = JsonSchema::compile(&document)
+                .map_err(|e| format!("Schema for event type {}: {}", event_type, e))?;
+            schemas.insert(event_type, schema);
+        }
+
+        if schemas.is_empty() && config.unknown_types == UnknownTypePolicy::Allow {
+            tracing::warn!(
+                "JsonSchemaValidator initialized with no schemas. This will allow all events."
+            );
+        }
+        Ok(Self { schemas, unknown_types: config.unknown_types })
+    }
+}
+
+impl EventValidator for JsonSchemaValidator {
+    fn name(&self) -> &'static str {
+        "JsonSchemaValidator"
+    }
+
+    fn validate(&self, event: &Event) -> Result<(), EventValidationError> {
+        let Some(schema) = self.schemas.get(&event.r#type) else {
+            return match self.unknown_types {
+                UnknownTypePolicy::Allow => Ok(()),
+                UnknownTypePolicy::Reject => {
+                    Err(EventValidationError::MissingSchema(event.r#type.clone()))
+                }
+            };
+        };
+
+        // A missing payload is validated as `null`
+        schema.validate(event.data.as_ref().unwrap_or(&Value::Null)).map_err(|violation| {
+            EventValidationError::SchemaViolation {
+                event_type: event.r#type.clone(),
+                pointer: violation.pointer,
+                message: violation.message,
+            }
+        })
+    }
+}
+
+/// Constructs a JsonSchemaValidator from the given parameters.
+/// Schema files are read and all schemas are compiled once, so invalid
+/// schemas fail at startup.
+fn construct_json_schema_validator(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventValidator + Send + Sync>, PluginError> {
+    let config: JsonSchemaValidationConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "JsonSchemaValidator".to_string(),
+            source: e,
+        })?;
+    let validator = JsonSchemaValidator::new(config).map_err(|message| {
+        PluginError::InvalidParameters { plugin_name: "JsonSchemaValidator".to_string(), message }
+    })?;
+    Ok(Box::new(validator))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  ValidationPluginFactory {
+        name: "JsonSchemaValidator",
+        constructor: construct_json_schema_validator,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use serde_json::json;
+
+    use super::*;
+
+    /// Creates a new event for testing purposes.
+    fn create_event(event_type: EventType, data: Option<Value>) -> Event {
+        Event {
+            source_id: 1.into(),
+            r#type: event_type,
+            timestamp: Utc::now(),
+            data,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    fn login_type() -> EventType {
+        EventType::Custom("Login".to_string())
+    }
+
+    fn create_validator(unknown_types: UnknownTypePolicy) -> Option<JsonSchemaValidator> {
+        let schema = json!({
+            "type": "object",
+            "required": ["user", "attempts"],
+            "properties": {
+                "user": { "type": "string", "minLength": 1 },
+                "attempts": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "ok": { "type": "boolean" } },
+                        "additionalProperties": false
+                    }
+                }
+            }
+        });
+        JsonSchemaValidator::new(JsonSchemaValidationConfig {
+            schemas: HashMap::from([(login_type(), SchemaSourceConfig::Inline(schema))]),
+            unknown_types,
+        })
+        .ok()
+    }
+
+    fn violation_pointer(result: Result<(), EventValidationError>) -> Option<String> {
+        match result {
+            Err(EventValidationError::SchemaViolation { pointer, .. }) => Some(pointer),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_validates_matching_data() {
+        let validator = create_validator(UnknownTypePolicy::Allow);
+        let event = create_event(login_type(), Some(json!({ "user": "a", "attempts": [] })));
+        assert!(validator.is_some_and(|v| v.validate(&event).is_ok()));
+    }
+
+    #[test]
+    fn test_reports_failing_pointer() {
+        let Some(validator) = create_validator(UnknownTypePolicy::Allow) else {
+            panic!("schema should compile");
+        };
+
+        let event = create_event(
+            login_type(),
+            Some(json!({ "user": "a", "attempts": [{ "ok": true }, { "ok": "yes" }] })),
+        );
+        assert_eq!(
+            violation_pointer(validator.validate(&event)).as_deref(),
+            Some("/attempts/1/ok")
+        );
+
+        let event = create_event(
+            login_type(),
+            Some(json!({ "user": "a", "attempts": [{ "ok": true, "extra": 1 }] })),
+        );
+        assert_eq!(
+            violation_pointer(validator.validate(&event)).as_deref(),
+            Some("/attempts/0/extra")
+        );
+
+        let event = create_event(login_type(), Some(json!({ "user": "a" })));
+        assert_eq!(violation_pointer(validator.validate(&event)).as_deref(), Some(""));
+
+        let event = create_event(login_type(), None);
+        assert_eq!(violation_pointer(validator.validate(&event)).as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_applies_unknown_type_policy() {
+        let event = create_event(EventType::Heartbeat, None);
+
+        let validator = create_validator(UnknownTypePolicy::Allow);
+        assert!(validator.is_some_and(|v| v.validate(&event).is_ok()));
+
+        let validator = create_validator(UnknownTypePolicy::Reject);
+        assert!(matches!(
+            validator.map(|v| v.validate(&event)),
+            Some(Err(EventValidationError::MissingSchema(EventType::Heartbeat)))
+        ));
+    }
+
+    #[test]
+    fn test_validates_combinators_and_constraints() {
+        let schema = JsonSchema::compile(&json!({
+            "oneOf": [
+                { "type": "integer", "minimum": 0, "exclusiveMaximum": 10 },
+                { "type": "string", "pattern": "^[a-z]+$", "maxLength": 3 }
+            ]
+        }));
+        let Ok(schema) = schema else {
+            panic!("schema should compile");
+        };
+
+        assert!(schema.validate(&json!(5)).is_ok());
+        assert!(schema.validate(&json!("abc")).is_ok());
+        assert!(schema.validate(&json!(10)).is_err());
+        assert!(schema.validate(&json!(1.5)).is_err());
+        assert!(schema.validate(&json!("abcd")).is_err());
+        assert!(schema.validate(&json!("ABC")).is_err());
+    }
+
+    #[test]
+    fn test_compares_numbers_numerically() {
+        let schema = JsonSchema::compile(&json!({
+            "properties": { "level": { "enum": [1, 2] }, "point": { "const": { "x": 1.0 } } }
+        }));
+        let Ok(schema) = schema else {
+            panic!("schema should compile");
+        };
+
+        assert!(schema.validate(&json!({ "level": 1.0, "point": { "x": 1 } })).is_ok());
+        assert!(schema.validate(&json!({ "level": 1.5 })).is_err());
+        assert!(schema.validate(&json!({ "point": { "x": "1" } })).is_err());
+    }
+
+    #[test]
+    fn test_rejects_invalid_schemas() {
+        let pointer = |schema: Value| JsonSchema::compile(&schema).err().map(|e| e.pointer);
+
+        assert_eq!(pointer(json!({ "$ref": "#/defs/a" })).as_deref(), Some("/$ref"));
+        assert_eq!(
+            pointer(json!({ "properties": { "a": { "type": "text" } } })).as_deref(),
+            Some("/properties/a/type")
+        );
+        assert_eq!(pointer(json!({ "pattern": "(" })).as_deref(), Some("/pattern"));
+        assert_eq!(pointer(json!({ "title": "Login", "type": ["object", "null"] })), None);
+    }
+
+    #[test]
+    fn test_escapes_pointer_tokens() {
+        let schema =
+            JsonSchema::compile(&json!({ "properties": { "a/b~c": { "type": "string" } } }));
+        let violation = schema.ok().and_then(|s| s.validate(&json!({ "a/b~c": 1 })).err());
+        assert_eq!(violation.map(|v| v.pointer).as_deref(), Some("/a~1b~0c"));
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashSet;
+
+use super::{EventValidationError, EventValidator};
+use crate::{
+    config::LabelValidationConfig,
+    event::Event,
+    plugins::{PluginError, ValidationPluginFactory},
+};
+
+#[derive(Debug)]
+pub struct LabelValidator {
+    pub allowed_keys: HashSet<String>,
+    pub max_labels: Option<usize>,
+    pub max_key_length: Option<usize>,
+    pub max_value_length: Option<usize>,
+}
+
+impl LabelValidator {
+    pub fn new(config: LabelValidationConfig) -> Self {
+        if config.allowed_keys.is_empty() {
+            tracing::info!(
+                "LabelValidator initialized with no allowed keys. This will allow all keys."
+            );
+        }
+        Self {
+            allowed_keys: config.allowed_keys,
+            max_labels: config.max_labels,
+            max_key_length: config.max_key_length,
+            max_value_length: config.max_value_length,
+        }
+    }
+
+    /// Checks the count and lengths of the labels.
+    fn check_limits(&self, event: &Event) -> Result<(), EventValidationError> {
+        if let Some(max) = self.max_labels
+            && event.labels.len() > max
+        {
+            return Err(EventValidationError::TooManyLabels { count: event.labels.len(), max });
+        }
+
+        for (key, value) in &event.labels {
+            if let Some(max) = self.max_key_length
+                && key.chars().count() > max
+            {
+                return Err(EventValidationError::LabelTooLong { key: key.clone(), max });
+            }
+            if let Some(max) = self.max_value_length
+                && value.chars().count() > max
+            {
+                return Err(EventValidationError::LabelTooLong { key: key.clone(), max });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl EventValidator for LabelValidator {
+    fn name(&self) -> &'static str {
+        "LabelValidator"
+    }
+
+    fn validate(&self, event: &Event) -> Result<(), EventValidationError> {
+        if let Some(key) = event
+            .labels
+            .keys()
+            .find(|key| !self.allowed_keys.is_empty() && !self.allowed_keys.contains(*key))
+        {
+            return Err(EventValidationError::DisallowedLabelKey(key.clone()));
+        }
+        self.check_limits(event)
+    }
+
+    /// Labels set by transformers are configured, so only the limits apply.
+    fn validate_transformed(&self, event: &Event) -> Result<(), EventValidationError> {
+        self.check_limits(event)
+    }
+}
+
+/// Constructs a LabelValidator from the given parameters.
+/// This function is called by the plugin factory to create a new instance of
+/// the plugin.
+fn construct_label_validator(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventValidator + Send + Sync>, PluginError> {
+    let config: LabelValidationConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "LabelValidator".to_string(),
+            source: e,
+        })?;
+    Ok(Box::new(LabelValidator::new(config)))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  ValidationPluginFactory {
+        name: "LabelValidator",
+        constructor: construct_label_validator,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::event::EventType;
+
+    /// Creates a new event with the given labels for testing purposes.
+    fn create_event(labels: &[(&str, &str)]) -> Event {
+        Event {
+            source_id: 1.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: labels.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    #[test]
+    fn test_validates_allowed_keys() {
+        let validator = LabelValidator::new(LabelValidationConfig {
+            allowed_keys: HashSet::from(["region".to_string()]),
+            ..Default::default()
+        });
+
+        assert!(validator.validate(&create_event(&[("region", "eu")])).is_ok());
+        assert!(validator.validate(&create_event(&[("owner", "bob")])).is_err());
+    }
+
+    #[test]
+    fn test_validates_max_labels() {
+        let validator = LabelValidator::new(LabelValidationConfig {
+            max_labels: Some(1),
+            ..Default::default()
+        });
+
+        assert!(validator.validate(&create_event(&[("a", "1")])).is_ok());
+        assert!(validator.validate(&create_event(&[("a", "1"), ("b", "2")])).is_err());
+    }
+
+    #[test]
+    fn test_validates_value_length() {
+        let validator = LabelValidator::new(LabelValidationConfig {
+            max_value_length: Some(3),
+            ..Default::default()
+        });
+
+        assert!(validator.validate(&create_event(&[("fw", "1.0")])).is_ok());
+        assert!(validator.validate(&create_event(&[("fw", "1.0.1")])).is_err());
+    }
+
+    #[test]
+    fn test_validates_limits_of_transformed_events() {
+        let validator = LabelValidator::new(LabelValidationConfig {
+            allowed_keys: HashSet::from(["region".to_string()]),
+            max_labels: Some(1),
+            ..Default::default()
+        });
+
+        // Keys added by transformers are configured, only the limits apply
+        assert!(
+            validator.validate_transformed(&create_event(&[("client_addr", "10.0.0.1")])).is_ok()
+        );
+        assert!(
+            validator
+                .validate_transformed(&create_event(&[
+                    ("region", "eu"),
+                    ("client_addr", "10.0.0.1")
+                ]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_validates_empty_config() {
+        let validator = LabelValidator::new(LabelValidationConfig::default());
+
+        assert!(validator.validate(&create_event(&[("any", "thing")])).is_ok());
+    }
+}
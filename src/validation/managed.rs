@@ -0,0 +1,285 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    fs,
+    hash::Hash,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::EventValidator;
+
+/// Which list of a validator is managed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListKind {
+    Allowed,
+    Denied,
+}
+
+impl FromStr for ListKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allowed" => Ok(Self::Allowed),
+            "denied" => Ok(Self::Denied),
+            _ => Err(format!("Unknown list '{}', expected 'allowed' or 'denied'", s)),
+        }
+    }
+}
+
+/// Entries to add to and remove from a list in one atomic change.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListChange {
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
+/// Runtime changes on top of the configured entries of a list, as persisted in
+/// the state file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ListOverrides {
+    #[serde(default)]
+    pub added: BTreeSet<String>,
+    #[serde(default)]
+    pub removed: BTreeSet<String>,
+}
+
+impl ListOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Validator lists that can be changed while the server is running.
+pub trait ManagedLists: Send + Sync {
+    /// Effective entries of a list, sorted.
+    fn entries(&self, kind: ListKind) -> Vec<String>;
+
+    /// Applies a change, either all entries are valid and applied or none.
+    fn update(&self, kind: ListKind, change: &ListChange) -> Result<(), String>;
+
+    /// Runtime changes of all lists.
+    fn overrides(&self) -> HashMap<ListKind, ListOverrides>;
+}
+
+/// Entries added and removed at runtime. Removed entries mask configured ones,
+/// added entries are matched in addition to them. Both sets are swapped as a
+/// whole on change, so readers holding a clone see a consistent list.
+#[derive(Debug, Clone)]
+pub struct Overlay<T> {
+    added: Arc<HashSet<T>>,
+    removed: Arc<HashSet<T>>,
+}
+
+impl<T> Default for Overlay<T> {
+    fn default() -> Self {
+        Self { added: Arc::new(HashSet::new()), removed: Arc::new(HashSet::new()) }
+    }
+}
+
+impl<T> Overlay<T>
+where
+    T: Eq + Hash + Clone + Display + FromStr,
+    T::Err: Display,
+{
+    /// Whether `value` is in the list, `configured` tells whether it is in the
+    /// configured entries.
+    pub fn contains(&self, value: &T, configured: impl FnOnce(&T) -> bool) -> bool {
+        !self.removed.contains(value) && (self.added.contains(value) || configured(value))
+    }
+
+    /// Returns the overlay with the change applied.
+    pub fn apply(&self, change: &ListChange) -> Result<Self, String> {
+        let parse = |entries: &[String]| -> Result<Vec<T>, String> {
+            entries
+                .iter()
+                .map(|entry| entry.parse().map_err(|e| format!("Invalid entry '{}': {}", entry, e)))
+                .collect()
+        };
+        let add = parse(&change.add)?;
+        let remove = parse(&change.remove)?;
+
+        let mut added = (*self.added).clone();
+        let mut removed = (*self.removed).clone();
+        for value in add {
+            removed.remove(&value);
+            added.insert(value);
+        }
+        for value in remove {
+            added.remove(&value);
+            removed.insert(value);
+        }
+        Ok(Self { added: Arc::new(added), removed: Arc::new(removed) })
+    }
+
+    /// Effective entries given the configured ones, sorted.
+    pub fn entries(&self, configured: impl IntoIterator<Item = String>) -> Vec<String> {
+        let removed: HashSet<String> = self.removed.iter().map(ToString::to_string).collect();
+        let entries: BTreeSet<String> = configured
+            .into_iter()
+            .chain(self.added.iter().map(ToString::to_string))
+            .filter(|entry| !removed.contains(entry))
+            .collect();
+        entries.into_iter().collect()
+    }
+
+    pub fn overrides(&self) -> ListOverrides {
+        ListOverrides {
+            added: self.added.iter().map(ToString::to_string).collect(),
+            removed: self.removed.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// An overlay that is replaced as a whole on change.
+#[derive(Debug)]
+pub struct SharedOverlay<T>(RwLock<Overlay<T>>);
+
+impl<T> Default for SharedOverlay<T> {
+    fn default() -> Self {
+        Self(RwLock::new(Overlay::default()))
+    }
+}
+
+impl<T> SharedOverlay<T>
+where
+    T: Eq + Hash + Clone + Display + FromStr,
+    T::Err: Display,
+{
+    pub fn get(&self) -> Overlay<T> {
+        match self.0.read() {
+            Ok(overlay) => overlay.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn apply(&self, change: &ListChange) -> Result<(), String> {
+        let mut overlay = self.0.write().map_err(|e| e.to_string())?;
+        *overlay = overlay.apply(change)?;
+        Ok(())
+    }
+}
+
+impl From<&ListOverrides> for ListChange {
+    fn from(overrides: &ListOverrides) -> Self {
+        Self {
+            add: overrides.added.iter().cloned().collect(),
+            remove: overrides.removed.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Persisted runtime list changes: validator name, then list kind.
+type ListState = BTreeMap<String, BTreeMap<ListKind, ListOverrides>>;
+
+/// Keeps runtime list changes in a JSON file, so they survive restarts.
+#[derive(Debug)]
+pub struct ListStateFile {
+    path: PathBuf,
+    /// Serializes writes of concurrent changes
+    write_lock: Mutex<()>,
+}
+
+impl ListStateFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, write_lock: Mutex::new(()) }
+    }
+
+    /// Re-applies the persisted changes to the validators. A missing file is
+    /// not an error.
+    pub fn restore(
+        &self,
+        validators: &[Box<dyn EventValidator + Send + Sync>],
+    ) -> Result<(), String> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(format!("Failed to read {}: {}", self.path.display(), err)),
+        };
+        let state: ListState = serde_json::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", self.path.display(), e))?;
+
+        for (name, lists) in state {
+            let Some(managed) =
+                validators.iter().find(|v| v.name() == name).and_then(|v| v.lists())
+            else {
+                tracing::warn!(validator = name, "Ignoring list state of unavailable validator");
+                continue;
+            };
+            for (kind, overrides) in lists.iter().filter(|(_, o)| !o.is_empty()) {
+                managed
+                    .update(*kind, &overrides.into())
+                    .map_err(|e| format!("Failed to restore {} {:?} list: {}", name, kind, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the runtime changes of all validators, replacing the file
+    /// atomically.
+    pub fn save(&self, validators: &[Box<dyn EventValidator + Send + Sync>]) -> Result<(), String> {
+        let _guard = self.write_lock.lock().map_err(|e| e.to_string())?;
+        let state: ListState = validators
+            .iter()
+            .filter_map(|v| v.lists().map(|lists| (v.name().to_string(), lists.overrides())))
+            .map(|(name, lists)| {
+                let lists = lists.into_iter().filter(|(_, overrides)| !overrides.is_empty());
+                (name, lists.collect::<BTreeMap<_, _>>())
+            })
+            .filter(|(_, lists)| !lists.is_empty())
+            .collect();
+        let content = serde_json::to_string_pretty(&state).map_err(|e| e.to_string())?;
+
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, content)
+            .and_then(|_| fs::rename(&tmp_path, &self.path))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::SourceId;
+
+    fn change(add: &[&str], remove: &[&str]) -> ListChange {
+        ListChange {
+            add: add.iter().map(|s| s.to_string()).collect(),
+            remove: remove.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_overlays_configured_entries() {
+        let configured = |id: &SourceId| *id == SourceId::from(1);
+        let overlay = Overlay::<SourceId>::default();
+
+        let Ok(overlay) = overlay.apply(&change(&["2"], &["1"])) else {
+            panic!("change should apply");
+        };
+        assert!(!overlay.contains(&1.into(), configured));
+        assert!(overlay.contains(&2.into(), configured));
+        assert_eq!(overlay.entries(["1".to_string()]), vec!["2".to_string()]);
+
+        let Ok(overlay) = overlay.apply(&change(&["1"], &["2"])) else {
+            panic!("change should apply");
+        };
+        assert!(overlay.contains(&1.into(), configured));
+        assert!(!overlay.contains(&2.into(), configured));
+    }
+
+    #[test]
+    fn test_rejects_invalid_entries_atomically() {
+        let overlay = Overlay::<SourceId>::default();
+        assert!(overlay.apply(&change(&["3", "not an id"], &[])).is_err());
+        assert!(overlay.overrides().added.is_empty());
+    }
+}
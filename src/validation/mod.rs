@@ -1,14 +1,51 @@
 pub mod event_type;
+pub mod guard;
+pub mod json_schema;
+pub mod labels;
+pub mod managed;
+pub mod rule_expr;
+pub mod rules;
 pub mod source_id;
+pub mod sqlite_registry;
 
 use std::fmt::Debug;
 
+use self::managed::ManagedLists;
 use crate::event::{Event, EventValidationError};
 
+#[async_trait::async_trait]
 pub trait EventValidator: Send + Sync + Debug {
     /// Validate an event.
     fn validate(&self, event: &Event) -> Result<(), EventValidationError>;
 
+    /// Validate an event on the request path. Validators that look up external
+    /// state override this so they don't block the runtime, the default runs
+    /// `validate`.
+    async fn validate_async(&self, event: &Event) -> Result<(), EventValidationError> {
+        self.validate(event)
+    }
+
+    /// Re-checks an event after the transformers ran, for limits they could
+    /// exceed, e.g. by adding labels. The default accepts the event.
+    fn validate_transformed(&self, _event: &Event) -> Result<(), EventValidationError> {
+        Ok(())
+    }
+
+    /// Whether `validate_async` yields to the runtime while it waits, so a
+    /// chain `timeout` can interrupt it. A timeout can't interrupt `validate`.
+    fn supports_timeout(&self) -> bool {
+        false
+    }
+
     /// Validator name (for logging purposes).
     fn name(&self) -> &'static str;
+
+    /// Start background work (e.g. reloading list files). Called once before
+    /// the server accepts requests.
+    fn start(&self) {}
+
+    /// Lists that can be changed at runtime through the admin API, if any.
+    fn lists(&self) -> Option<&dyn ManagedLists> {
+        None
+    }
 }
@@ -0,0 +1,918 @@
+//! A small predicate language for validation rules.
+//!
+//! Expressions combine event fields with comparisons, boolean operators and a
+//! few functions, e.g.
+//! `type == "Login" => has(data.user_id) && len(data.user_id) < 64`.
+//!
+//! * Fields: `source_id`, `type`, `timestamp`, `data`, `data.<path>` and
+//!   `labels.<key>`. Path segments are identifiers, indices (`data.items.0`)
+//!   or bracketed keys and indices (`data["user id"]`, `data.items[0]`).
+//! * Literals: strings (`"Login"`), numbers, `true`, `false` and `null`.
+//! * Operators, by increasing precedence: `=>` (implication), `||`, `&&`, `!`,
+//!   and `==`, `!=`, `<`, `<=`, `>`, `>=`.
+//! * Functions: `has(path)`, `len(x)`, `matches(x, "regex")`,
+//!   `starts_with(x, s)`, `ends_with(x, s)`, `contains(x, s)`, `now()` and
+//!   `age(timestamp)` (seconds).
+//!
+//! Expressions are type-checked when parsed. Values of `data` paths and labels
+//! are only known at runtime: a missing value is `null`, and comparing values
+//! of different types is false.
+
+use std::{borrow::Cow, cmp::Ordering, fmt::Display};
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde_json::Value;
+
+use crate::event::{Event, SourceId};
+
+/// Error in a rule expression, `position` is the character offset of the
+/// offending token.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("{message} at column {}", position + 1)]
+pub struct ExprError {
+    pub position: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Dot,
+    Not,
+    And,
+    Or,
+    Implies,
+    Cmp(CmpOp),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "'{}'", name),
+            Token::Str(value) => write!(f, "\"{}\"", value),
+            Token::Num(value) => write!(f, "{}", value),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::LBracket => write!(f, "'['"),
+            Token::RBracket => write!(f, "']'"),
+            Token::Comma => write!(f, "','"),
+            Token::Dot => write!(f, "'.'"),
+            Token::Not => write!(f, "'!'"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Implies => write!(f, "'=>'"),
+            Token::Cmp(op) => write!(f, "'{}'", op),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Display for CmpOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// Splits an expression into tokens with their character offsets.
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let start = pos;
+        let c = chars[pos];
+        let next = chars.get(pos + 1).copied();
+        let token = match c {
+            c if c.is_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            '&' if next == Some('&') => Token::And,
+            '|' if next == Some('|') => Token::Or,
+            '=' if next == Some('>') => Token::Implies,
+            '=' if next == Some('=') => Token::Cmp(CmpOp::Eq),
+            '!' if next == Some('=') => Token::Cmp(CmpOp::Ne),
+            '<' if next == Some('=') => Token::Cmp(CmpOp::Le),
+            '>' if next == Some('=') => Token::Cmp(CmpOp::Ge),
+            '!' => Token::Not,
+            '<' => Token::Cmp(CmpOp::Lt),
+            '>' => Token::Cmp(CmpOp::Gt),
+            '"' => {
+                let mut value = String::new();
+                pos += 1;
+                loop {
+                    match chars.get(pos) {
+                        None => return Err(error(start, "unterminated string")),
+                        Some('"') => break,
+                        Some('\\') => {
+                            let escaped = match chars.get(pos + 1) {
+                                Some('n') => '\n',
+                                Some('t') => '\t',
+                                Some(c @ ('"' | '\\')) => *c,
+                                _ => return Err(error(pos, "invalid escape sequence")),
+                            };
+                            value.push(escaped);
+                            pos += 2;
+                        }
+                        Some(c) => {
+                            value.push(*c);
+                            pos += 1;
+                        }
+                    }
+                }
+                tokens.push((start, Token::Str(value)));
+                pos += 1;
+                continue;
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                pos += 1;
+                while chars.get(pos).is_some_and(|c| c.is_ascii_digit()) {
+                    pos += 1;
+                }
+                if chars.get(pos) == Some(&'.')
+                    && chars.get(pos + 1).is_some_and(|c| c.is_ascii_digit())
+                {
+                    pos += 1;
+                    while chars.get(pos).is_some_and(|c| c.is_ascii_digit()) {
+                        pos += 1;
+                    }
+                }
+                let literal: String = chars[start..pos].iter().collect();
+                let value = literal.parse().map_err(|_| error(start, "invalid number"))?;
+                tokens.push((start, Token::Num(value)));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                while chars.get(pos).is_some_and(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+                {
+                    pos += 1;
+                }
+                tokens.push((start, Token::Ident(chars[start..pos].iter().collect())));
+                continue;
+            }
+            c => return Err(error(start, &format!("unexpected character '{}'", c))),
+        };
+        pos += match token {
+            Token::And | Token::Or | Token::Implies => 2,
+            Token::Cmp(CmpOp::Eq | CmpOp::Ne | CmpOp::Le | CmpOp::Ge) => 2,
+            _ => 1,
+        };
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    SourceId,
+    Type,
+    Timestamp,
+    Data(Vec<Segment>),
+    Label(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Func {
+    Has,
+    Len,
+    Matches,
+    StartsWith,
+    EndsWith,
+    Contains,
+    Now,
+    Age,
+}
+
+impl Func {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "has" => Some(Func::Has),
+            "len" => Some(Func::Len),
+            "matches" => Some(Func::Matches),
+            "starts_with" => Some(Func::StartsWith),
+            "ends_with" => Some(Func::EndsWith),
+            "contains" => Some(Func::Contains),
+            "now" => Some(Func::Now),
+            "age" => Some(Func::Age),
+            _ => None,
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Func::Now => 0,
+            Func::Has | Func::Len | Func::Age => 1,
+            Func::Matches | Func::StartsWith | Func::EndsWith | Func::Contains => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Literal {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Timestamp(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone)]
+enum ExprKind {
+    Literal(Literal),
+    Field(Field),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Implies(Box<Node>, Box<Node>),
+    Compare(CmpOp, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+    /// `matches` with its pattern compiled during type checking
+    Matches(Box<Node>, Regex),
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    position: usize,
+    kind: ExprKind,
+}
+
+/// Recursive descent parser over the token list.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(position, _)| *position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, token)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ExprError> {
+        let position = self.position();
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(error(position, &format!("expected {}, found {}", expected, token))),
+            None => Err(error(position, &format!("expected {}", expected))),
+        }
+    }
+
+    fn node(position: usize, kind: ExprKind) -> Node {
+        Node { position, kind }
+    }
+
+    /// implies := or ("=>" implies)?
+    fn implies(&mut self) -> Result<Node, ExprError> {
+        let lhs = self.or()?;
+        if self.peek() == Some(&Token::Implies) {
+            let position = self.position();
+            self.next();
+            let rhs = self.implies()?;
+            return Ok(Self::node(position, ExprKind::Implies(Box::new(lhs), Box::new(rhs))));
+        }
+        Ok(lhs)
+    }
+
+    /// or := and ("||" and)*
+    fn or(&mut self) -> Result<Node, ExprError> {
+        let mut lhs = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            let position = self.position();
+            self.next();
+            let rhs = self.and()?;
+            lhs = Self::node(position, ExprKind::Or(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    /// and := unary ("&&" unary)*
+    fn and(&mut self) -> Result<Node, ExprError> {
+        let mut lhs = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            let position = self.position();
+            self.next();
+            let rhs = self.unary()?;
+            lhs = Self::node(position, ExprKind::And(Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    /// unary := "!" unary | comparison
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        if self.peek() == Some(&Token::Not) {
+            let position = self.position();
+            self.next();
+            let operand = self.unary()?;
+            return Ok(Self::node(position, ExprKind::Not(Box::new(operand))));
+        }
+        self.comparison()
+    }
+
+    /// comparison := primary (cmp primary)?
+    fn comparison(&mut self) -> Result<Node, ExprError> {
+        let lhs = self.primary()?;
+        if let Some(Token::Cmp(op)) = self.peek() {
+            let op = *op;
+            let position = self.position();
+            self.next();
+            let rhs = self.primary()?;
+            return Ok(Self::node(position, ExprKind::Compare(op, Box::new(lhs), Box::new(rhs))));
+        }
+        Ok(lhs)
+    }
+
+    /// primary := literal | field | call | "(" implies ")"
+    fn primary(&mut self) -> Result<Node, ExprError> {
+        let position = self.position();
+        let kind = match self.next() {
+            Some(Token::Str(value)) => ExprKind::Literal(Literal::String(value)),
+            Some(Token::Num(value)) => ExprKind::Literal(Literal::Number(value)),
+            Some(Token::LParen) => {
+                let inner = self.implies()?;
+                self.expect(Token::RParen)?;
+                return Ok(inner);
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => ExprKind::Literal(Literal::Bool(true)),
+                "false" => ExprKind::Literal(Literal::Bool(false)),
+                "null" => ExprKind::Literal(Literal::Null),
+                _ if self.peek() == Some(&Token::LParen) => self.call(&name, position)?,
+                _ => ExprKind::Field(self.field(&name, position)?),
+            },
+            Some(token) => return Err(error(position, &format!("unexpected {}", token))),
+            None => return Err(error(position, "unexpected end of expression")),
+        };
+        Ok(Self::node(position, kind))
+    }
+
+    fn call(&mut self, name: &str, position: usize) -> Result<ExprKind, ExprError> {
+        let func = Func::parse(name)
+            .ok_or_else(|| error(position, &format!("unknown function '{}'", name)))?;
+        self.expect(Token::LParen)?;
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::RParen) {
+            args.push(self.implies()?);
+            while self.peek() == Some(&Token::Comma) {
+                self.next();
+                args.push(self.implies()?);
+            }
+        }
+        self.expect(Token::RParen)?;
+        if args.len() != func.arity() {
+            return Err(error(
+                position,
+                &format!("'{}' expects {} argument(s), got {}", name, func.arity(), args.len()),
+            ));
+        }
+        Ok(ExprKind::Call(func, args))
+    }
+
+    fn field(&mut self, name: &str, position: usize) -> Result<Field, ExprError> {
+        let segments = self.segments()?;
+        let no_segments = |field: Field| {
+            if segments.is_empty() {
+                Ok(field)
+            } else {
+                Err(error(position, &format!("'{}' has no fields", name)))
+            }
+        };
+        match name {
+            "source_id" => no_segments(Field::SourceId),
+            "type" => no_segments(Field::Type),
+            "timestamp" => no_segments(Field::Timestamp),
+            "data" => Ok(Field::Data(segments)),
+            "labels" => match segments.as_slice() {
+                [Segment::Key(key)] => Ok(Field::Label(key.clone())),
+                _ => Err(error(position, "expected a single label key, e.g. labels.region")),
+            },
+            _ => Err(error(position, &format!("unknown field '{}'", name))),
+        }
+    }
+
+    /// segments := ("." (ident | index) | "[" (string | index) "]")*
+    fn segments(&mut self) -> Result<Vec<Segment>, ExprError> {
+        let mut segments = Vec::new();
+        loop {
+            let position = self.position();
+            match self.peek() {
+                Some(Token::Dot) => {
+                    self.next();
+                    segments.push(match self.next() {
+                        Some(Token::Ident(key)) => Segment::Key(key),
+                        Some(Token::Num(index)) => Segment::Index(to_index(index, position)?),
+                        _ => return Err(error(position, "expected a field name after '.'")),
+                    });
+                }
+                Some(Token::LBracket) => {
+                    self.next();
+                    segments.push(match self.next() {
+                        Some(Token::Str(key)) => Segment::Key(key),
+                        Some(Token::Num(index)) => Segment::Index(to_index(index, position)?),
+                        _ => return Err(error(position, "expected a string key or an index")),
+                    });
+                    self.expect(Token::RBracket)?;
+                }
+                _ => return Ok(segments),
+            }
+        }
+    }
+}
+
+fn to_index(value: f64, position: usize) -> Result<usize, ExprError> {
+    if value >= 0.0 && value.fract() == 0.0 {
+        Ok(value as usize)
+    } else {
+        Err(error(position, "expected a non-negative integer index, use [index]"))
+    }
+}
+
+/// Static type of an expression. `Dynamic` values (`data` paths, labels and
+/// source ids) are only known at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Null,
+    Bool,
+    Number,
+    String,
+    Timestamp,
+    Dynamic,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Type::Null => "null",
+            Type::Bool => "bool",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Timestamp => "timestamp",
+            Type::Dynamic => "dynamic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Type-checks a node, resolving `matches` patterns and timestamp literals.
+fn check(node: &mut Node) -> Result<Type, ExprError> {
+    let position = node.position;
+    let expect = |node: &mut Node, allowed: &[Type]| -> Result<Type, ExprError> {
+        let actual = check(node)?;
+        if actual == Type::Dynamic || allowed.contains(&actual) {
+            Ok(actual)
+        } else {
+            let allowed: Vec<String> = allowed.iter().map(ToString::to_string).collect();
+            Err(error(
+                node.position,
+                &format!("expected {}, found {}", allowed.join(" or "), actual),
+            ))
+        }
+    };
+
+    match &mut node.kind {
+        ExprKind::Literal(Literal::Null) => Ok(Type::Null),
+        ExprKind::Literal(Literal::Bool(_)) => Ok(Type::Bool),
+        ExprKind::Literal(Literal::Number(_)) => Ok(Type::Number),
+        ExprKind::Literal(Literal::String(_)) => Ok(Type::String),
+        ExprKind::Literal(Literal::Timestamp(_)) => Ok(Type::Timestamp),
+        ExprKind::Field(Field::Type) => Ok(Type::String),
+        ExprKind::Field(Field::Timestamp) => Ok(Type::Timestamp),
+        ExprKind::Field(Field::SourceId | Field::Data(_) | Field::Label(_)) => Ok(Type::Dynamic),
+        ExprKind::Not(operand) => {
+            expect(operand, &[Type::Bool])?;
+            Ok(Type::Bool)
+        }
+        ExprKind::And(lhs, rhs) | ExprKind::Or(lhs, rhs) | ExprKind::Implies(lhs, rhs) => {
+            expect(lhs, &[Type::Bool])?;
+            expect(rhs, &[Type::Bool])?;
+            Ok(Type::Bool)
+        }
+        ExprKind::Compare(op, lhs, rhs) => {
+            let mut lhs_type = check(lhs)?;
+            let mut rhs_type = check(rhs)?;
+            // Timestamps compare with RFC 3339 string literals
+            if lhs_type == Type::Timestamp {
+                rhs_type = coerce_timestamp(rhs, rhs_type)?;
+            }
+            if rhs_type == Type::Timestamp {
+                lhs_type = coerce_timestamp(lhs, lhs_type)?;
+            }
+
+            let comparable = match op {
+                CmpOp::Eq | CmpOp::Ne => {
+                    lhs_type == rhs_type
+                        || [lhs_type, rhs_type].contains(&Type::Dynamic)
+                        || [lhs_type, rhs_type].contains(&Type::Null)
+                }
+                CmpOp::Lt | CmpOp::Le | CmpOp::Gt | CmpOp::Ge => {
+                    let ordered = |t: Type| {
+                        matches!(t, Type::Number | Type::String | Type::Timestamp | Type::Dynamic)
+                    };
+                    ordered(lhs_type)
+                        && ordered(rhs_type)
+                        && (lhs_type == rhs_type
+                            || lhs_type == Type::Dynamic
+                            || rhs_type == Type::Dynamic)
+                }
+            };
+            if !comparable {
+                return Err(error(
+                    position,
+                    &format!("cannot compare {} {} {}", lhs_type, op, rhs_type),
+                ));
+            }
+            Ok(Type::Bool)
+        }
+        ExprKind::Call(func, args) => match func {
+            Func::Has => match &args[0].kind {
+                ExprKind::Field(Field::Data(_) | Field::Label(_)) => Ok(Type::Bool),
+                _ => Err(error(args[0].position, "has() expects a data path or a label")),
+            },
+            Func::Len => {
+                expect(&mut args[0], &[Type::String])?;
+                Ok(Type::Number)
+            }
+            Func::StartsWith | Func::EndsWith | Func::Contains => {
+                expect(&mut args[0], &[Type::String])?;
+                expect(&mut args[1], &[Type::String])?;
+                Ok(Type::Bool)
+            }
+            Func::Now => Ok(Type::Timestamp),
+            Func::Age => {
+                expect(&mut args[0], &[Type::Timestamp])?;
+                Ok(Type::Number)
+            }
+            Func::Matches => {
+                expect(&mut args[0], &[Type::String])?;
+                let ExprKind::Literal(Literal::String(pattern)) = &args[1].kind else {
+                    return Err(error(args[1].position, "matches() expects a string literal"));
+                };
+                let regex = Regex::new(pattern)
+                    .map_err(|e| error(args[1].position, &format!("invalid regex: {}", e)))?;
+                let subject = args.swap_remove(0);
+                node.kind = ExprKind::Matches(Box::new(subject), regex);
+                Ok(Type::Bool)
+            }
+        },
+        ExprKind::Matches(subject, _) => {
+            expect(subject, &[Type::String])?;
+            Ok(Type::Bool)
+        }
+    }
+}
+
+/// Turns a string literal compared with a timestamp into a timestamp literal.
+fn coerce_timestamp(node: &mut Node, node_type: Type) -> Result<Type, ExprError> {
+    let ExprKind::Literal(Literal::String(value)) = &node.kind else {
+        return Ok(node_type);
+    };
+    let timestamp = DateTime::parse_from_rfc3339(value)
+        .map_err(|_| error(node.position, "expected an RFC 3339 timestamp"))?;
+    node.kind = ExprKind::Literal(Literal::Timestamp(timestamp.with_timezone(&Utc)));
+    Ok(Type::Timestamp)
+}
+
+/// Runtime value of an expression.
+#[derive(Debug, Clone)]
+enum Val<'a> {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(Cow<'a, str>),
+    Timestamp(DateTime<Utc>),
+    /// Array or object from the event data
+    Json(&'a Value),
+}
+
+impl<'a> Val<'a> {
+    fn from_json(value: &'a Value) -> Self {
+        match value {
+            Value::Null => Val::Null,
+            Value::Bool(value) => Val::Bool(*value),
+            Value::Number(value) => value.as_f64().map_or(Val::Null, Val::Number),
+            Value::String(value) => Val::Str(Cow::Borrowed(value)),
+            Value::Array(_) | Value::Object(_) => Val::Json(value),
+        }
+    }
+
+    fn is_true(&self) -> bool {
+        matches!(self, Val::Bool(true))
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        match self {
+            Val::Str(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn equals(&self, other: &Val) -> bool {
+        match (self, other) {
+            (Val::Null, Val::Null) => true,
+            (Val::Bool(a), Val::Bool(b)) => a == b,
+            (Val::Number(a), Val::Number(b)) => a == b,
+            (Val::Str(a), Val::Str(b)) => a == b,
+            (Val::Timestamp(a), Val::Timestamp(b)) => a == b,
+            (Val::Json(a), Val::Json(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    fn compare(&self, other: &Val) -> Option<Ordering> {
+        match (self, other) {
+            (Val::Number(a), Val::Number(b)) => a.partial_cmp(b),
+            (Val::Str(a), Val::Str(b)) => Some(a.cmp(b)),
+            (Val::Timestamp(a), Val::Timestamp(b)) => Some(a.cmp(b)),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed and type-checked boolean expression.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Self, ExprError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, pos: 0, end: input.chars().count() };
+        let mut root = parser.implies()?;
+        if let Some(token) = parser.peek() {
+            return Err(error(parser.position(), &format!("unexpected {}", token)));
+        }
+
+        let root_type = check(&mut root)?;
+        if !matches!(root_type, Type::Bool | Type::Dynamic) {
+            return Err(error(root.position, &format!("expected bool, found {}", root_type)));
+        }
+        Ok(Self { root })
+    }
+
+    /// Evaluates the expression, only a `true` result passes.
+    pub fn matches(&self, event: &Event, now: DateTime<Utc>) -> bool {
+        eval(&self.root, event, now).is_true()
+    }
+}
+
+fn eval<'a>(node: &'a Node, event: &'a Event, now: DateTime<Utc>) -> Val<'a> {
+    match &node.kind {
+        ExprKind::Literal(literal) => match literal {
+            Literal::Null => Val::Null,
+            Literal::Bool(value) => Val::Bool(*value),
+            Literal::Number(value) => Val::Number(*value),
+            Literal::String(value) => Val::Str(Cow::Borrowed(value)),
+            Literal::Timestamp(value) => Val::Timestamp(*value),
+        },
+        ExprKind::Field(field) => resolve(field, event),
+        ExprKind::Not(operand) => Val::Bool(!eval(operand, event, now).is_true()),
+        ExprKind::And(lhs, rhs) => {
+            Val::Bool(eval(lhs, event, now).is_true() && eval(rhs, event, now).is_true())
+        }
+        ExprKind::Or(lhs, rhs) => {
+            Val::Bool(eval(lhs, event, now).is_true() || eval(rhs, event, now).is_true())
+        }
+        ExprKind::Implies(lhs, rhs) => {
+            Val::Bool(!eval(lhs, event, now).is_true() || eval(rhs, event, now).is_true())
+        }
+        ExprKind::Compare(op, lhs, rhs) => {
+            let lhs = eval(lhs, event, now);
+            let rhs = eval(rhs, event, now);
+            Val::Bool(match op {
+                CmpOp::Eq => lhs.equals(&rhs),
+                CmpOp::Ne => !lhs.equals(&rhs),
+                CmpOp::Lt => lhs.compare(&rhs) == Some(Ordering::Less),
+                CmpOp::Le => matches!(lhs.compare(&rhs), Some(Ordering::Less | Ordering::Equal)),
+                CmpOp::Gt => lhs.compare(&rhs) == Some(Ordering::Greater),
+                CmpOp::Ge => {
+                    matches!(lhs.compare(&rhs), Some(Ordering::Greater | Ordering::Equal))
+                }
+            })
+        }
+        ExprKind::Matches(subject, regex) => {
+            let subject = eval(subject, event, now);
+            Val::Bool(subject.as_str().is_some_and(|s| regex.is_match(s)))
+        }
+        ExprKind::Call(func, args) => {
+            let arg = |idx: usize| eval(&args[idx], event, now);
+            match func {
+                Func::Has => Val::Bool(!matches!(arg(0), Val::Null)),
+                Func::Len => match arg(0) {
+                    Val::Str(value) => Val::Number(value.chars().count() as f64),
+                    Val::Json(Value::Array(items)) => Val::Number(items.len() as f64),
+                    Val::Json(Value::Object(fields)) => Val::Number(fields.len() as f64),
+                    _ => Val::Null,
+                },
+                Func::StartsWith | Func::EndsWith | Func::Contains => {
+                    let (subject, needle) = (arg(0), arg(1));
+                    let Some(needle) = needle.as_str() else {
+                        return Val::Bool(false);
+                    };
+                    Val::Bool(match (func, &subject) {
+                        (Func::StartsWith, Val::Str(s)) => s.starts_with(needle),
+                        (Func::EndsWith, Val::Str(s)) => s.ends_with(needle),
+                        (Func::Contains, Val::Str(s)) => s.contains(needle),
+                        (Func::Contains, Val::Json(Value::Array(items))) => {
+                            items.iter().any(|item| item.as_str() == Some(needle))
+                        }
+                        _ => false,
+                    })
+                }
+                Func::Now => Val::Timestamp(now),
+                Func::Age => match arg(0) {
+                    Val::Timestamp(at) => {
+                        Val::Number((now - at).num_milliseconds() as f64 / 1000.0)
+                    }
+                    _ => Val::Null,
+                },
+                // Replaced by `ExprKind::Matches` during type checking
+                Func::Matches => Val::Null,
+            }
+        }
+    }
+}
+
+fn resolve<'a>(field: &'a Field, event: &'a Event) -> Val<'a> {
+    match field {
+        Field::SourceId => match &event.source_id {
+            SourceId::Numeric(id) => Val::Number(*id as f64),
+            source_id => Val::Str(Cow::Owned(source_id.to_string())),
+        },
+        Field::Type => Val::Str(Cow::Owned(event.r#type.to_string())),
+        Field::Timestamp => Val::Timestamp(event.timestamp),
+        Field::Label(key) => {
+            event.labels.get(key).map_or(Val::Null, |value| Val::Str(Cow::Borrowed(value)))
+        }
+        Field::Data(segments) => {
+            let mut value = match &event.data {
+                Some(data) => data,
+                None => return Val::Null,
+            };
+            for segment in segments {
+                let next = match segment {
+                    Segment::Key(key) => value.get(key),
+                    Segment::Index(index) => value.get(index),
+                };
+                match next {
+                    Some(next) => value = next,
+                    None => return Val::Null,
+                }
+            }
+            Val::from_json(value)
+        }
+    }
+}
+
+fn error(position: usize, message: &str) -> ExprError {
+    ExprError { position, message: message.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::event::EventType;
+
+    /// Creates a new event for testing purposes.
+    fn create_event(event_type: &str, data: Option<Value>) -> Event {
+        Event {
+            source_id: 7.into(),
+            r#type: EventType::from_str(event_type).unwrap_or(EventType::Heartbeat),
+            timestamp: Utc::now(),
+            data,
+            labels: [("region".to_string(), "eu-west".to_string())].into(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    fn eval_expr(expr: &str, event: &Event) -> Option<bool> {
+        Expr::parse(expr).ok().map(|expr| expr.matches(event, Utc::now()))
+    }
+
+    #[test]
+    fn test_evaluates_implication() {
+        let expr = r#"type == "Login" => has(data.user_id) && len(data.user_id) < 8"#;
+
+        let login = create_event("Login", Some(json!({ "user_id": "alice" })));
+        assert_eq!(eval_expr(expr, &login), Some(true));
+
+        let long_id = create_event("Login", Some(json!({ "user_id": "alice-the-admin" })));
+        assert_eq!(eval_expr(expr, &long_id), Some(false));
+
+        let missing = create_event("Login", Some(json!({})));
+        assert_eq!(eval_expr(expr, &missing), Some(false));
+
+        let heartbeat = create_event("Heartbeat", None);
+        assert_eq!(eval_expr(expr, &heartbeat), Some(true));
+    }
+
+    #[test]
+    fn test_evaluates_fields_and_functions() {
+        let event = create_event(
+            "Reading",
+            Some(json!({ "items": [{ "v": 1.5 }, { "v": 3 }], "tags": ["a", "b"], "n k": 1 })),
+        );
+
+        assert_eq!(eval_expr("source_id == 7", &event), Some(true));
+        assert_eq!(eval_expr(r#"labels.region == "eu-west""#, &event), Some(true));
+        assert_eq!(eval_expr(r#"starts_with(labels.region, "eu")"#, &event), Some(true));
+        assert_eq!(eval_expr(r#"matches(labels.region, "^[a-z]+-[a-z]+$")"#, &event), Some(true));
+        assert_eq!(eval_expr("!has(labels.firmware)", &event), Some(true));
+        assert_eq!(eval_expr("data.items.1.v >= 3 && data.items[0].v < 2", &event), Some(true));
+        assert_eq!(eval_expr(r#"data["n k"] == 1"#, &event), Some(true));
+        assert_eq!(
+            eval_expr(r#"contains(data.tags, "b") && len(data.items) == 2"#, &event),
+            Some(true)
+        );
+        assert_eq!(eval_expr("age(timestamp) < 60", &event), Some(true));
+        assert_eq!(eval_expr(r#"timestamp > "2020-01-01T00:00:00Z""#, &event), Some(true));
+        // Mismatched runtime types compare as false
+        assert_eq!(eval_expr(r#"data.items.0.v == "1.5""#, &event), Some(false));
+        assert_eq!(eval_expr("data.missing < 1 || data.missing == null", &event), Some(true));
+    }
+
+    #[test]
+    fn test_reports_parse_errors() {
+        let err = |expr: &str| Expr::parse(expr).err().map(|e| (e.position, e.message));
+
+        assert_eq!(err("type == "), Some((8, "unexpected end of expression".to_string())));
+        assert_eq!(err("user == 1").map(|e| e.0), Some(0));
+        assert_eq!(err("size(data)").map(|e| e.1), Some("unknown function 'size'".to_string()));
+        assert_eq!(err(r#"type == "a"#).map(|e| e.1), Some("unterminated string".to_string()));
+        assert_eq!(err("(type == \"a\"").map(|e| e.0), Some(12));
+        assert_eq!(err("type == \"a\" )").map(|e| e.0), Some(12));
+    }
+
+    #[test]
+    fn test_reports_type_errors() {
+        let err = |expr: &str| Expr::parse(expr).err().map(|e| e.message);
+
+        assert_eq!(err(r#"type == 1"#), Some("cannot compare string == number".to_string()));
+        assert_eq!(err(r#"type < true"#), Some("cannot compare string < bool".to_string()));
+        assert_eq!(err("len(timestamp) > 1"), Some("expected string, found timestamp".to_string()));
+        assert_eq!(err("has(type)"), Some("has() expects a data path or a label".to_string()));
+        assert_eq!(err("len(data)"), Some("expected bool, found number".to_string()));
+        assert_eq!(
+            err(r#"matches(type, "(")"#).map(|e| e.starts_with("invalid regex")),
+            Some(true)
+        );
+        assert_eq!(
+            err(r#"timestamp > "yesterday""#),
+            Some("expected an RFC 3339 timestamp".to_string())
+        );
+    }
+}
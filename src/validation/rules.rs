@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+
+use super::{EventValidationError, EventValidator, rule_expr::Expr};
+use crate::{
+    config::RuleValidationConfig,
+    event::Event,
+    plugins::{PluginError, ValidationPluginFactory},
+};
+
+/// A named rule with the message reported when an event violates it.
+#[derive(Debug)]
+pub struct Rule {
+    pub name: String,
+    pub expr: Expr,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct RuleValidator {
+    pub rules: Vec<Rule>,
+}
+
+impl RuleValidator {
+    /// Parses and type-checks all rules.
+    pub fn new(config: RuleValidationConfig) -> Result<Self, String> {
+        let mut names = HashSet::new();
+        let mut rules = Vec::with_capacity(config.rules.len());
+        for rule in config.rules {
+            if !names.insert(rule.name.clone()) {
+                return Err(format!("Duplicate rule name '{}'", rule.name));
+            }
+            let expr = Expr::parse(&rule.expr)
+                .map_err(|e| format!("Rule '{}' is invalid: {}", rule.name, e))?;
+            let message =
+                rule.message.unwrap_or_else(|| format!("Event violates rule '{}'", rule.name));
+            rules.push(Rule { name: rule.name, expr, message });
+        }
+
+        if rules.is_empty() {
+            tracing::warn!("RuleValidator initialized with no rules. This will allow all events.");
+        }
+        Ok(Self { rules })
+    }
+}
+
+impl EventValidator for RuleValidator {
+    fn name(&self) -> &'static str {
+        "RuleValidator"
+    }
+
+    fn validate(&self, event: &Event) -> Result<(), EventValidationError> {
+        let now = Utc::now();
+        match self.rules.iter().find(|rule| !rule.expr.matches(event, now)) {
+            Some(rule) => Err(EventValidationError::RuleViolation {
+                rule: rule.name.clone(),
+                message: rule.message.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Constructs a RuleValidator from the given parameters.
+/// Rules are parsed and type-checked here, so invalid rules fail at startup.
+fn construct_rule_validator(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventValidator + Send + Sync>, PluginError> {
+    let config: RuleValidationConfig =
+        config_params.try_into().map_err(|e| PluginError::ParameterDeserialization {
+            plugin_name: "RuleValidator".to_string(),
+            source: e,
+        })?;
+    let validator = RuleValidator::new(config).map_err(|message| {
+        PluginError::InvalidParameters { plugin_name: "RuleValidator".to_string(), message }
+    })?;
+    Ok(Box::new(validator))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  ValidationPluginFactory {
+        name: "RuleValidator",
+        constructor: construct_rule_validator,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{config::RuleConfig, event::EventType};
+
+    /// Creates a new event for testing purposes.
+    fn create_event(event_type: EventType, data: Option<serde_json::Value>) -> Event {
+        Event {
+            source_id: 1.into(),
+            r#type: event_type,
+            timestamp: Utc::now(),
+            data,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    fn create_rule(name: &str, expr: &str, message: Option<&str>) -> RuleConfig {
+        RuleConfig {
+            name: name.to_string(),
+            expr: expr.to_string(),
+            message: message.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_reports_first_violated_rule() {
+        let validator = RuleValidator::new(RuleValidationConfig {
+            rules: vec![
+                create_rule(
+                    "login-user",
+                    r#"type == "Login" => has(data.user_id)"#,
+                    Some("Login events need a user_id"),
+                ),
+                create_rule("no-tests", r#"data.env != "test""#, None),
+            ],
+        });
+        let Ok(validator) = validator else {
+            panic!("rules should parse");
+        };
+
+        let login = EventType::Custom("Login".to_string());
+        assert!(
+            validator
+                .validate(&create_event(login.clone(), Some(json!({ "user_id": "a" }))))
+                .is_ok()
+        );
+        assert!(validator.validate(&create_event(EventType::Heartbeat, None)).is_ok());
+
+        match validator.validate(&create_event(login, Some(json!({})))) {
+            Err(EventValidationError::RuleViolation { rule, message }) => {
+                assert_eq!(rule, "login-user");
+                assert_eq!(message, "Login events need a user_id");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let test_event = create_event(EventType::Heartbeat, Some(json!({ "env": "test" })));
+        assert!(matches!(
+            validator.validate(&test_event),
+            Err(EventValidationError::RuleViolation { rule, .. }) if rule == "no-tests"
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_rules() {
+        let invalid = RuleValidator::new(RuleValidationConfig {
+            rules: vec![create_rule("bad", r#"type == 1"#, None)],
+        });
+        assert!(invalid.is_err_and(|e| e.contains("Rule 'bad' is invalid")));
+
+        let duplicate = RuleValidator::new(RuleValidationConfig {
+            rules: vec![create_rule("a", "true", None), create_rule("a", "true", None)],
+        });
+        assert!(duplicate.is_err());
+    }
+}
@@ -1,26 +1,197 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
 
-use super::{EventValidationError, EventValidator};
+use super::{
+    EventValidationError, EventValidator,
+    managed::{ListChange, ListKind, ListOverrides, ManagedLists, SharedOverlay},
+};
 use crate::{
-    config::SourceIdValidationConfig,
-    event::Event,
+    config::{SourceIdRange, SourceIdValidationConfig},
+    event::{Event, SourceId},
+    metrics::VALIDATOR_LIST_RELOADS_TOTAL,
     plugins::{PluginError, ValidationPluginFactory},
 };
 
+/// Separator of inclusive ranges in list files, e.g. `1000..1999`.
+const RANGE_SEPARATOR: &str = "..";
+
+/// Source ids and inclusive numeric ranges.
+#[derive(Debug, Clone, Default)]
+pub struct SourceIdSet {
+    pub ids: HashSet<SourceId>,
+    pub ranges: Vec<SourceIdRange>,
+}
+
+impl SourceIdSet {
+    pub fn new(ids: HashSet<SourceId>, ranges: Vec<SourceIdRange>) -> Self {
+        Self { ids, ranges }
+    }
+
+    pub fn contains(&self, source_id: &SourceId) -> bool {
+        self.ids.contains(source_id) || self.ranges.iter().any(|range| range.contains(source_id))
+    }
+
+    /// Adds all ids and ranges of another set.
+    pub fn extend(&mut self, other: &SourceIdSet) {
+        self.ids.extend(other.ids.iter().cloned());
+        self.ranges.extend(other.ranges.iter().copied());
+    }
+
+    /// Parses a list file: one source id or `start..end` range per line,
+    /// blank lines and `#` comments are ignored.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut set = Self::default();
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some((start, end)) = line.split_once(RANGE_SEPARATOR) {
+                let range = match (start.trim().parse(), end.trim().parse()) {
+                    (Ok(start), Ok(end)) if start <= end => SourceIdRange { start, end },
+                    _ => return Err(format!("line {}: invalid range '{}'", idx + 1, line)),
+                };
+                set.ranges.push(range);
+            } else {
+                let source_id = line.parse().map_err(|e| format!("line {}: {}", idx + 1, e))?;
+                set.ids.insert(source_id);
+            }
+        }
+        Ok(set)
+    }
+}
+
+/// Modification time and size of a list file, used to detect changes.
+type FileStamp = (SystemTime, u64);
+
+fn file_stamp(path: &Path) -> Result<FileStamp, String> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?;
+    let modified =
+        metadata.modified().map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?;
+    Ok((modified, metadata.len()))
+}
+
+/// Inline ids merged with the content of an optional list file, and the
+/// changes made at runtime.
+#[derive(Debug)]
+struct ListSource {
+    inline: SourceIdSet,
+    file: Option<PathBuf>,
+    stamp: Mutex<Option<FileStamp>>,
+    /// Swapped as a whole on reload, so a validation sees either the old or
+    /// the new list
+    current: RwLock<Arc<SourceIdSet>>,
+    overlay: SharedOverlay<SourceId>,
+}
+
+impl ListSource {
+    fn new(inline: SourceIdSet, file: Option<PathBuf>) -> Result<Self, String> {
+        let source = Self {
+            current: RwLock::new(Arc::new(inline.clone())),
+            inline,
+            file,
+            stamp: Mutex::new(None),
+            overlay: SharedOverlay::default(),
+        };
+        source.reload_if_changed()?;
+        Ok(source)
+    }
+
+    fn get(&self) -> Arc<SourceIdSet> {
+        match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn contains(&self, source_id: &SourceId) -> bool {
+        let configured = self.get();
+        self.overlay.get().contains(source_id, |id| configured.contains(id))
+    }
+
+    fn entries(&self) -> Vec<String> {
+        let configured = self.get();
+        let ids = configured.ids.iter().map(ToString::to_string);
+        let ranges = configured
+            .ranges
+            .iter()
+            .map(|range| format!("{}{}{}", range.start, RANGE_SEPARATOR, range.end));
+        self.overlay.get().entries(ids.chain(ranges))
+    }
+
+    /// Re-reads the list file if it changed since the last load.
+    /// Returns the number of entries if the list was reloaded.
+    fn reload_if_changed(&self) -> Result<Option<usize>, String> {
+        let Some(path) = &self.file else {
+            return Ok(None);
+        };
+        let stamp = file_stamp(path)?;
+        if self.stamp.lock().map(|last| *last == Some(stamp)).unwrap_or(false) {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let from_file =
+            SourceIdSet::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let entries = from_file.ids.len() + from_file.ranges.len();
+
+        let mut merged = self.inline.clone();
+        merged.extend(&from_file);
+        if let Ok(mut current) = self.current.write() {
+            *current = Arc::new(merged);
+        }
+        if let Ok(mut last) = self.stamp.lock() {
+            *last = Some(stamp);
+        }
+        Ok(Some(entries))
+    }
+}
+
 #[derive(Debug)]
 pub struct SourceIdValidator {
-    pub allowed_ids: HashSet<u64>,
+    allowed: Arc<ListSource>,
+    denied: Arc<ListSource>,
+    /// Whether an allowlist is configured, an empty allowlist file still
+    /// restricts sources
+    restricted: bool,
+    reload_interval: Duration,
 }
 
 impl SourceIdValidator {
-    pub fn new(config: SourceIdValidationConfig) -> Self {
-        if config.allowed.is_empty() {
+    pub fn new(config: SourceIdValidationConfig) -> Result<Self, String> {
+        let restricted = !config.allowed.is_empty()
+            || !config.allowed_ranges.is_empty()
+            || config.allowed_file.is_some();
+        if !restricted {
             tracing::warn!(
                 "SourceIdValidator initialized with no allowed IDs. This will allow all source \
                  IDs."
             );
         }
-        Self { allowed_ids: config.allowed }
+
+        let allowed = ListSource::new(
+            SourceIdSet::new(config.allowed, config.allowed_ranges),
+            config.allowed_file,
+        )?;
+        let denied = ListSource::new(
+            SourceIdSet::new(config.denied, config.denied_ranges),
+            config.denied_file,
+        )?;
+
+        Ok(Self {
+            allowed: Arc::new(allowed),
+            denied: Arc::new(denied),
+            restricted,
+            reload_interval: Duration::from_secs(config.reload_interval.max(1)),
+        })
     }
 }
 
@@ -30,19 +201,103 @@ impl EventValidator for SourceIdValidator {
     }
 
     fn validate(&self, event: &Event) -> Result<(), EventValidationError> {
-        if self.allowed_ids.is_empty() || self.allowed_ids.contains(&event.source_id) {
+        // The denylist takes precedence over the allowlist
+        if self.denied.contains(&event.source_id) {
+            return Err(EventValidationError::DeniedSourceId(event.source_id.clone()));
+        }
+        if !self.restricted || self.allowed.contains(&event.source_id) {
             Ok(())
         } else {
-            Err(EventValidationError::DisallowedSourceId(event.source_id))
+            Err(EventValidationError::DisallowedSourceId(event.source_id.clone()))
+        }
+    }
+
+    fn lists(&self) -> Option<&dyn ManagedLists> {
+        Some(self)
+    }
+
+    fn start(&self) {
+        let sources: Vec<(&'static str, Arc<ListSource>)> =
+            [("allowed", self.allowed.clone()), ("denied", self.denied.clone())]
+                .into_iter()
+                .filter(|(_, source)| source.file.is_some())
+                .collect();
+        if sources.is_empty() {
+            return;
+        }
+
+        // Poll the list files for changes
+        let reload_interval = self.reload_interval;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(reload_interval);
+            // The first tick completes immediately, the lists were just loaded
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                for (list, source) in &sources {
+                    let source = source.clone();
+                    let result = tokio::task::spawn_blocking(move || source.reload_if_changed())
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()));
+                    let status = match result {
+                        Ok(None) => continue,
+                        Ok(Some(entries)) => {
+                            tracing::info!(list, entries, "Reloaded source id list file");
+                            "success"
+                        }
+                        Err(err) => {
+                            tracing::error!(list, "Failed to reload source id list file: {}", err);
+                            "error"
+                        }
+                    };
+                    metrics::counter!(
+                        VALIDATOR_LIST_RELOADS_TOTAL,
+                        "validator" => "SourceIdValidator",
+                        "list" => *list,
+                        "status" => status
+                    )
+                    .increment(1);
+                }
+            }
+        });
+    }
+}
+
+impl SourceIdValidator {
+    fn list(&self, kind: ListKind) -> &ListSource {
+        match kind {
+            ListKind::Allowed => &self.allowed,
+            ListKind::Denied => &self.denied,
         }
     }
 }
 
+impl ManagedLists for SourceIdValidator {
+    fn entries(&self, kind: ListKind) -> Vec<String> {
+        self.list(kind).entries()
+    }
+
+    fn update(&self, kind: ListKind, change: &ListChange) -> Result<(), String> {
+        if kind == ListKind::Allowed && !self.restricted {
+            return Err("SourceIdValidator has no allowlist configured".to_string());
+        }
+        self.list(kind).overlay.apply(change)
+    }
+
+    fn overrides(&self) -> HashMap<ListKind, ListOverrides> {
+        [ListKind::Allowed, ListKind::Denied]
+            .into_iter()
+            .map(|kind| (kind, self.list(kind).overlay.get().overrides()))
+            .collect()
+    }
+}
+
 /// Constructs a SourceIdValidator from the given parameters.
 /// This function is called by the plugin factory to create a new instance
 /// of the plugin.
 /// It deserializes the parameters from TOML format and creates a new
-/// SourceIdValidator instance.
+/// SourceIdValidator instance. List files are read once here, so a missing or
+/// malformed file fails at startup.
 fn construct_source_id_validator(
     config_params: toml::Value,
 ) -> Result<Box<dyn EventValidator + Send + Sync>, PluginError> {
@@ -51,7 +306,10 @@ fn construct_source_id_validator(
             plugin_name: "SourceIdValidator".to_string(),
             source: e,
         })?;
-    Ok(Box::new(SourceIdValidator::new(config)))
+    let validator = SourceIdValidator::new(config).map_err(|message| {
+        PluginError::InvalidParameters { plugin_name: "SourceIdValidator".to_string(), message }
+    })?;
+    Ok(Box::new(validator))
 }
 
 // Submit plugin to an inventory
@@ -71,17 +329,35 @@ mod tests {
 
     /// Creates a new event for testing purposes.
     fn create_event(source_id: u64, event_type: EventType) -> Event {
-        Event { source_id, r#type: event_type, timestamp: Utc::now(), data: None }
+        Event {
+            source_id: source_id.into(),
+            r#type: event_type,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
     }
 
-    fn get_allowed_ids() -> HashSet<u64> {
-        vec![1, 2, 3].into_iter().collect()
+    fn get_allowed_ids() -> HashSet<SourceId> {
+        vec![1, 2, 3].into_iter().map(SourceId::from).collect()
+    }
+
+    fn create_validator(config: SourceIdValidationConfig) -> SourceIdValidator {
+        match SourceIdValidator::new(config) {
+            Ok(validator) => validator,
+            Err(err) => panic!("Failed to create validator: {}", err),
+        }
     }
 
     #[test]
     fn test_validates_allowed_source_id() {
-        let validator =
-            SourceIdValidator::new(SourceIdValidationConfig { allowed: get_allowed_ids() });
+        let validator = create_validator(SourceIdValidationConfig {
+            allowed: get_allowed_ids(),
+            ..Default::default()
+        });
 
         let event = create_event(1, EventType::Heartbeat);
         assert!(validator.validate(&event).is_ok());
@@ -89,19 +365,127 @@ mod tests {
 
     #[test]
     fn test_validates_disallowed_source_id() {
-        let validator =
-            SourceIdValidator::new(SourceIdValidationConfig { allowed: get_allowed_ids() });
+        let validator = create_validator(SourceIdValidationConfig {
+            allowed: get_allowed_ids(),
+            ..Default::default()
+        });
 
         let event = create_event(4, EventType::Heartbeat);
         assert!(validator.validate(&event).is_err());
     }
 
     #[test]
-    fn test_validates_empty_allowed_source_ids() {
+    fn test_validates_string_source_ids() {
+        let allowed = HashSet::from([SourceId::Name("gw-01".to_string())]);
         let validator =
-            SourceIdValidator::new(SourceIdValidationConfig { allowed: HashSet::new() });
+            create_validator(SourceIdValidationConfig { allowed, ..Default::default() });
+
+        let mut event = create_event(1, EventType::Heartbeat);
+        event.source_id = SourceId::Name("gw-01".to_string());
+        assert!(validator.validate(&event).is_ok());
+
+        event.source_id = SourceId::Name("gw-02".to_string());
+        assert!(validator.validate(&event).is_err());
+    }
+
+    #[test]
+    fn test_validates_empty_allowed_source_ids() {
+        let validator = create_validator(SourceIdValidationConfig::default());
 
         let event = create_event(4, EventType::Heartbeat);
         assert!(validator.validate(&event).is_ok());
     }
+
+    #[test]
+    fn test_validates_ranges_and_denylist() {
+        let validator = create_validator(SourceIdValidationConfig {
+            allowed_ranges: vec![SourceIdRange { start: 100, end: 199 }],
+            denied: HashSet::from([150.into()]),
+            ..Default::default()
+        });
+
+        assert!(validator.validate(&create_event(100, EventType::Heartbeat)).is_ok());
+        assert!(validator.validate(&create_event(199, EventType::Heartbeat)).is_ok());
+        assert!(validator.validate(&create_event(200, EventType::Heartbeat)).is_err());
+        assert!(matches!(
+            validator.validate(&create_event(150, EventType::Heartbeat)),
+            Err(EventValidationError::DeniedSourceId(_))
+        ));
+    }
+
+    #[test]
+    fn test_parses_list_files() {
+        let set = SourceIdSet::parse("# registry export\n1\n\n10..20 # range\ngw-01\n");
+        let Ok(set) = set else {
+            panic!("list should parse");
+        };
+        assert!(set.contains(&1.into()));
+        assert!(set.contains(&15.into()));
+        assert!(set.contains(&SourceId::Name("gw-01".to_string())));
+        assert!(!set.contains(&21.into()));
+
+        assert_eq!(
+            SourceIdSet::parse("1\n20..10").err(),
+            Some("line 2: invalid range '20..10'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_manages_lists_at_runtime() {
+        let validator = create_validator(SourceIdValidationConfig {
+            allowed_ranges: vec![SourceIdRange { start: 1, end: 10 }],
+            ..Default::default()
+        });
+        let change = |add: &[&str], remove: &[&str]| ListChange {
+            add: add.iter().map(|s| s.to_string()).collect(),
+            remove: remove.iter().map(|s| s.to_string()).collect(),
+        };
+
+        assert!(validator.update(ListKind::Denied, &change(&["5"], &[])).is_ok());
+        assert!(validator.update(ListKind::Allowed, &change(&["gw-01"], &[])).is_ok());
+        assert!(validator.validate(&create_event(5, EventType::Heartbeat)).is_err());
+        assert!(validator.validate(&create_event(6, EventType::Heartbeat)).is_ok());
+        assert_eq!(validator.entries(ListKind::Allowed), vec!["1..10", "gw-01"]);
+        assert_eq!(validator.entries(ListKind::Denied), vec!["5"]);
+
+        assert!(validator.update(ListKind::Denied, &change(&[], &["5"])).is_ok());
+        assert!(validator.validate(&create_event(5, EventType::Heartbeat)).is_ok());
+        assert!(validator.update(ListKind::Denied, &change(&["bad id"], &[])).is_err());
+
+        let unrestricted = create_validator(SourceIdValidationConfig::default());
+        assert!(unrestricted.update(ListKind::Allowed, &change(&["1"], &[])).is_err());
+    }
+
+    #[test]
+    fn test_reloads_changed_list_file() {
+        let path = std::env::temp_dir().join(format!(
+            "telemetron-denied-{}-{}.txt",
+            std::process::id(),
+            line!()
+        ));
+        let write = |content: &str| fs::write(&path, content).is_ok();
+        assert!(write("1\n"));
+
+        let validator = create_validator(SourceIdValidationConfig {
+            denied_file: Some(path.clone()),
+            ..Default::default()
+        });
+        assert!(validator.validate(&create_event(1, EventType::Heartbeat)).is_err());
+        assert!(validator.validate(&create_event(2, EventType::Heartbeat)).is_ok());
+
+        // Unchanged file isn't re-read
+        assert_eq!(validator.denied.reload_if_changed(), Ok(None));
+
+        assert!(write("2\n3\n"));
+        assert_eq!(validator.denied.reload_if_changed(), Ok(Some(2)));
+        assert!(validator.validate(&create_event(1, EventType::Heartbeat)).is_ok());
+        assert!(validator.validate(&create_event(2, EventType::Heartbeat)).is_err());
+
+        // A broken file keeps the previous list
+        assert!(write("not an id\n"));
+        assert!(validator.denied.reload_if_changed().is_err());
+        assert!(validator.validate(&create_event(2, EventType::Heartbeat)).is_err());
+
+        let _ = fs::remove_file(&path);
+    }
 }
@@ -0,0 +1,213 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicUsize, Ordering},
+};
+
+use rusqlite::{Connection, OpenFlags, types::Value};
+
+use super::{EventValidationError, EventValidator};
+use crate::{
+    config::SqliteRegistryValidationConfig,
+    event::{Event, SourceId},
+    plugins::{PluginError, ValidationPluginFactory},
+};
+
+const VALIDATOR_NAME: &str = "SqliteRegistryValidator";
+
+/// Read-only connections to the registry database, used round-robin so
+/// concurrent lookups don't all wait on one connection.
+#[derive(Debug)]
+struct Registry {
+    connections: Vec<Mutex<Connection>>,
+    next: AtomicUsize,
+    query: String,
+}
+
+impl Registry {
+    /// Whether the registry has a row for the source id. Numeric ids are bound
+    /// as integers, other ids as text.
+    fn contains(&self, source_id: &SourceId) -> Result<bool, String> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+        let connection = self.connections[index].lock().map_err(|e| e.to_string())?;
+        let mut statement = connection.prepare_cached(&self.query).map_err(|e| e.to_string())?;
+        let value = match source_id {
+            SourceId::Numeric(id) => {
+                i64::try_from(*id).map_or_else(|_| Value::Text(id.to_string()), Value::Integer)
+            }
+            other => Value::Text(other.to_string()),
+        };
+        statement.exists([value]).map_err(|e| e.to_string())
+    }
+}
+
+/// Accepts events of sources provisioned in a local SQLite registry, e.g. a
+/// device inventory export. Lookups run on the blocking thread pool.
+#[derive(Debug)]
+pub struct SqliteRegistryValidator {
+    registry: Arc<Registry>,
+}
+
+impl SqliteRegistryValidator {
+    /// Opens the database and checks the query, so a missing database or table
+    /// fails at startup.
+    pub fn new(config: SqliteRegistryValidationConfig) -> Result<Self, String> {
+        if config.connections == 0 {
+            return Err("connections must be at least 1".to_string());
+        }
+        let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let connections = (0..config.connections)
+            .map(|_| {
+                Connection::open_with_flags(&config.path, flags).map(Mutex::new).map_err(|e| {
+                    format!("Failed to open registry {}: {}", config.path.display(), e)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(connection) = connections.first() {
+            let connection = connection.lock().map_err(|e| e.to_string())?;
+            let statement = connection
+                .prepare(&config.query)
+                .map_err(|e| format!("Invalid registry query: {}", e))?;
+            if statement.parameter_count() != 1 {
+                return Err("Registry query must take exactly one parameter (?1)".to_string());
+            }
+        }
+
+        let registry = Registry { connections, next: AtomicUsize::new(0), query: config.query };
+        Ok(Self { registry: Arc::new(registry) })
+    }
+
+    fn check(
+        source_id: &SourceId,
+        result: Result<bool, String>,
+    ) -> Result<(), EventValidationError> {
+        match result {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(EventValidationError::UnprovisionedSourceId(source_id.clone())),
+            Err(message) => Err(EventValidationError::ValidatorUnavailable {
+                validator: VALIDATOR_NAME,
+                message,
+            }),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EventValidator for SqliteRegistryValidator {
+    fn name(&self) -> &'static str {
+        VALIDATOR_NAME
+    }
+
+    fn validate(&self, event: &Event) -> Result<(), EventValidationError> {
+        Self::check(&event.source_id, self.registry.contains(&event.source_id))
+    }
+
+    async fn validate_async(&self, event: &Event) -> Result<(), EventValidationError> {
+        let registry = self.registry.clone();
+        let source_id = event.source_id.clone();
+        let result = tokio::task::spawn_blocking(move || registry.contains(&source_id))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        Self::check(&event.source_id, result)
+    }
+
+    fn supports_timeout(&self) -> bool {
+        true
+    }
+}
+
+/// Constructs a SqliteRegistryValidator from the given parameters.
+fn construct_sqlite_registry_validator(
+    config_params: toml::Value,
+) -> Result<Box<dyn EventValidator + Send + Sync>, PluginError> {
+    let config: SqliteRegistryValidationConfig = config_params.try_into().map_err(|e| {
+        PluginError::ParameterDeserialization { plugin_name: VALIDATOR_NAME.to_string(), source: e }
+    })?;
+    let validator = SqliteRegistryValidator::new(config).map_err(|message| {
+        PluginError::InvalidParameters { plugin_name: VALIDATOR_NAME.to_string(), message }
+    })?;
+    Ok(Box::new(validator))
+}
+
+// Submit plugin to an inventory
+inventory::submit! {
+  ValidationPluginFactory {
+        name: VALIDATOR_NAME,
+        constructor: construct_sqlite_registry_validator,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::event::EventType;
+
+    /// Creates a registry database with a numeric and a named source.
+    fn create_registry(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("telemetron-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let Ok(connection) = Connection::open(&path) else {
+            panic!("registry should be created");
+        };
+        let created = connection.execute_batch(
+            "CREATE TABLE devices (source_id PRIMARY KEY);
+             INSERT INTO devices VALUES (1001), ('gw-01');",
+        );
+        assert!(created.is_ok());
+        path
+    }
+
+    fn create_event(source_id: SourceId) -> Event {
+        Event {
+            source_id,
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: Default::default(),
+            sample_weight: 1,
+            payload: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validates_provisioned_sources() {
+        let path = create_registry("provisioned");
+        let config = SqliteRegistryValidationConfig {
+            path: path.clone(),
+            query: "SELECT 1 FROM devices WHERE source_id = ?1".to_string(),
+            connections: 2,
+        };
+        let Ok(validator) = SqliteRegistryValidator::new(config) else {
+            panic!("registry should open");
+        };
+
+        assert!(validator.validate_async(&create_event(1001.into())).await.is_ok());
+        assert!(validator.validate(&create_event(SourceId::Name("gw-01".to_string()))).is_ok());
+        assert!(matches!(
+            validator.validate_async(&create_event(7.into())).await,
+            Err(EventValidationError::UnprovisionedSourceId(_))
+        ));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_rejects_invalid_query() {
+        let path = create_registry("invalid-query");
+        let config = |query: &str| SqliteRegistryValidationConfig {
+            path: path.clone(),
+            query: query.to_string(),
+            connections: 1,
+        };
+        assert!(
+            SqliteRegistryValidator::new(config("SELECT 1 FROM missing WHERE id = ?1")).is_err()
+        );
+        assert!(SqliteRegistryValidator::new(config("SELECT 1 FROM devices")).is_err());
+        let _ = std::fs::remove_file(path);
+    }
+}
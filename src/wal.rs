@@ -0,0 +1,566 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use crate::{
+    config::{FsyncPolicy, WalConfig},
+    event::{Event, PersistedEvent},
+    metrics::{WAL_APPENDED_EVENTS_TOTAL, WAL_SEGMENTS, WAL_UNCOMMITTED_EVENTS},
+};
+
+/// File holding the sequence number up to which all events are processed.
+const COMMITTED_FILE: &str = "committed";
+
+/// Most appends written as one group, synced once with the `always` fsync
+/// policy.
+const MAX_GROUP_APPENDS: usize = 512;
+
+/// An accepted event and its sequence number.
+#[derive(Debug, Serialize, Deserialize)]
+struct WalRecord {
+    seq: u64,
+    #[serde(flatten)]
+    event: PersistedEvent,
+}
+
+/// Work for the writer thread, applied in order.
+#[derive(Debug)]
+enum WalOp {
+    /// Appends an event, replying with its sequence number once written
+    Append(Box<Event>, oneshot::Sender<Result<u64, String>>),
+    /// Syncs the appended events, replying once done
+    Sync(oneshot::Sender<Result<(), String>>),
+    /// Writes the committed offset and deletes the segments done with
+    Commit { committed: u64, segments: Vec<u64> },
+}
+
+/// Which events are processed, and the segments holding them.
+#[derive(Debug, Default)]
+struct Offsets {
+    /// Appended events not yet processed by all plugins
+    pending: BTreeSet<u64>,
+    last_seq: u64,
+    /// All events up to this sequence number are processed
+    committed: u64,
+    /// Segment files, named by the sequence number of their first event
+    segments: BTreeSet<u64>,
+}
+
+/// Writer thread of the log, owning the segment new events are appended to.
+#[derive(Debug)]
+struct Writer {
+    dir: PathBuf,
+    segment_entries: usize,
+    fsync: FsyncPolicy,
+    offsets: Arc<Mutex<Offsets>>,
+    /// Open segment file, `None` until the next append starts a new segment
+    file: Option<File>,
+    entries: usize,
+    next_seq: u64,
+    /// Whether appends since the last sync may not be on disk
+    unsynced: bool,
+    /// Why the segment left by the current group failed to sync, if it did
+    sync_error: Option<String>,
+}
+
+/// Write-ahead log of accepted events. Events are appended as JSON lines to
+/// segment files before they are acknowledged, and committed once all
+/// processor plugins are done with them. Segments whose events are all
+/// committed are deleted; the events of the others are replayed on startup.
+/// Files are written by a writer thread, which groups the appends waiting for
+/// it so they share one sync.
+#[derive(Debug)]
+pub struct WriteAheadLog {
+    offsets: Arc<Mutex<Offsets>>,
+    /// Sends work to the writer thread
+    ops: Option<mpsc::Sender<WalOp>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl WriteAheadLog {
+    /// Opens the log, deleting fully committed segments. Returns the log and
+    /// the uncommitted events to replay, oldest first. Lines that can't be
+    /// read, e.g. cut off by a crash, are skipped.
+    pub fn open(config: &WalConfig) -> Result<(Self, Vec<(u64, Event)>), String> {
+        let dir = &config.dir;
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+        let committed_path = dir.join(COMMITTED_FILE);
+        let committed = match fs::read_to_string(&committed_path) {
+            Ok(content) => content.trim().parse().unwrap_or_else(|err| {
+                tracing::warn!("Ignoring unreadable {}: {}", committed_path.display(), err);
+                0
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(err) => {
+                return Err(format!("Failed to read {}: {}", committed_path.display(), err));
+            }
+        };
+
+        let read_dir =
+            fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+        let segments: BTreeSet<u64> = read_dir
+            .filter_map(|entry| {
+                entry
+                    .ok()?
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix("segment-")?
+                    .strip_suffix(".jsonl")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+
+        let mut offsets = Offsets { committed, last_seq: committed, ..Offsets::default() };
+        let mut replay = Vec::new();
+        for segment in segments {
+            let records = read_segment(dir, segment)?;
+            // A segment may only hold a cut-off line, don't reuse its name
+            offsets.last_seq =
+                records.iter().map(|r| r.seq).fold(offsets.last_seq.max(segment), u64::max);
+            let uncommitted: Vec<_> = records
+                .into_iter()
+                .filter(|record| record.seq > committed)
+                .map(|record| (record.seq, record.event.into_event()))
+                .collect();
+            if uncommitted.is_empty() {
+                delete_segment(dir, segment);
+                continue;
+            }
+            offsets.pending.extend(uncommitted.iter().map(|(seq, _)| *seq));
+            offsets.segments.insert(segment);
+            replay.extend(uncommitted);
+        }
+        metrics::gauge!(WAL_UNCOMMITTED_EVENTS).set(offsets.pending.len() as f64);
+        metrics::gauge!(WAL_SEGMENTS).set(offsets.segments.len() as f64);
+
+        // New events go to a new segment, the last one may end in a cut-off line
+        let next_seq = offsets.last_seq + 1;
+        let offsets = Arc::new(Mutex::new(offsets));
+        let writer = Writer {
+            dir: dir.clone(),
+            segment_entries: config.segment_entries.max(1),
+            fsync: config.fsync,
+            offsets: offsets.clone(),
+            file: None,
+            entries: 0,
+            next_seq,
+            unsynced: false,
+            sync_error: None,
+        };
+        let (ops, receiver) = mpsc::channel();
+        let writer = thread::Builder::new()
+            .name("wal-writer".to_string())
+            .spawn(move || writer.run(receiver))
+            .map_err(|e| format!("Failed to start the WAL writer: {}", e))?;
+        Ok((Self { offsets, ops: Some(ops), writer: Some(writer) }, replay))
+    }
+
+    fn send_op(&self, op: WalOp) -> Result<(), String> {
+        self.ops
+            .as_ref()
+            .and_then(|ops| ops.send(op).ok())
+            .ok_or_else(|| "WAL writer is not running".to_string())
+    }
+
+    /// Appends an accepted event, syncing it to disk with the `always` fsync
+    /// policy. Returns its sequence number, to commit once processed.
+    pub async fn append(&self, event: &Event) -> Result<u64, String> {
+        let (reply, seq) = oneshot::channel();
+        self.send_op(WalOp::Append(Box::new(event.clone()), reply))?;
+        seq.await.map_err(|_| "WAL writer stopped".to_string())?
+    }
+
+    /// Syncs appended events to disk, for the `interval` fsync policy. Returns
+    /// once the writer has also applied the commits made before.
+    pub async fn sync(&self) -> Result<(), String> {
+        let (reply, synced) = oneshot::channel();
+        self.send_op(WalOp::Sync(reply))?;
+        synced.await.map_err(|_| "WAL writer stopped".to_string())?
+    }
+
+    /// Commits processed events. Once all events before them are committed
+    /// too, the committed offset advances and segments holding only
+    /// committed events are deleted by the writer thread.
+    pub fn commit(&self, seqs: &[u64]) {
+        let mut offsets = match self.offsets.lock() {
+            Ok(offsets) => offsets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for seq in seqs {
+            offsets.pending.remove(seq);
+        }
+        metrics::gauge!(WAL_UNCOMMITTED_EVENTS).set(offsets.pending.len() as f64);
+
+        // Events are processed out of order across workers and plugins
+        let committed = offsets.pending.first().map_or(offsets.last_seq, |first| first - 1);
+        if committed <= offsets.committed {
+            return;
+        }
+        offsets.committed = committed;
+
+        // A segment ends where the next one starts; the last one is written to
+        let segments: Vec<u64> = offsets
+            .segments
+            .iter()
+            .zip(offsets.segments.iter().skip(1))
+            .take_while(|(_, next)| **next <= committed + 1)
+            .map(|(segment, _)| *segment)
+            .collect();
+        for segment in &segments {
+            offsets.segments.remove(segment);
+        }
+        metrics::gauge!(WAL_SEGMENTS).set(offsets.segments.len() as f64);
+        drop(offsets);
+        if let Err(err) = self.send_op(WalOp::Commit { committed, segments }) {
+            tracing::error!("Failed to commit WAL events: {}", err);
+        }
+    }
+}
+
+impl Drop for WriteAheadLog {
+    /// Waits for the writer thread to apply the pending work.
+    fn drop(&mut self) {
+        self.ops.take();
+        if let Some(writer) = self.writer.take()
+            && writer.join().is_err()
+        {
+            tracing::error!("WAL writer panicked");
+        }
+    }
+}
+
+impl Writer {
+    /// Applies the work of a log until it is dropped. The appends waiting
+    /// together are written as one group and acknowledged after one sync.
+    fn run(mut self, ops: mpsc::Receiver<WalOp>) {
+        while let Ok(op) = ops.recv() {
+            let mut appended = Vec::new();
+            let mut syncs = Vec::new();
+            let mut next = Some(op);
+            while let Some(op) = next {
+                match op {
+                    WalOp::Append(event, reply) => match self.write(&event) {
+                        Ok(seq) => appended.push((seq, reply)),
+                        Err(err) => {
+                            let _ = reply.send(Err(err));
+                        }
+                    },
+                    WalOp::Sync(reply) => syncs.push(reply),
+                    WalOp::Commit { committed, segments } => self.commit(committed, &segments),
+                }
+                next = (appended.len() < MAX_GROUP_APPENDS).then(|| ops.try_recv().ok()).flatten();
+            }
+
+            let sync = !syncs.is_empty() || self.fsync == FsyncPolicy::Always;
+            let result = match self.sync_error.take() {
+                Some(err) => Err(err),
+                None if sync => self.sync(),
+                None => Ok(()),
+            };
+            if result.is_err() {
+                // The next event starts a new segment, after what may be lost
+                self.file = None;
+            }
+            if !appended.is_empty() {
+                // Appends only wait for the sync with the `always` policy
+                let synced = if self.fsync == FsyncPolicy::Always { &result } else { &Ok(()) };
+                self.acknowledge(appended, synced);
+            }
+            for reply in syncs {
+                let _ = reply.send(result.clone());
+            }
+        }
+    }
+
+    /// Writes an event to the current segment, starting a new one when it is
+    /// full. Returns its sequence number.
+    fn write(&mut self, event: &Event) -> Result<u64, String> {
+        let seq = self.next_seq;
+        // A failed write may leave a partial line, so don't reuse the number
+        self.next_seq += 1;
+
+        let record = WalRecord { seq, event: PersistedEvent::from(event.clone()) };
+        let line = serde_json::to_string(&record).map_err(|e| e.to_string())? + "\n";
+
+        let new_segment = self.entries >= self.segment_entries;
+        if new_segment
+            && self.unsynced
+            && let Err(err) = self.sync()
+        {
+            // Rotating: the previous segment must not stay unsynced
+            tracing::error!("{}", err);
+            if self.fsync == FsyncPolicy::Always {
+                self.sync_error = Some(err);
+            }
+        }
+        if new_segment || self.file.is_none() {
+            let path = segment_path(&self.dir, seq);
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+            self.file = Some(file);
+            self.entries = 0;
+            self.unsynced = false;
+            let mut offsets = self.offsets.lock().map_err(|e| e.to_string())?;
+            offsets.segments.insert(seq);
+            metrics::gauge!(WAL_SEGMENTS).set(offsets.segments.len() as f64);
+        }
+
+        let written = self.file.as_mut().map(|file| file.write_all(line.as_bytes()));
+        match written {
+            Some(Ok(())) => {}
+            Some(Err(err)) => {
+                // The next event starts a new segment, after the partial line
+                self.file = None;
+                return Err(format!("Failed to write to the WAL: {}", err));
+            }
+            None => return Err("WAL segment not open".to_string()),
+        }
+        self.entries += 1;
+        self.unsynced = true;
+        Ok(seq)
+    }
+
+    fn sync(&mut self) -> Result<(), String> {
+        if !self.unsynced {
+            return Ok(());
+        }
+        if let Some(file) = &self.file {
+            file.sync_data().map_err(|e| format!("Failed to sync the WAL: {}", e))?;
+        }
+        self.unsynced = false;
+        Ok(())
+    }
+
+    /// Marks a group of written events as pending and replies with their
+    /// sequence numbers, or fails them all if the group could not be synced.
+    fn acknowledge(
+        &self,
+        appended: Vec<(u64, oneshot::Sender<Result<u64, String>>)>,
+        result: &Result<(), String>,
+    ) {
+        if let Err(err) = result {
+            for (_, reply) in appended {
+                let _ = reply.send(Err(err.clone()));
+            }
+            return;
+        }
+        let mut offsets = match self.offsets.lock() {
+            Ok(offsets) => offsets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for (seq, _) in &appended {
+            offsets.pending.insert(*seq);
+            offsets.last_seq = *seq;
+        }
+        metrics::counter!(WAL_APPENDED_EVENTS_TOTAL).increment(appended.len() as u64);
+        metrics::gauge!(WAL_UNCOMMITTED_EVENTS).set(offsets.pending.len() as f64);
+        drop(offsets);
+        for (seq, reply) in appended {
+            let _ = reply.send(Ok(seq));
+        }
+    }
+
+    /// Writes the committed offset and deletes the segments done with.
+    fn commit(&self, committed: u64, segments: &[u64]) {
+        // Replace the offset at once, so a crash leaves the old or the new one
+        let path = self.dir.join(COMMITTED_FILE);
+        let tmp = path.with_extension("tmp");
+        if let Err(err) =
+            fs::write(&tmp, committed.to_string()).and_then(|_| fs::rename(&tmp, &path))
+        {
+            tracing::error!("Failed to write {}: {}", path.display(), err);
+            return;
+        }
+        for segment in segments {
+            delete_segment(&self.dir, *segment);
+        }
+    }
+}
+
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("segment-{:020}.jsonl", segment))
+}
+
+fn read_segment(dir: &Path, segment: u64) -> Result<Vec<WalRecord>, String> {
+    let path = segment_path(dir, segment);
+    let file =
+        File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut records = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(err) => tracing::warn!(
+                "Skipping unreadable line {} of {}: {}",
+                number + 1,
+                path.display(),
+                err
+            ),
+        }
+    }
+    Ok(records)
+}
+
+fn delete_segment(dir: &Path, segment: u64) {
+    let path = segment_path(dir, segment);
+    if let Err(err) = fs::remove_file(&path) {
+        tracing::error!("Failed to delete WAL segment {}: {}", path.display(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{event::EventType, tenant::TenantId};
+
+    fn create_event(source_id: u64) -> Event {
+        Event {
+            source_id: source_id.into(),
+            r#type: EventType::Heartbeat,
+            timestamp: Utc::now(),
+            data: None,
+            labels: Default::default(),
+            tenant: TenantId::new("a"),
+            sample_weight: 10,
+            payload: None,
+        }
+    }
+
+    fn create_config(name: &str) -> WalConfig {
+        let dir =
+            std::env::temp_dir().join(format!("telemetron-wal-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        WalConfig { enabled: true, dir, segment_entries: 2, ..WalConfig::default() }
+    }
+
+    async fn append(wal: &WriteAheadLog, source_id: u64) -> u64 {
+        let Ok(seq) = wal.append(&create_event(source_id)).await else {
+            panic!("event should be appended");
+        };
+        seq
+    }
+
+    fn segment_count(config: &WalConfig) -> usize {
+        let Ok(read_dir) = fs::read_dir(&config.dir) else {
+            panic!("WAL dir should exist");
+        };
+        read_dir
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("segment-"))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_replays_uncommitted_events() {
+        let config = create_config("replay");
+        let Ok((wal, replay)) = WriteAheadLog::open(&config) else {
+            panic!("WAL should open");
+        };
+        assert!(replay.is_empty());
+        let mut seqs = Vec::new();
+        for source_id in 1..=5 {
+            seqs.push(append(&wal, source_id).await);
+        }
+        assert_eq!(seqs, vec![1, 2, 3, 4, 5]);
+        assert_eq!(segment_count(&config), 3);
+
+        // Committing 2 and 3 doesn't advance the offset past 1
+        wal.commit(&[2, 3]);
+        assert!(wal.sync().await.is_ok());
+        assert_eq!(segment_count(&config), 3);
+        wal.commit(&[1]);
+        assert!(wal.sync().await.is_ok());
+        assert_eq!(segment_count(&config), 2);
+        drop(wal);
+
+        let Ok((reopened, replay)) = WriteAheadLog::open(&config) else {
+            panic!("WAL should reopen");
+        };
+        let replayed: Vec<_> = replay.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(replayed, vec![4, 5]);
+        assert!(
+            replay.iter().all(|(_, e)| e.tenant == TenantId::new("a") && e.sample_weight == 10)
+        );
+        assert_eq!(append(&reopened, 6).await, 6);
+
+        reopened.commit(&[4, 5, 6]);
+        drop(reopened);
+        let Ok((_, replay)) = WriteAheadLog::open(&config) else {
+            panic!("WAL should reopen");
+        };
+        assert!(replay.is_empty());
+        assert_eq!(segment_count(&config), 0);
+        let _ = fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn test_groups_concurrent_appends() {
+        let config = create_config("group");
+        let Ok((wal, _)) = WriteAheadLog::open(&config) else {
+            panic!("WAL should open");
+        };
+        let wal = std::sync::Arc::new(wal);
+        let appends: Vec<_> = (0..50)
+            .map(|source_id| {
+                let wal = wal.clone();
+                tokio::spawn(async move { append(&wal, source_id).await })
+            })
+            .collect();
+        let mut seqs = Vec::new();
+        for result in futures::future::join_all(appends).await {
+            let Ok(seq) = result else {
+                panic!("append should not panic");
+            };
+            seqs.push(seq);
+        }
+        seqs.sort_unstable();
+        assert_eq!(seqs, (1..=50).collect::<Vec<_>>());
+        drop(wal);
+
+        let Ok((_, replay)) = WriteAheadLog::open(&config) else {
+            panic!("WAL should reopen");
+        };
+        assert_eq!(replay.len(), 50);
+        let _ = fs::remove_dir_all(config.dir);
+    }
+
+    #[tokio::test]
+    async fn test_skips_cut_off_line() {
+        let config = create_config("cut-off");
+        let Ok((wal, _)) = WriteAheadLog::open(&config) else {
+            panic!("WAL should open");
+        };
+        append(&wal, 1).await;
+        drop(wal);
+        let path = segment_path(&config.dir, 1);
+        let Ok(mut file) = OpenOptions::new().append(true).open(&path) else {
+            panic!("segment should exist");
+        };
+        assert!(file.write_all(b"{\"seq\":2,\"ten").is_ok());
+
+        let Ok((reopened, replay)) = WriteAheadLog::open(&config) else {
+            panic!("WAL should reopen");
+        };
+        assert_eq!(replay.len(), 1);
+        // New events go to a new segment, not after the cut-off line
+        assert_eq!(append(&reopened, 2).await, 2);
+        assert_eq!(segment_count(&config), 2);
+        let _ = fs::remove_dir_all(config.dir);
+    }
+}